@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use phf::phf_map;
+
+static JMP_TABLE: phf::Map<&'static str, u16> = phf_map! {
+    "" => 0u16,
+    "jgt" => 1u16,
+    "jeq" => 2u16,
+    "jge" => 3u16,
+    "jlt" => 4u16,
+    "jne" => 5u16,
+    "jle" => 6u16,
+    "jmp" => 7u16,
+};
+
+static DEST_TABLE: phf::Map<&'static str, u16> = phf_map! {
+    "" => 0u16,
+    "m" => 1u16,
+    "d" => 2u16,
+    "md" => 3u16,
+    "a" => 4u16,
+    "am" => 5u16,
+    "ad" => 6u16,
+    "amd" => 7u16,
+};
+
+static COMP_TABLE: phf::Map<&'static str, u16> = phf_map! {
+    "0" =>   0b101010u16,
+    "1" =>   0b111111u16,
+    "-1" =>  0b111010u16,
+    "d" =>   0b001100u16,
+    "a" =>   0b110000u16,
+    "!d" =>  0b001101u16,
+    "!a" =>  0b110001u16,
+    "-d" =>  0b001111u16,
+    "-a" =>  0b110011u16,
+    "d+1" => 0b011111u16,
+    "a+1" => 0b110111u16,
+    "d-1" => 0b001110u16,
+    "a-1" => 0b110010u16,
+    "d+a" => 0b000010u16,
+    "a+d" => 0b000010u16,
+    "d-a" => 0b010011u16,
+    "a-d" => 0b000111u16,
+    "d&a" => 0b000000u16,
+    "a&d" => 0b000000u16,
+    "d|a" => 0b010101u16,
+    "a|d" => 0b010101u16,
+};
+
+static INTRINSIC_TABLE: phf::Map<&'static str, u16> = phf_map! {
+    "r0" => 0,
+    "r1" => 1,
+    "r2" => 2,
+    "r3" => 3,
+    "r4" => 4,
+    "r5" => 5,
+    "r6" => 6,
+    "r7" => 7,
+    "r8" => 8,
+    "r9" => 9,
+    "r10" => 10,
+    "r11" => 11,
+    "r12" => 12,
+    "r13" => 13,
+    "r14" => 14,
+    "r15" => 15,
+    "sp" => 0,
+    "lcl" => 1,
+    "arg" => 2,
+    "this" => 3,
+    "that" => 4,
+    "screen" => 16384,
+    "kbd" => 24576,
+};
+
+/// First address given to variables.
+pub const VARIABLE_BASE: u16 = 16;
+/// End of the variables, the screen map starts there.
+pub const VARIABLE_END: u16 = 16384;
+/// Number of instructions the ROM holds.
+pub const ROM_SIZE: usize = 32768;
+/// Largest constant an A-instruction can hold.
+pub const MAX_CONSTANT: u16 = 0x7fff;
+
+///
+/// An error in the assembly, at the instruction with ROM
+/// `address`, or the one following a label. `line` counts from
+/// 1 over all lines of the source.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub address: u16,
+    /// The offending instruction without its comment.
+    pub instr: String,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}: {}", self.line, self.message, self.instr)
+    }
+}
+
+///
+/// An assembled program, with the address of every label and
+/// variable.
+///
+#[derive(Debug, Default)]
+pub struct Program {
+    pub code: Vec<u16>,
+    pub labels: HashMap<String, u16>,
+    /// Variables in the order they were allocated from
+    /// `VARIABLE_BASE` on.
+    pub variables: Vec<String>,
+}
+
+// An instruction, its source line and ROM address
+struct Instr<'a> {
+    line: usize,
+    address: u16,
+    text: &'a str,
+}
+
+fn c_instr(instr: &str) -> Result<u16, String> {
+    let (dest, rest) = instr.split_once('=').unwrap_or(("", instr));
+    let (comp, jmp) = rest.split_once(';').unwrap_or((rest, ""));
+
+    let jmp = jmp.trim().to_lowercase();
+    let jmp_opcode = JMP_TABLE.get(&jmp).ok_or_else(|| format!("unknown jump '{}'", jmp))?;
+
+    let dest = dest.trim().to_lowercase();
+    let dst = DEST_TABLE.get(&dest).ok_or_else(|| format!("unknown destination '{}'", dest))?;
+
+    let comp = comp.trim().to_lowercase();
+    let comp_a = comp.replace('m', "a");
+    let is_m = comp_a != comp;
+    let comp_opcode = COMP_TABLE.get(&comp_a).ok_or_else(|| format!("unknown computation '{}'", comp))?;
+
+    Ok(0xE000u16 | if is_m { 1u16 << 12 } else { 0 } | comp_opcode << 6 | dst << 3 | jmp_opcode)
+}
+
+///
+/// Assemble Hack assembly `source`. Labels may be used before they
+/// are declared, other symbols are variables allocated from
+/// `VARIABLE_BASE` in the order of their first use. Returns every
+/// error found rather than just the first.
+///
+pub fn assemble(source: &str) -> Result<Program, Vec<AsmError>> {
+    let mut program = Program::default();
+    let mut errors = Vec::new();
+
+    let mut instrs = Vec::new();
+    for (line, text) in (1..).zip(source.lines()) {
+        let text = text.split("//").next().unwrap_or("").trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        // Addresses past the end only label errors
+        let address = u16::try_from(instrs.len()).unwrap_or(u16::MAX);
+        if let Some(label) = text.strip_prefix('(') {
+            let label = label.strip_suffix(')').unwrap_or("").trim();
+            let error = if label.is_empty() || label.starts_with(|c: char| c.is_ascii_digit()) {
+                Some("invalid label")
+            } else if program.labels.contains_key(label) {
+                Some("duplicate label")
+            } else {
+                None
+            };
+
+            match error {
+                Some(message) => errors.push(AsmError { line, address, instr: text.to_string(), message: message.to_string() }),
+                None => {
+                    program.labels.insert(label.to_string(), address);
+                }
+            }
+            continue;
+        }
+
+        if instrs.len() == ROM_SIZE {
+            let message = format!("beyond the end of ROM, which holds {} instructions", ROM_SIZE);
+            errors.push(AsmError { line, address, instr: text.to_string(), message });
+        }
+        instrs.push(Instr { line, address, text });
+    }
+
+    let mut variables: HashMap<&str, u16> = HashMap::new();
+    for instr in &instrs {
+        let word = match instr.text.strip_prefix('@') {
+            Some(symbol) => {
+                let symbol = symbol.trim();
+                if let Ok(value) = symbol.parse::<u16>() {
+                    if value <= MAX_CONSTANT {
+                        Ok(value)
+                    } else {
+                        Err(format!("constant out of range 0..{}", MAX_CONSTANT))
+                    }
+                } else if symbol.is_empty() || symbol.starts_with(|c: char| c.is_ascii_digit()) {
+                    Err("invalid symbol".to_string())
+                } else if let Some(addr) = program.labels.get(symbol) {
+                    if *addr <= MAX_CONSTANT {
+                        Ok(*addr)
+                    } else {
+                        Err(format!("label '{}' beyond the end of ROM", symbol))
+                    }
+                } else if let Some(addr) = INTRINSIC_TABLE.get(&symbol.to_lowercase()) {
+                    Ok(*addr)
+                } else if let Some(addr) = variables.get(symbol) {
+                    Ok(*addr)
+                } else if variables.len() < usize::from(VARIABLE_END - VARIABLE_BASE) {
+                    let next = VARIABLE_BASE + variables.len() as u16;
+                    variables.insert(symbol, next);
+                    program.variables.push(symbol.to_string());
+                    Ok(next)
+                } else {
+                    Err(format!("no room for variable '{}' below {}", symbol, VARIABLE_END))
+                }
+            }
+            None => c_instr(instr.text),
+        };
+
+        match word {
+            Ok(word) => program.code.push(word),
+            Err(message) => errors.push(AsmError { line: instr.line, address: instr.address, instr: instr.text.to_string(), message }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        errors.sort_by_key(|e| e.line);
+        Err(errors)
+    }
+}
+
+/// Write `code` in the `.hack` format, one binary word per line.
+pub fn write_hack(code: &[u16], out: &mut impl Write) -> io::Result<()> {
+    for instr in code {
+        writeln!(out, "{:016b}", instr)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(source: &str) -> Vec<u16> {
+        assemble(source).unwrap().code
+    }
+
+    #[test]
+    fn variables_keep_their_slot() {
+        let program = assemble("@a\nM=0\n@b\nM=0\n(LOOP)\n@c\nM=0\n@b\n@LOOP\n0;JMP").unwrap();
+
+        assert_eq!(program.code[0], 16);
+        assert_eq!(program.code[2], 17);
+        // `a` is not used again, `c` still gets a slot of its own
+        assert_eq!(program.code[4], 18);
+        assert_eq!(program.code[6], 17);
+        assert_eq!(program.code[7], 4);
+        assert_eq!(program.variables, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn labels_declared_after_use() {
+        assert_eq!(code("@END\n0;JMP\n(END)\n@END\n0;JMP"), [2, 0b1110101010000111, 2, 0b1110101010000111]);
+    }
+
+    #[test]
+    fn commutative_computations() {
+        assert_eq!(code("D=A+D"), code("D=D+A"));
+        assert_eq!(code("M=M&D"), code("M=D&M"));
+        assert_eq!(code("AM=M|D"), code("AM=D|M"));
+    }
+
+    #[test]
+    fn registers() {
+        assert_eq!(code("@R10\n@r15\n@SP\n@THAT\n@SCREEN\n@KBD"), [10, 15, 0, 4, 16384, 24576]);
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let errors = assemble("@32768\nD=D*A\n(1X)\n@x\nA=D;JUMP").unwrap_err();
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+
+        assert_eq!(lines, [1, 2, 3, 5]);
+        assert_eq!(errors[1].message, "unknown computation 'd*a'");
+    }
+
+    #[test]
+    fn reports_a_program_too_large_for_rom() {
+        let source = "D=0\n".repeat(ROM_SIZE) + "(END)\n@END\n0;JMP\n";
+        let errors = assemble(&source).unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].address), (32770, 32768));
+        assert_eq!(errors[0].message, "beyond the end of ROM, which holds 32768 instructions");
+        assert_eq!(errors[1].message, "label 'END' beyond the end of ROM");
+        assert!(assemble(&"D=0\n".repeat(ROM_SIZE)).is_ok());
+    }
+
+    #[test]
+    fn reports_variables_past_the_screen() {
+        let count = usize::from(VARIABLE_END - VARIABLE_BASE);
+        let source: String = (0..=count).map(|i| format!("@v{}\n", i)).collect();
+        let errors = assemble(&(source + "@v0\n")).unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, count + 1);
+        assert_eq!(errors[0].message, format!("no room for variable 'v{}' below 16384", count));
+    }
+}
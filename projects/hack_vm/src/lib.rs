@@ -0,0 +1,14 @@
+//!
+//! Typed model of the Hack VM language shared by the
+//! Jack compiler and the VM translator.
+//!
+//! `parse` turns `.vm` source into a `Module` and the
+//! `Display` implementations print it back in the
+//! canonical textual form.
+//!
+
+mod op;
+mod parser;
+
+pub use op::{Command, Index, Module, Op, Segment};
+pub use parser::{parse, ParseError};
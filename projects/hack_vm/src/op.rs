@@ -0,0 +1,171 @@
+use std::fmt;
+
+pub type Index = i32;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Local,
+    Argument,
+    Static,
+    Constant,
+    This,
+    That,
+    Pointer,
+    Temp,
+}
+
+impl Segment {
+    pub fn from_name(name: &str) -> Option<Segment> {
+        let seg = match name {
+            "local" => Segment::Local,
+            "argument" => Segment::Argument,
+            "static" => Segment::Static,
+            "constant" => Segment::Constant,
+            "this" => Segment::This,
+            "that" => Segment::That,
+            "pointer" => Segment::Pointer,
+            "temp" => Segment::Temp,
+            _ => return None,
+        };
+
+        Some(seg)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Segment::Local => "local",
+            Segment::Argument => "argument",
+            Segment::Static => "static",
+            Segment::Constant => "constant",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Pointer => "pointer",
+            Segment::Temp => "temp",
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+    /// Extension commands, not part of the standard VM language.
+    /// They compute what `Math.multiply` and `Math.divide` would,
+    /// `mod` the remainder left by `div`. `shl` and `shr` shift
+    /// the bits of x left and right (logically) by y.
+    Mul,
+    Div,
+    Mod,
+    Shl,
+    Shr,
+    Push(Segment, Index),
+    Pop(Segment, Index),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function(String, i32),
+    Call(String, i32),
+    Return,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Add => write!(f, "add"),
+            Op::Sub => write!(f, "sub"),
+            Op::Neg => write!(f, "neg"),
+            Op::Eq => write!(f, "eq"),
+            Op::Gt => write!(f, "gt"),
+            Op::Lt => write!(f, "lt"),
+            Op::And => write!(f, "and"),
+            Op::Or => write!(f, "or"),
+            Op::Not => write!(f, "not"),
+            Op::Mul => write!(f, "mul"),
+            Op::Div => write!(f, "div"),
+            Op::Mod => write!(f, "mod"),
+            Op::Shl => write!(f, "shl"),
+            Op::Shr => write!(f, "shr"),
+            Op::Push(seg, idx) => write!(f, "push {seg} {idx}"),
+            Op::Pop(seg, idx) => write!(f, "pop {seg} {idx}"),
+            Op::Label(l) => write!(f, "label {l}"),
+            Op::Goto(l) => write!(f, "goto {l}"),
+            Op::IfGoto(l) => write!(f, "if-goto {l}"),
+            Op::Function(name, nlocals) => write!(f, "function {name} {nlocals}"),
+            Op::Call(name, nargs) => write!(f, "call {name} {nargs}"),
+            Op::Return => write!(f, "return"),
+        }
+    }
+}
+
+///
+/// A single VM command together with the
+/// (1-based) line it was read from.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub op: Op,
+    pub line: usize,
+}
+
+///
+/// The commands of one `.vm` file. `name` is the
+/// file stem and scopes the `static` segment.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Module {
+    pub name: String,
+    pub commands: Vec<Command>,
+}
+
+impl Module {
+    pub fn new(name: &str) -> Module {
+        Module {
+            name: name.to_string(),
+            commands: Vec::new(),
+        }
+    }
+
+    ///
+    /// Build a module from generated ops. Lines are numbered
+    /// as they will appear once the module is printed.
+    ///
+    pub fn from_ops(name: &str, ops: Vec<Op>) -> Module {
+        let commands = ops
+            .into_iter()
+            .enumerate()
+            .map(|(i, op)| Command { op, line: i + 1 })
+            .collect();
+
+        Module {
+            name: name.to_string(),
+            commands,
+        }
+    }
+
+    pub fn ops(&self) -> impl Iterator<Item = &Op> {
+        self.commands.iter().map(|c| &c.op)
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for op in self.ops() {
+            writeln!(f, "{op}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use std::error::Error;
+use std::fmt::Display;
+
+use crate::op::{Command, Index, Module, Op, Segment};
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.vm:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+///
+/// Parse the source of a `.vm` file. `name` is the file stem
+/// and becomes the name of the returned module.
+///
+pub fn parse(name: &str, source: &str) -> Result<Module, ParseError> {
+    let mut module = Module::new(name);
+
+    for (lineno, line) in source.lines().enumerate() {
+        // Everything after first "//" is a comment
+        let line = match line.find("//") {
+            Some(cmt) => &line[..cmt],
+            None => line,
+        };
+
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let op = parse_op(&tokens).map_err(|message| ParseError {
+            file: name.to_string(),
+            line: lineno + 1,
+            message,
+        })?;
+
+        module.commands.push(Command {
+            op,
+            line: lineno + 1,
+        });
+    }
+
+    Ok(module)
+}
+
+fn parse_op(tokens: &[&str]) -> Result<Op, String> {
+    let arity = match tokens[0] {
+        "push" | "pop" | "function" | "call" => 2,
+        "label" | "goto" | "if-goto" => 1,
+        _ => 0,
+    };
+    if tokens.len() != arity + 1 {
+        return Err(format!(
+            "'{}' expects {} argument(s), got {}",
+            tokens[0],
+            arity,
+            tokens.len() - 1
+        ));
+    }
+
+    let op = match tokens[0] {
+        "add" => Op::Add,
+        "sub" => Op::Sub,
+        "neg" => Op::Neg,
+        "eq" => Op::Eq,
+        "gt" => Op::Gt,
+        "lt" => Op::Lt,
+        "and" => Op::And,
+        "or" => Op::Or,
+        "not" => Op::Not,
+        "mul" => Op::Mul,
+        "div" => Op::Div,
+        "mod" => Op::Mod,
+        "shl" => Op::Shl,
+        "shr" => Op::Shr,
+        "push" => {
+            let (seg, idx) = parse_segment(tokens[1], tokens[2])?;
+            Op::Push(seg, idx)
+        }
+        "pop" => {
+            let (seg, idx) = parse_segment(tokens[1], tokens[2])?;
+            if seg == Segment::Constant {
+                return Err("cannot pop into the constant segment".to_string());
+            }
+            Op::Pop(seg, idx)
+        }
+        "label" => Op::Label(tokens[1].to_string()),
+        "goto" => Op::Goto(tokens[1].to_string()),
+        "if-goto" => Op::IfGoto(tokens[1].to_string()),
+        "function" => Op::Function(tokens[1].to_string(), parse_number(tokens[2])?),
+        "call" => Op::Call(tokens[1].to_string(), parse_number(tokens[2])?),
+        "return" => Op::Return,
+        cmd => return Err(format!("unknown command '{cmd}'")),
+    };
+
+    Ok(op)
+}
+
+fn parse_segment(segment: &str, idx: &str) -> Result<(Segment, Index), String> {
+    let seg = Segment::from_name(segment).ok_or(format!("unknown segment '{segment}'"))?;
+    let idx = parse_number(idx)?;
+
+    let max = match seg {
+        Segment::Constant => 32767,
+        Segment::Pointer => 1,
+        Segment::Temp => 7,
+        _ => Index::MAX,
+    };
+    if idx > max {
+        return Err(format!("index {idx} out of range for segment {seg}"));
+    }
+
+    Ok((seg, idx))
+}
+
+fn parse_number(s: &str) -> Result<i32, String> {
+    match s.parse::<i32>() {
+        Ok(n) if n >= 0 => Ok(n),
+        _ => Err(format!("expected a non-negative number, got '{s}'")),
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use hack_vm::{parse, Module, Op, Segment};
+
+fn vm_files(path: &Path, res: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        if path.extension().and_then(|e| e.to_str()) == Some("vm") {
+            res.push(path.to_owned());
+        }
+        return;
+    }
+
+    for entry in fs::read_dir(path).unwrap() {
+        vm_files(&entry.unwrap().path(), res);
+    }
+}
+
+fn round_trip(module: &Module) {
+    let printed = module.to_string();
+    let reparsed = parse(&module.name, &printed).unwrap();
+
+    assert_eq!(
+        module.ops().collect::<Vec<_>>(),
+        reparsed.ops().collect::<Vec<_>>()
+    );
+    assert_eq!(printed, reparsed.to_string());
+}
+
+#[test]
+fn parses_every_command() {
+    let src = "\
+// comment line
+function Foo.bar 2
+  push constant 7 // trailing comment
+pop local 1
+push static 3
+label LOOP
+if-goto LOOP
+goto END
+label END
+add
+sub
+neg
+eq
+gt
+lt
+and
+or
+not
+mul
+div
+mod
+shl
+shr
+call Foo.baz 1
+return
+";
+    let module = parse("Foo", src).unwrap();
+
+    assert_eq!(module.name, "Foo");
+    assert_eq!(module.commands[0].line, 2);
+    assert_eq!(module.commands[0].op, Op::Function("Foo.bar".to_string(), 2));
+    assert_eq!(module.commands[1].op, Op::Push(Segment::Constant, 7));
+    assert_eq!(module.commands[3].op, Op::Push(Segment::Static, 3));
+    assert_eq!(module.commands[17].op, Op::Mul);
+    assert_eq!(module.commands.len(), 24);
+
+    round_trip(&module);
+}
+
+#[test]
+fn rejects_invalid_commands() {
+    for src in [
+        "pop constant 1",
+        "push pointer 2",
+        "push temp 8",
+        "push constant 32768",
+        "push local -1",
+        "push nowhere 0",
+        "push local",
+        "return 1",
+        "jump here",
+    ] {
+        let err = parse("Bad", src).unwrap_err();
+        assert_eq!(err.line, 1, "{src}");
+    }
+}
+
+#[test]
+fn generated_modules_round_trip() {
+    let module = Module::from_ops(
+        "Main",
+        vec![
+            Op::Function("Main.main".to_string(), 1),
+            Op::Push(Segment::Argument, 0),
+            Op::Pop(Segment::Pointer, 1),
+            Op::Push(Segment::That, 0),
+            Op::Return,
+        ],
+    );
+
+    assert_eq!(module.commands[4].line, 5);
+    round_trip(&module);
+}
+
+#[test]
+fn project_vm_files_round_trip() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let mut files = Vec::new();
+    vm_files(&root.join("07"), &mut files);
+    vm_files(&root.join("08"), &mut files);
+    vm_files(&root.join("../tools/OS"), &mut files);
+    assert!(!files.is_empty());
+
+    for path in files {
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let src = fs::read_to_string(&path).unwrap();
+        let module = parse(name, &src).unwrap_or_else(|e| panic!("{e}"));
+
+        round_trip(&module);
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::compiler::{
+    analyzer::Analyzer,
+    analyzers::semantic::SymbolTable,
+    diagnostics::{
+        Diagnostic, ARGUMENT_COUNT, NOT_AN_OBJECT, UNKNOWN_CLASS, UNKNOWN_SUBROUTINE,
+        WRONG_CALL_KIND,
+    },
+    parser::Parser,
+    syntax::{
+        Expression, Statement, SubroutineCall, SubroutineDec, SubroutineKind, SubroutineType, SyntaxTree, Term,
+        TermId, Type,
+    },
+};
+
+// Declarations of the OS API, the OS itself is compiled VM code
+const OS_API: [(&str, &str); 8] = [
+    ("Array.jack", include_str!("../../../../../tools/OS/api/Array.jack")),
+    ("Keyboard.jack", include_str!("../../../../../tools/OS/api/Keyboard.jack")),
+    ("Math.jack", include_str!("../../../../../tools/OS/api/Math.jack")),
+    ("Memory.jack", include_str!("../../../../../tools/OS/api/Memory.jack")),
+    ("Output.jack", include_str!("../../../../../tools/OS/api/Output.jack")),
+    ("Screen.jack", include_str!("../../../../../tools/OS/api/Screen.jack")),
+    ("String.jack", include_str!("../../../../../tools/OS/api/String.jack")),
+    ("Sys.jack", include_str!("../../../../../tools/OS/api/Sys.jack")),
+];
+
+/// A type by name, `Type` refers to the tokens of its tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TypeName {
+    Int,
+    Char,
+    Boolean,
+    Class(String),
+}
+
+impl TypeName {
+    fn new(tree: &SyntaxTree, t: Type) -> TypeName {
+        match t {
+            Type::Int => TypeName::Int,
+            Type::Char => TypeName::Char,
+            Type::Boolean => TypeName::Boolean,
+            Type::ClassName(name) => TypeName::Class(tree.get_id(name).to_string()),
+        }
+    }
+}
+
+/// The signature of a subroutine.
+#[derive(Debug, Clone)]
+pub(crate) struct SubroutineInfo {
+    pub(crate) kind: SubroutineKind,
+    pub(crate) params: Vec<TypeName>,
+    /// `None` for `void`
+    pub(crate) returns: Option<TypeName>,
+}
+
+impl SubroutineInfo {
+    fn new(tree: &SyntaxTree, sd: &SubroutineDec) -> SubroutineInfo {
+        SubroutineInfo {
+            kind: sd.kind,
+            params: sd.params.iter().map(|p| TypeName::new(tree, p.p_type)).collect(),
+            returns: match sd.f_type {
+                SubroutineType::Void => None,
+                SubroutineType::Type(t) => Some(TypeName::new(tree, t)),
+            },
+        }
+    }
+}
+
+///
+/// The classes of a whole program and the subroutines they
+/// declare, for checking calls across classes. Starts with the
+/// OS API, classes of the program replace those of the OS.
+///
+pub struct Program {
+    classes: HashMap<String, HashMap<String, SubroutineInfo>>,
+    /// Classes with syntax errors, which may lack subroutines
+    incomplete: HashSet<String>,
+}
+
+impl Program {
+    pub fn new() -> Program {
+        let mut program = Program {
+            classes: HashMap::new(),
+            incomplete: HashSet::new(),
+        };
+
+        for (file, source) in OS_API {
+            let parser = Parser::from_source(PathBuf::from(file), source.to_string());
+            if let (Some(tree), _) = parser.parse() {
+                program.add(&tree);
+            }
+        }
+
+        program
+    }
+
+    /// Add the class of `tree`, replacing one of the same name.
+    pub fn add(&mut self, tree: &SyntaxTree) {
+        let subroutines = tree
+            .root
+            .subroutines
+            .iter()
+            .map(|sd| (tree.get_id(sd.name).to_string(), SubroutineInfo::new(tree, sd)))
+            .collect();
+
+        let name = tree.get_id(tree.root.name).to_string();
+        self.incomplete.remove(&name);
+        self.classes.insert(name, subroutines);
+    }
+
+    ///
+    /// Add class `name` of a file with syntax errors, with the
+    /// subroutines of its partial `tree` if there is one. Calls to
+    /// subroutines it lacks are not reported.
+    ///
+    pub fn add_incomplete(&mut self, name: &str, tree: Option<&SyntaxTree>) {
+        let name = match tree {
+            Some(tree) => {
+                self.add(tree);
+                tree.get_id(tree.root.name)
+            }
+            None => {
+                self.classes.insert(name.to_string(), HashMap::new());
+                name
+            }
+        };
+        self.incomplete.insert(name.to_string());
+    }
+
+    pub fn has_class(&self, name: &str) -> bool {
+        self.classes.contains_key(name)
+    }
+
+    /// The signature of `class.name`, if both exist.
+    pub(crate) fn subroutine(&self, class: &str, name: &str) -> Option<&SubroutineInfo> {
+        self.classes.get(class)?.get(name)
+    }
+}
+
+struct CallData<'a> {
+    table: SymbolTable<'a>,
+    class: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// "1 argument", "2 arguments"
+fn arguments(n: usize) -> String {
+    if n == 1 {
+        "1 argument".to_string()
+    } else {
+        format!("{} arguments", n)
+    }
+}
+
+///
+/// Check the calls of a class against the `Program`: the class
+/// and the subroutine must exist, methods must be called on an
+/// object and functions and constructors on their class, with as
+/// many arguments as they have parameters. The classes naming the
+/// types of declarations must exist too.
+///
+/// Meant for classes the `SemanticAnalyzer` passed, the result is
+/// empty if the class passed.
+///
+pub struct CallChecker<'p> {
+    pub program: &'p Program,
+}
+
+impl Analyzer for CallChecker<'_> {
+    type Output = Vec<Diagnostic>;
+
+    fn analyze(&self, tree: &SyntaxTree) -> Self::Output {
+        let mut data = CallData {
+            table: SymbolTable::new(tree, &mut Vec::new()),
+            class: tree.get_id(tree.root.name),
+            diagnostics: Vec::new(),
+        };
+
+        self.check_types(tree, &mut data);
+        for sd in &tree.root.subroutines {
+            data.table.enter(sd, &mut Vec::new());
+            self.check_stmts(&sd.body.stmts, &mut data);
+        }
+
+        data.diagnostics
+    }
+}
+
+impl CallChecker<'_> {
+    // Each declaration of a variable, parameter or return value of
+    // an unknown class once, `var Foo a, b;` has a single type
+    fn check_types(&self, tree: &SyntaxTree, data: &mut CallData) {
+        let fields = tree.root.fields.iter().map(|f| f.var_dec.var_type);
+        let subroutines = tree.root.subroutines.iter().flat_map(|sd| {
+            let returns = match sd.f_type {
+                SubroutineType::Void => None,
+                SubroutineType::Type(t) => Some(t),
+            };
+            let params = sd.params.iter().map(|p| p.p_type);
+            let vars = sd.body.var_decs.iter().map(|v| v.var_type);
+            returns.into_iter().chain(params).chain(vars)
+        });
+
+        let mut reported = Vec::new();
+        for t in fields.chain(subroutines) {
+            let Type::ClassName(id) = t else {
+                continue;
+            };
+            let class = tree.get_id(id);
+            if self.program.has_class(class) || reported.contains(&id) {
+                continue;
+            }
+            reported.push(id);
+
+            let tok = &tree.tokens[id];
+            data.diagnostics.push(
+                Diagnostic::error(UNKNOWN_CLASS, format!("cannot find class `{}`", class), tok.file, tok.span)
+                    .with_label("not a class"),
+            );
+        }
+    }
+
+    fn check_stmts(&self, stmts: &[Statement], data: &mut CallData) {
+        for stmt in stmts {
+            match stmt {
+                Statement::Let(ls) => {
+                    if let Some(idx) = &ls.idx {
+                        self.check_expression(idx, data);
+                    }
+                    self.check_expression(&ls.eq_to, data);
+                }
+                Statement::If(is) => {
+                    self.check_expression(&is.cond, data);
+                    self.check_stmts(&is.body, data);
+                    self.check_stmts(&is.else_body, data);
+                }
+                Statement::While(ws) => {
+                    self.check_expression(&ws.cond, data);
+                    self.check_stmts(&ws.body, data);
+                }
+                Statement::Do(ds) => self.check_call(&ds.call, data),
+                Statement::Return(rs) => {
+                    if let Some(expr) = &rs.ret_val {
+                        self.check_expression(expr, data);
+                    }
+                }
+                Statement::Error => {}
+            }
+        }
+    }
+
+    fn check_expression(&self, expr: &Expression, data: &mut CallData) {
+        self.check_term(expr.init_term, data);
+        for (_, term) in &expr.ops {
+            self.check_term(*term, data);
+        }
+    }
+
+    fn check_term(&self, term: TermId, data: &mut CallData) {
+        match &data.table.tree.terms[term] {
+            Term::ArrayAccess(arr) => self.check_expression(&arr.idx, data),
+            Term::Call(call) => self.check_call(call, data),
+            Term::BracketExpression(expr) => self.check_expression(expr, data),
+            Term::Unary(unary) => self.check_term(unary.term, data),
+            Term::Int(_) | Term::String(_) | Term::VarName(_) | Term::KeywordConstant(_) => {}
+        }
+    }
+
+    fn check_call(&self, call: &SubroutineCall, data: &mut CallData) {
+        for arg in &call.args {
+            self.check_expression(arg, data);
+        }
+
+        let tree = data.table.tree;
+        let name = tree.get_id(call.name);
+        let name_tok = &tree.tokens[call.name];
+
+        // The class called, and whether on an object as a method
+        // is. A caller which isn't a variable is a class.
+        let (class, on_object) = match call.caller {
+            Some(caller) => match data.table.lookup(tree.get_id(caller)) {
+                Some(sym) => match sym.var_type {
+                    Type::ClassName(c) => (tree.get_id(c), true),
+                    t => {
+                        let tok = &tree.tokens[caller];
+                        data.diagnostics.push(
+                            Diagnostic::error(
+                                NOT_AN_OBJECT,
+                                format!("cannot call `{}` on `{}`, which is not an object", name, tree.get_id(caller)),
+                                tok.file,
+                                tok.span,
+                            )
+                            .with_label(format!("has type `{}`", t.to_string())),
+                        );
+                        return;
+                    }
+                },
+                None => (tree.get_id(caller), false),
+            },
+            None => (data.class, true),
+        };
+
+        // The class itself is the one declaring its subroutines,
+        // even if another of the same name was given
+        let sub = if class == data.class {
+            data.table.subroutine(name).map(|sd| SubroutineInfo::new(tree, sd))
+        } else {
+            let Some(subroutines) = self.program.classes.get(class) else {
+                // The declaration of the object was reported
+                if on_object {
+                    return;
+                }
+                let caller = call.caller.unwrap_or(call.name);
+                let tok = &tree.tokens[caller];
+                data.diagnostics.push(
+                    Diagnostic::error(UNKNOWN_CLASS, format!("cannot find class `{}`", class), tok.file, tok.span)
+                        .with_label("not a class or a variable in scope"),
+                );
+                return;
+            };
+            subroutines.get(name).cloned()
+        };
+
+        let Some(sub) = sub else {
+            // Its declaration may be the one that failed to parse
+            if self.program.incomplete.contains(class) {
+                return;
+            }
+            data.diagnostics.push(
+                Diagnostic::error(
+                    UNKNOWN_SUBROUTINE,
+                    format!("no subroutine `{}` in class `{}`", name, class),
+                    name_tok.file,
+                    name_tok.span,
+                )
+                .with_label(format!("not found in `{}`", class)),
+            );
+            return;
+        };
+
+        let kind_error = match (sub.kind, on_object) {
+            (SubroutineKind::Method, false) => Some((
+                format!("method `{}.{}` called on its class", class, name),
+                "needs an object".to_string(),
+            )),
+            (SubroutineKind::Function | SubroutineKind::Constructor, true) => Some((
+                format!("{} `{}.{}` called on an object", sub.kind.to_string(), class, name),
+                format!("call it as `{}.{}`", class, name),
+            )),
+            _ => None,
+        };
+        if let Some((message, label)) = kind_error {
+            data.diagnostics.push(
+                Diagnostic::error(WRONG_CALL_KIND, message, name_tok.file, name_tok.span).with_label(label),
+            );
+        }
+
+        if call.args.len() != sub.params.len() {
+            let given = call.args.len();
+            data.diagnostics.push(
+                Diagnostic::error(
+                    ARGUMENT_COUNT,
+                    format!(
+                        "`{}.{}` takes {} but {} {} given",
+                        class,
+                        name,
+                        arguments(sub.params.len()),
+                        given,
+                        if given == 1 { "was" } else { "were" }
+                    ),
+                    name_tok.file,
+                    name_tok.span,
+                )
+                .with_label(format!("expected {}", arguments(sub.params.len()))),
+            );
+        }
+    }
+}
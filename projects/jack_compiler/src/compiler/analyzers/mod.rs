@@ -1,3 +1,6 @@
+pub mod calls;
 pub mod noop;
+pub mod semantic;
+pub mod type_checker;
 pub mod vm_generator;
 pub mod xml;
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use crate::compiler::{
+    analyzer::Analyzer,
+    diagnostics::{
+        Diagnostic, DUPLICATE_DECLARATION, FIELD_IN_FUNCTION, METHOD_WITHOUT_OBJECT,
+        THIS_IN_FUNCTION, UNDECLARED_VARIABLE,
+    },
+    syntax::{
+        ClassVarKind, Expression, IdentifierId, KeywordConstant, Statement, SubroutineCall,
+        SubroutineDec, SubroutineKind, SyntaxTree, Term, TermId, Type,
+    },
+};
+
+/// Where a variable lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VarKind {
+    Static,
+    Field,
+    Argument,
+    Local,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct VarSymbol {
+    pub(crate) kind: VarKind,
+    pub(crate) var_type: Type,
+    /// The name in the declaration
+    pub(crate) name: IdentifierId,
+}
+
+///
+/// The names visible in a subroutine of a class: the variables
+/// of the class, those of the subroutine which shadow them, and
+/// the subroutines of the class.
+///
+pub(crate) struct SymbolTable<'a> {
+    pub(crate) tree: &'a SyntaxTree<'a>,
+    class: HashMap<&'a str, VarSymbol>,
+    subroutine: HashMap<&'a str, VarSymbol>,
+    subroutines: HashMap<&'a str, &'a SubroutineDec>,
+}
+
+// The name at `id` was already declared at `prev`
+fn duplicate(tree: &SyntaxTree, id: IdentifierId, prev: IdentifierId) -> Diagnostic {
+    let name = tree.get_id(id);
+    let tok = &tree.tokens[id];
+
+    Diagnostic::error(
+        DUPLICATE_DECLARATION,
+        format!("the name `{}` is declared more than once", name),
+        tok.file,
+        tok.span,
+    )
+    .with_label(format!("`{}` redeclared here", name))
+    .with_secondary(tree.tokens[prev].span, format!("previous declaration of `{}` here", name))
+}
+
+impl<'a> SymbolTable<'a> {
+    /// The class level names of `tree`, reporting any declared twice.
+    pub(crate) fn new(tree: &'a SyntaxTree<'a>, diagnostics: &mut Vec<Diagnostic>) -> SymbolTable<'a> {
+        let mut table = SymbolTable {
+            tree,
+            class: HashMap::new(),
+            subroutine: HashMap::new(),
+            subroutines: HashMap::new(),
+        };
+
+        for var in &tree.root.fields {
+            let kind = match var.kind {
+                ClassVarKind::Static => VarKind::Static,
+                ClassVarKind::Field => VarKind::Field,
+            };
+            let sym = VarSymbol {
+                kind,
+                var_type: var.var_dec.var_type,
+                name: var.var_dec.name,
+            };
+            Self::declare(tree, &mut table.class, sym, diagnostics);
+        }
+
+        for sd in &tree.root.subroutines {
+            let name = tree.get_id(sd.name);
+            match table.subroutines.get(name) {
+                Some(prev) => diagnostics.push(duplicate(tree, sd.name, prev.name)),
+                None => {
+                    table.subroutines.insert(name, sd);
+                }
+            }
+        }
+
+        table
+    }
+
+    /// Switch to the parameters and locals of `sd`.
+    pub(crate) fn enter(&mut self, sd: &SubroutineDec, diagnostics: &mut Vec<Diagnostic>) {
+        self.subroutine.clear();
+        for p in &sd.params {
+            let sym = VarSymbol {
+                kind: VarKind::Argument,
+                var_type: p.p_type,
+                name: p.name,
+            };
+            Self::declare(self.tree, &mut self.subroutine, sym, diagnostics);
+        }
+        for vd in &sd.body.var_decs {
+            let sym = VarSymbol {
+                kind: VarKind::Local,
+                var_type: vd.var_type,
+                name: vd.name,
+            };
+            Self::declare(self.tree, &mut self.subroutine, sym, diagnostics);
+        }
+    }
+
+    pub(crate) fn lookup(&self, name: &str) -> Option<&VarSymbol> {
+        self.subroutine.get(name).or_else(|| self.class.get(name))
+    }
+
+    /// A subroutine of the class.
+    pub(crate) fn subroutine(&self, name: &str) -> Option<&'a SubroutineDec> {
+        self.subroutines.get(name).copied()
+    }
+
+    // Add `sym` to `vars` unless its name is taken
+    fn declare(
+        tree: &'a SyntaxTree<'a>,
+        vars: &mut HashMap<&'a str, VarSymbol>,
+        sym: VarSymbol,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let name = tree.get_id(sym.name);
+        match vars.get(name) {
+            Some(prev) => diagnostics.push(duplicate(tree, sym.name, prev.name)),
+            None => {
+                vars.insert(name, sym);
+            }
+        }
+    }
+}
+
+struct SemData<'a> {
+    table: SymbolTable<'a>,
+    /// The file of the class, for the spans of terms
+    file: &'a str,
+    /// Whether the current subroutine is a function, without `this`
+    in_function: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+///
+/// Check the names used in a class before any VM code is written:
+/// every variable must be declared, and only once in its scope,
+/// and functions can't use `this`, fields or methods of the class
+/// without an object.
+///
+/// The result is empty if the class passed.
+///
+#[derive(Default)]
+pub struct SemanticAnalyzer;
+
+impl Analyzer for SemanticAnalyzer {
+    type Output = Vec<Diagnostic>;
+
+    fn analyze(&self, tree: &SyntaxTree) -> Self::Output {
+        let mut diagnostics = Vec::new();
+        let table = SymbolTable::new(tree, &mut diagnostics);
+        let mut data = SemData {
+            table,
+            file: tree.tokens[tree.root.name].file,
+            in_function: false,
+            diagnostics,
+        };
+
+        for sd in &tree.root.subroutines {
+            data.table.enter(sd, &mut data.diagnostics);
+            data.in_function = matches!(sd.kind, SubroutineKind::Function);
+            self.check_stmts(&sd.body.stmts, &mut data);
+        }
+
+        data.diagnostics
+    }
+}
+
+impl SemanticAnalyzer {
+    fn check_stmts(&self, stmts: &[Statement], data: &mut SemData) {
+        for stmt in stmts {
+            match stmt {
+                Statement::Let(ls) => {
+                    self.check_var(ls.name, true, data);
+                    if let Some(idx) = &ls.idx {
+                        self.check_expression(idx, data);
+                    }
+                    self.check_expression(&ls.eq_to, data);
+                }
+                Statement::If(is) => {
+                    self.check_expression(&is.cond, data);
+                    self.check_stmts(&is.body, data);
+                    self.check_stmts(&is.else_body, data);
+                }
+                Statement::While(ws) => {
+                    self.check_expression(&ws.cond, data);
+                    self.check_stmts(&ws.body, data);
+                }
+                Statement::Do(ds) => self.check_call(&ds.call, data),
+                Statement::Return(rs) => {
+                    if let Some(expr) = &rs.ret_val {
+                        self.check_expression(expr, data);
+                    }
+                }
+                Statement::Error => {}
+            }
+        }
+    }
+
+    fn check_expression(&self, expr: &Expression, data: &mut SemData) {
+        self.check_term(expr.init_term, data);
+        for (_, term) in &expr.ops {
+            self.check_term(*term, data);
+        }
+    }
+
+    fn check_term(&self, term: TermId, data: &mut SemData) {
+        let tree = data.table.tree;
+        match &tree.terms[term] {
+            Term::VarName(name) => self.check_var(*name, false, data),
+            Term::ArrayAccess(arr) => {
+                self.check_var(arr.var, false, data);
+                self.check_expression(&arr.idx, data);
+            }
+            Term::KeywordConstant(KeywordConstant::This) if data.in_function => {
+                data.diagnostics.push(
+                    Diagnostic::error(
+                        THIS_IN_FUNCTION,
+                        "`this` cannot be used in a function",
+                        data.file,
+                        tree.term_spans[term],
+                    )
+                    .with_label("functions have no `this` object"),
+                );
+            }
+            Term::Call(call) => self.check_call(call, data),
+            Term::BracketExpression(expr) => self.check_expression(expr, data),
+            Term::Unary(unary) => self.check_term(unary.term, data),
+            Term::Int(_) | Term::String(_) | Term::KeywordConstant(_) => {}
+        }
+    }
+
+    // A variable read, or assigned to if `assign`
+    fn check_var(&self, id: IdentifierId, assign: bool, data: &mut SemData) {
+        let tree = data.table.tree;
+        let name = tree.get_id(id);
+        match data.table.lookup(name) {
+            Some(sym) => self.check_field(id, *sym, data),
+            None => {
+                let message = if assign {
+                    format!("cannot assign to undeclared variable `{}`", name)
+                } else {
+                    format!("cannot find variable `{}` in this scope", name)
+                };
+                let tok = &tree.tokens[id];
+                data.diagnostics.push(
+                    Diagnostic::error(UNDECLARED_VARIABLE, message, tok.file, tok.span)
+                        .with_label("not declared"),
+                );
+            }
+        }
+    }
+
+    // Fields need `this`, which functions don't have
+    fn check_field(&self, id: IdentifierId, sym: VarSymbol, data: &mut SemData) {
+        if sym.kind != VarKind::Field || !data.in_function {
+            return;
+        }
+
+        let tree = data.table.tree;
+        let tok = &tree.tokens[id];
+        data.diagnostics.push(
+            Diagnostic::error(
+                FIELD_IN_FUNCTION,
+                format!("cannot access field `{}` in a function", tree.get_id(id)),
+                tok.file,
+                tok.span,
+            )
+            .with_label("functions have no `this` object")
+            .with_secondary(tree.tokens[sym.name].span, "field declared here"),
+        );
+    }
+
+    fn check_call(&self, call: &SubroutineCall, data: &mut SemData) {
+        let tree = data.table.tree;
+        match call.caller {
+            // A variable, or else the name of a class
+            Some(caller) => {
+                if let Some(sym) = data.table.lookup(tree.get_id(caller)) {
+                    self.check_field(caller, *sym, data);
+                }
+            }
+            // A subroutine of this class, called on `this` if a method
+            None => {
+                let name = tree.get_id(call.name);
+                if let Some(sd) = data.table.subroutine(name) {
+                    if data.in_function && matches!(sd.kind, SubroutineKind::Method) {
+                        let tok = &tree.tokens[call.name];
+                        data.diagnostics.push(
+                            Diagnostic::error(
+                                METHOD_WITHOUT_OBJECT,
+                                format!("cannot call method `{}` without an object in a function", name),
+                                tok.file,
+                                tok.span,
+                            )
+                            .with_label("called without an object")
+                            .with_secondary(tree.tokens[sd.name].span, "method declared here"),
+                        );
+                    }
+                }
+            }
+        }
+
+        for arg in &call.args {
+            self.check_expression(arg, data);
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use std::fmt::Display;
+
+use crate::compiler::{
+    analyzer::Analyzer,
+    analyzers::{
+        calls::{Program, TypeName},
+        semantic::SymbolTable,
+    },
+    diagnostics::{Diagnostic, INVALID_OPERAND, NOT_AN_ARRAY, RETURN_VALUE, TYPE_MISMATCH},
+    syntax::{
+        Expression, IdentifierId, KeywordConstant, Op, Statement, SubroutineCall, SubroutineDec,
+        SubroutineType, SyntaxTree, Term, TermId, Type, UnaryOp,
+    },
+    tokens::Span,
+};
+
+/// The type of an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ty<'a> {
+    Int,
+    Char,
+    Boolean,
+    Class(&'a str),
+    Null,
+    Void,
+    /// Array elements, results of unknown subroutines and classes
+    /// which don't exist, fits anywhere
+    Unknown,
+}
+
+impl Display for Ty<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ty::Int => write!(f, "int"),
+            Ty::Char => write!(f, "char"),
+            Ty::Boolean => write!(f, "boolean"),
+            Ty::Class(name) => write!(f, "{}", name),
+            Ty::Null => write!(f, "null"),
+            Ty::Void => write!(f, "void"),
+            Ty::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+struct TypeData<'a> {
+    table: SymbolTable<'a>,
+    program: &'a Program,
+    /// The file of the class, for the spans of terms
+    file: &'a str,
+    class: &'a str,
+    subroutine: &'a SubroutineDec,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> TypeData<'a> {
+    fn ty(&self, t: Type) -> Ty<'a> {
+        match t {
+            Type::Int => Ty::Int,
+            Type::Char => Ty::Char,
+            Type::Boolean => Ty::Boolean,
+            // Reported by the `CallChecker`
+            Type::ClassName(name) if !self.program.has_class(self.table.tree.get_id(name)) => Ty::Unknown,
+            Type::ClassName(name) => Ty::Class(self.table.tree.get_id(name)),
+        }
+    }
+
+    // A type of another class, from the `Program`
+    fn ty_name(&self, t: &'a TypeName) -> Ty<'a> {
+        match t {
+            TypeName::Int => Ty::Int,
+            TypeName::Char => Ty::Char,
+            TypeName::Boolean => Ty::Boolean,
+            TypeName::Class(name) if self.program.has_class(name) => Ty::Class(name),
+            TypeName::Class(_) => Ty::Unknown,
+        }
+    }
+
+    fn returns(&self, sd: &SubroutineDec) -> Ty<'a> {
+        match sd.f_type {
+            SubroutineType::Void => Ty::Void,
+            SubroutineType::Type(t) => self.ty(t),
+        }
+    }
+
+    // From the first term of `expr` to the last
+    fn span(&self, expr: &Expression) -> Span {
+        let spans = &self.table.tree.term_spans;
+        let last = expr.ops.last().map_or(expr.init_term, |(_, term)| *term);
+        spans[expr.init_term].to(&spans[last])
+    }
+
+    fn mismatch(&self, expected: impl Display, found: Ty, span: Span) -> Diagnostic {
+        Diagnostic::error(TYPE_MISMATCH, "mismatched types", self.file, span)
+            .with_label(format!("expected `{}`, found `{}`", expected, found))
+    }
+}
+
+///
+/// Check the types of a class, by inferring the type of every
+/// expression. Calls to other classes and the OS are checked
+/// against their signatures in the `Program`, array elements can
+/// be of any type.
+///
+/// Without `strict` Jack is weakly typed as in the book: `int` and
+/// `char` are interchangeable, `null` and integers fit anywhere,
+/// any object can be assigned to an `Array` and any object indexed,
+/// and conditions can be integers. With `strict` only `null` is
+/// converted, to any class.
+///
+/// Variables of a class missing from the `Program` can hold
+/// anything, the `CallChecker` reports their declarations.
+///
+/// Meant for classes the `SemanticAnalyzer` passed, the result is
+/// empty if the class passed.
+///
+pub struct TypeChecker<'p> {
+    pub program: &'p Program,
+    pub strict: bool,
+}
+
+impl Analyzer for TypeChecker<'_> {
+    type Output = Vec<Diagnostic>;
+
+    fn analyze(&self, tree: &SyntaxTree) -> Self::Output {
+        let Some(first) = tree.root.subroutines.first() else {
+            return Vec::new();
+        };
+
+        // Names declared twice were reported already
+        let table = SymbolTable::new(tree, &mut Vec::new());
+        let mut data = TypeData {
+            table,
+            program: self.program,
+            file: tree.tokens[tree.root.name].file,
+            class: tree.get_id(tree.root.name),
+            subroutine: first,
+            diagnostics: Vec::new(),
+        };
+
+        for sd in &tree.root.subroutines {
+            data.table.enter(sd, &mut Vec::new());
+            data.subroutine = sd;
+            self.check_stmts(&sd.body.stmts, &mut data);
+        }
+
+        data.diagnostics
+    }
+}
+
+impl TypeChecker<'_> {
+    // Whether a value of type `from` can be used as a `to`
+    fn assignable(&self, to: Ty, from: Ty) -> bool {
+        match (to, from) {
+            (Ty::Unknown, _) | (_, Ty::Unknown) => true,
+            (Ty::Void, _) | (_, Ty::Void) => false,
+            (Ty::Class(_), Ty::Null) => true,
+            _ if to == from => true,
+            _ if self.strict => false,
+            (_, Ty::Null) => true,
+            (Ty::Int | Ty::Char, Ty::Int | Ty::Char) => true,
+            // Objects are addresses, as in `let memory = 0;`, and
+            // `Array` is Jack's pointer
+            (Ty::Class(_), Ty::Int | Ty::Char) | (Ty::Int | Ty::Char, Ty::Class(_)) => true,
+            (Ty::Class("Array"), Ty::Class(_)) | (Ty::Class(_), Ty::Class("Array")) => true,
+            _ => false,
+        }
+    }
+
+    // Arrays take part in address arithmetic unless strict
+    fn is_numeric(&self, t: Ty) -> bool {
+        match t {
+            Ty::Int | Ty::Char | Ty::Unknown => true,
+            Ty::Class("Array") => !self.strict,
+            _ => false,
+        }
+    }
+
+    fn check_stmts(&self, stmts: &[Statement], data: &mut TypeData) {
+        for stmt in stmts {
+            match stmt {
+                Statement::Let(ls) => {
+                    let var = self.var_type(ls.name, data);
+                    let value = self.infer(&ls.eq_to, data);
+                    let to = match &ls.idx {
+                        Some(idx) => {
+                            self.check_indexing(ls.name, var, idx, data);
+                            Ty::Unknown
+                        }
+                        None => var,
+                    };
+                    if !self.assignable(to, value) {
+                        let tree = data.table.tree;
+                        let decl = data.table.lookup(tree.get_id(ls.name)).map(|sym| sym.name);
+                        let mut e = data.mismatch(to, value, data.span(&ls.eq_to));
+                        if let Some(decl) = decl {
+                            e = e.with_secondary(tree.tokens[decl].span, format!("declared as `{}` here", to));
+                        }
+                        data.diagnostics.push(e);
+                    }
+                }
+                Statement::If(is) => {
+                    self.check_condition(&is.cond, data);
+                    self.check_stmts(&is.body, data);
+                    self.check_stmts(&is.else_body, data);
+                }
+                Statement::While(ws) => {
+                    self.check_condition(&ws.cond, data);
+                    self.check_stmts(&ws.body, data);
+                }
+                Statement::Do(ds) => {
+                    self.infer_call(&ds.call, data);
+                }
+                Statement::Return(rs) => {
+                    let tree = data.table.tree;
+                    let sd = data.subroutine;
+                    let name = tree.get_id(sd.name);
+                    let returns = data.returns(sd);
+                    let decl = tree.tokens[sd.name].span;
+                    match &rs.ret_val {
+                        None if returns != Ty::Void => {
+                            data.diagnostics.push(
+                                Diagnostic::error(
+                                    RETURN_VALUE,
+                                    format!("`return` without a value in `{}`, which returns `{}`", name, returns),
+                                    data.file,
+                                    rs.span,
+                                )
+                                .with_label(format!("expected a value of type `{}`", returns))
+                                .with_secondary(decl, format!("`{}` declared here", name)),
+                            );
+                        }
+                        Some(expr) if returns == Ty::Void => {
+                            self.infer(expr, data);
+                            data.diagnostics.push(
+                                Diagnostic::error(
+                                    RETURN_VALUE,
+                                    format!("`return` with a value in `{}`, which returns `void`", name),
+                                    data.file,
+                                    data.span(expr),
+                                )
+                                .with_label("not returned")
+                                .with_secondary(decl, format!("`{}` declared here", name)),
+                            );
+                        }
+                        Some(expr) => {
+                            let value = self.infer(expr, data);
+                            if !self.assignable(returns, value) {
+                                let e = data
+                                    .mismatch(returns, value, data.span(expr))
+                                    .with_secondary(decl, format!("`{}` returns `{}`", name, returns));
+                                data.diagnostics.push(e);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                Statement::Error => {}
+            }
+        }
+    }
+
+    fn check_condition(&self, cond: &Expression, data: &mut TypeData) {
+        let t = self.infer(cond, data);
+        let ok = self.assignable(Ty::Boolean, t) || (!self.strict && self.is_numeric(t));
+        if !ok {
+            let e = data.mismatch(Ty::Boolean, t, data.span(cond));
+            data.diagnostics.push(e);
+        }
+    }
+
+    // `var[idx]`, where `var` has type `t`
+    fn check_indexing(&self, var: IdentifierId, t: Ty, idx: &Expression, data: &mut TypeData) {
+        let ok = match t {
+            Ty::Class("Array") | Ty::Unknown => true,
+            Ty::Class(_) => !self.strict,
+            _ => false,
+        };
+        if !ok {
+            let tok = &data.table.tree.tokens[var];
+            data.diagnostics.push(
+                Diagnostic::error(
+                    NOT_AN_ARRAY,
+                    format!("cannot index into a value of type `{}`", t),
+                    tok.file,
+                    tok.span,
+                )
+                .with_label("not an `Array`"),
+            );
+        }
+
+        let i = self.infer(idx, data);
+        if !self.assignable(Ty::Int, i) {
+            let e = data.mismatch(Ty::Int, i, data.span(idx));
+            data.diagnostics.push(e);
+        }
+    }
+
+    fn var_type<'a>(&self, name: IdentifierId, data: &TypeData<'a>) -> Ty<'a> {
+        let tree = data.table.tree;
+        match data.table.lookup(tree.get_id(name)) {
+            Some(sym) => data.ty(sym.var_type),
+            None => Ty::Unknown,
+        }
+    }
+
+    // Jack has no precedence, the operators apply from left to right
+    fn infer<'a>(&self, expr: &Expression, data: &mut TypeData<'a>) -> Ty<'a> {
+        let tree = data.table.tree;
+        let spans = &tree.term_spans;
+        let mut lhs_span = spans[expr.init_term];
+        let mut lhs = self.infer_term(expr.init_term, data);
+        for (op, term) in &expr.ops {
+            let rhs = self.infer_term(*term, data);
+            let rhs_span = spans[*term];
+            lhs = self.binary(op, (lhs, lhs_span), (rhs, rhs_span), data);
+            lhs_span = lhs_span.to(&rhs_span);
+        }
+
+        lhs
+    }
+
+    fn binary<'a>(&self, op: &Op, lhs: (Ty<'a>, Span), rhs: (Ty<'a>, Span), data: &mut TypeData) -> Ty<'a> {
+        let (l, r) = (lhs.0, rhs.0);
+        let operands_ok = match op {
+            Op::Plus | Op::Minus | Op::Multiply | Op::Divide | Op::Less | Op::Greater => {
+                self.is_numeric(l) && self.is_numeric(r)
+            }
+            // Logical on booleans, bitwise on integers
+            Op::And | Op::Or => {
+                let logical = |t| matches!(t, Ty::Boolean | Ty::Unknown);
+                let either = |t| logical(t) || self.is_numeric(t);
+                (logical(l) && logical(r))
+                    || (self.is_numeric(l) && self.is_numeric(r))
+                    || (!self.strict && either(l) && either(r))
+            }
+            Op::Equal => self.assignable(l, r) || self.assignable(r, l),
+            Op::Unknown => true,
+        };
+
+        if !operands_ok {
+            // The operand which doesn't fit, the right one if both do
+            // by themselves but not together
+            let bad = match op {
+                Op::And | Op::Or | Op::Equal => rhs,
+                _ if !self.is_numeric(l) => lhs,
+                _ => rhs,
+            };
+            let message = match op {
+                Op::Equal => format!("cannot compare `{}` with `{}`", l, r),
+                _ => format!("cannot apply `{}` to `{}` and `{}`", op.to_string(), l, r),
+            };
+            data.diagnostics.push(
+                Diagnostic::error(INVALID_OPERAND, message, data.file, bad.1)
+                    .with_label(format!("this is `{}`", bad.0)),
+            );
+            return match op {
+                Op::Equal | Op::Less | Op::Greater => Ty::Boolean,
+                _ => Ty::Unknown,
+            };
+        }
+
+        match op {
+            Op::Plus | Op::Minus | Op::Multiply | Op::Divide => Ty::Int,
+            Op::Less | Op::Greater | Op::Equal => Ty::Boolean,
+            Op::And | Op::Or if l == Ty::Unknown => r,
+            Op::And | Op::Or if l == Ty::Char => Ty::Int,
+            Op::And | Op::Or => l,
+            Op::Unknown => Ty::Unknown,
+        }
+    }
+
+    fn infer_term<'a>(&self, term: TermId, data: &mut TypeData<'a>) -> Ty<'a> {
+        let tree = data.table.tree;
+        match &tree.terms[term] {
+            Term::Int(_) => Ty::Int,
+            Term::String(_) => Ty::Class("String"),
+            Term::KeywordConstant(kw) => match kw {
+                KeywordConstant::True | KeywordConstant::False => Ty::Boolean,
+                KeywordConstant::Null => Ty::Null,
+                KeywordConstant::This => Ty::Class(data.class),
+                KeywordConstant::Unknown => Ty::Unknown,
+            },
+            Term::VarName(name) => self.var_type(*name, data),
+            Term::ArrayAccess(arr) => {
+                let t = self.var_type(arr.var, data);
+                self.check_indexing(arr.var, t, &arr.idx, data);
+                Ty::Unknown
+            }
+            Term::Call(call) => self.infer_call(call, data),
+            Term::BracketExpression(expr) => self.infer(expr, data),
+            Term::Unary(unary) => {
+                let t = self.infer_term(unary.term, data);
+                let ok = match unary.op {
+                    UnaryOp::Minus => self.is_numeric(t),
+                    UnaryOp::Not => self.is_numeric(t) || t == Ty::Boolean,
+                    UnaryOp::Unknown => true,
+                };
+                if !ok {
+                    data.diagnostics.push(
+                        Diagnostic::error(
+                            INVALID_OPERAND,
+                            format!("cannot apply `{}` to `{}`", unary.op.to_string(), t),
+                            data.file,
+                            tree.term_spans[unary.term],
+                        )
+                        .with_label(format!("this is `{}`", t)),
+                    );
+                    return Ty::Unknown;
+                }
+                match t {
+                    Ty::Char => Ty::Int,
+                    t => t,
+                }
+            }
+        }
+    }
+
+    // The result of a call, arguments checked against the
+    // parameters of the callee
+    fn infer_call<'a>(&self, call: &SubroutineCall, data: &mut TypeData<'a>) -> Ty<'a> {
+        let tree = data.table.tree;
+        let class = match call.caller {
+            Some(caller) => match data.table.lookup(tree.get_id(caller)) {
+                Some(sym) => data.ty(sym.var_type),
+                None => Ty::Class(tree.get_id(caller)),
+            },
+            None => Ty::Class(data.class),
+        };
+
+        let args: Vec<(Ty, Span)> = call.args.iter().map(|a| (self.infer(a, data), data.span(a))).collect();
+        let name = tree.get_id(call.name);
+        // Parameters with where they are declared, in this class
+        let (params, returns): (Vec<(Ty, Option<Span>)>, Ty) = match class {
+            Ty::Class(c) if c == data.class => {
+                let Some(sd) = data.table.subroutine(name) else {
+                    return Ty::Unknown;
+                };
+                let params = sd.params.iter().map(|p| (data.ty(p.p_type), Some(tree.tokens[p.name].span)));
+                (params.collect(), data.returns(sd))
+            }
+            Ty::Class(c) => {
+                let Some(sub) = data.program.subroutine(c, name) else {
+                    return Ty::Unknown;
+                };
+                let params = sub.params.iter().map(|p| (data.ty_name(p), None));
+                let returns = sub.returns.as_ref().map_or(Ty::Void, |t| data.ty_name(t));
+                (params.collect(), returns)
+            }
+            _ => return Ty::Unknown,
+        };
+
+        for ((t, span), (expected, decl)) in args.into_iter().zip(params) {
+            if !self.assignable(expected, t) {
+                let mut e = data.mismatch(expected, t, span);
+                if let Some(decl) = decl {
+                    e = e.with_secondary(decl, "parameter declared here");
+                }
+                data.diagnostics.push(e);
+            }
+        }
+
+        match returns {
+            // Jack's void subroutines return 0
+            Ty::Void if !self.strict => Ty::Unknown,
+            t => t,
+        }
+    }
+}
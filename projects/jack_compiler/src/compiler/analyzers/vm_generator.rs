@@ -1,11 +1,7 @@
 use core::fmt;
-use std::{
-    collections::HashMap,
-    error::Error,
-    fs::File,
-    io::{self, BufWriter, Write},
-    path::{Path, PathBuf},
-};
+use std::{collections::HashMap, error::Error};
+
+use hack_vm::{Index, Module, Op, Segment};
 
 use crate::compiler::{
     analyzer::Analyzer,
@@ -19,132 +15,75 @@ use crate::compiler::{
 
 type Label<'a> = &'a str;
 
-#[derive(Debug, Copy, Clone)]
-enum Segment {
-    Local(i32),
-    Argument(i32),
-    Static(i32),
-    Constant(i32),
-    This(i32),
-    That, // always 0
-    Pointer(i32),
-    Temp(i32),
+/// Character code of newline in the Jack character set.
+const JACK_NEWLINE: i32 = 128;
+
+#[derive(Default)]
+struct VMWriter {
+    ops: Vec<Op>,
+    native_math: bool,
 }
 
-impl fmt::Display for Segment {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Segment::Local(i) => write!(f, "local {i}"),
-            Segment::Argument(i) => write!(f, "argument {i}"),
-            Segment::Static(i) => write!(f, "static {i}"),
-            Segment::Constant(i) => write!(f, "constant {i}"),
-            Segment::This(i) => write!(f, "this {i}"),
-            Segment::That => write!(f, "that 0"),
-            Segment::Pointer(i) => write!(f, "pointer {i}"),
-            Segment::Temp(i) => write!(f, "temp {i}"),
-        }
+impl VMWriter {
+    fn push(&mut self, s: Segment, i: Index) {
+        self.ops.push(Op::Push(s, i));
     }
-}
 
-#[derive(Debug, Copy, Clone)]
-enum Op {
-    Add,
-    Sub,
-    Neg,
-    Eq,
-    Gt,
-    Lt,
-    And,
-    Or,
-    Not,
-    Mul,
-    Div,
-}
+    fn pop(&mut self, s: Segment, i: Index) {
+        self.ops.push(Op::Pop(s, i));
+    }
 
-impl fmt::Display for Op {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Op::Add => write!(f, "add"),
-            Op::Sub => write!(f, "sub"),
-            Op::Neg => write!(f, "neg"),
-            Op::Eq => write!(f, "eq"),
-            Op::Gt => write!(f, "gt"),
-            Op::Lt => write!(f, "lt"),
-            Op::And => write!(f, "and"),
-            Op::Or => write!(f, "or"),
-            Op::Not => write!(f, "not"),
-            Op::Mul => write!(f, "call Math.multiply 2"),
-            Op::Div => write!(f, "call Math.divide 2"),
-        }
+    fn arith(&mut self, op: Op) {
+        self.ops.push(op);
     }
-}
 
-impl From<&syntax::Op> for Op {
-    fn from(value: &syntax::Op) -> Self {
-        match value {
+    fn binary(&mut self, op: &syntax::Op) {
+        match op {
             syntax::Op::Unknown => unreachable!(),
-            syntax::Op::Plus => Op::Add,
-            syntax::Op::Minus => Op::Sub,
-            syntax::Op::Multiply => Op::Mul,
-            syntax::Op::Divide => Op::Div,
-            syntax::Op::And => Op::And,
-            syntax::Op::Or => Op::Or,
-            syntax::Op::Less => Op::Lt,
-            syntax::Op::Greater => Op::Gt,
-            syntax::Op::Equal => Op::Eq,
+            syntax::Op::Plus => self.arith(Op::Add),
+            syntax::Op::Minus => self.arith(Op::Sub),
+            syntax::Op::Multiply if self.native_math => self.arith(Op::Mul),
+            syntax::Op::Divide if self.native_math => self.arith(Op::Div),
+            syntax::Op::Multiply => self.call("Math", "multiply", 2),
+            syntax::Op::Divide => self.call("Math", "divide", 2),
+            syntax::Op::And => self.arith(Op::And),
+            syntax::Op::Or => self.arith(Op::Or),
+            syntax::Op::Less => self.arith(Op::Lt),
+            syntax::Op::Greater => self.arith(Op::Gt),
+            syntax::Op::Equal => self.arith(Op::Eq),
         }
     }
-}
 
-impl From<UnaryOp> for Op {
-    fn from(value: UnaryOp) -> Self {
-        match value {
+    fn unary(&mut self, op: UnaryOp) {
+        match op {
             UnaryOp::Unknown => unreachable!(),
-            UnaryOp::Minus => Op::Neg,
-            UnaryOp::Not => Op::Not,
+            UnaryOp::Minus => self.arith(Op::Neg),
+            UnaryOp::Not => self.arith(Op::Not),
         }
     }
-}
-
-struct VMWriter {
-    fw: BufWriter<File>,
-}
-
-impl VMWriter {
-    fn push(&mut self, s: Segment) -> io::Result<()> {
-        writeln!(&mut self.fw, "push {s}")
-    }
-
-    fn pop(&mut self, s: Segment) -> io::Result<()> {
-        writeln!(&mut self.fw, "pop {s}")
-    }
-
-    fn arith(&mut self, op: Op) -> io::Result<()> {
-        writeln!(&mut self.fw, "{op}")
-    }
 
-    fn label(&mut self, l: Label) -> io::Result<()> {
-        writeln!(&mut self.fw, "label {l}")
+    fn label(&mut self, l: Label) {
+        self.ops.push(Op::Label(l.to_string()));
     }
 
-    fn goto(&mut self, l: Label) -> io::Result<()> {
-        writeln!(&mut self.fw, "goto {l}")
+    fn goto(&mut self, l: Label) {
+        self.ops.push(Op::Goto(l.to_string()));
     }
 
-    fn if_goto(&mut self, l: Label) -> io::Result<()> {
-        writeln!(&mut self.fw, "if-goto {l}")
+    fn if_goto(&mut self, l: Label) {
+        self.ops.push(Op::IfGoto(l.to_string()));
     }
 
-    fn call(&mut self, caller: &str, name: &str, nargs: i32) -> io::Result<()> {
-        writeln!(&mut self.fw, "call {caller}.{name} {nargs}")
+    fn call(&mut self, caller: &str, name: &str, nargs: i32) {
+        self.ops.push(Op::Call(format!("{caller}.{name}"), nargs));
     }
 
-    fn function(&mut self, caller: &str, name: &str, nvars: i32) -> io::Result<()> {
-        writeln!(&mut self.fw, "function {caller}.{name} {nvars}")
+    fn function(&mut self, caller: &str, name: &str, nvars: i32) {
+        self.ops.push(Op::Function(format!("{caller}.{name}"), nvars));
     }
 
-    fn ret(&mut self) -> io::Result<()> {
-        writeln!(&mut self.fw, "return")
+    fn ret(&mut self) {
+        self.ops.push(Op::Return);
     }
 }
 
@@ -167,6 +106,7 @@ pub type VMGeneratorResult = Result<(), Box<dyn Error>>;
 struct SymbolData {
     stype: Type,
     segment: Segment,
+    index: Index,
 }
 
 struct GenData<'a> {
@@ -199,37 +139,41 @@ impl<'a> GenData<'a> {
     }
 }
 
+///
+/// Generate the VM code of a class. The result is a
+/// `hack_vm::Module` named after `tree.filename`.
+///
+/// With `native_math` `*` and `/` become the `mul` and `div`
+/// extension commands instead of calls of `Math.multiply` and
+/// `Math.divide`. Only `vmtranslator` and `vm_emulator`
+/// understand them.
+///
+#[derive(Default)]
 pub struct VMGenerator {
-    path: PathBuf,
+    pub native_math: bool,
 }
 
 impl Analyzer for VMGenerator {
-    type Output = VMGeneratorResult;
+    type Output = Result<Module, Box<dyn Error>>;
 
-    fn analyze(&self, tree: &SyntaxTree) -> VMGeneratorResult {
+    fn analyze(&self, tree: &SyntaxTree) -> Self::Output {
         let mut data = GenData {
             global: HashMap::new(),
             local: HashMap::new(),
             tree,
             w: VMWriter {
-                fw: BufWriter::new(File::create(&self.path).unwrap()),
+                native_math: self.native_math,
+                ..Default::default()
             },
             label_idx: 0,
         };
         self.generate_class(&tree.root, &mut data)?;
 
-        Ok(())
+        Ok(Module::from_ops(&tree.filename, data.w.ops))
     }
 }
 
 impl VMGenerator {
-    pub fn new(dir: &str, filename: &str) -> Self {
-        let fullname = filename.to_string() + ".vm";
-        let path = Path::new(dir).join(fullname);
-
-        VMGenerator { path }
-    }
-
     fn generate_class(&self, root: &ClassNode, data: &mut GenData) -> VMGeneratorResult {
         let mut si = 0;
         let mut fi = 0;
@@ -240,7 +184,8 @@ impl VMGenerator {
                         data.tree.get_id(var.var_dec.name),
                         SymbolData {
                             stype: var.var_dec.var_type,
-                            segment: Segment::Static(si),
+                            segment: Segment::Static,
+                            index: si,
                         },
                     );
                     si += 1;
@@ -250,7 +195,8 @@ impl VMGenerator {
                         data.tree.get_id(var.var_dec.name),
                         SymbolData {
                             stype: var.var_dec.var_type,
-                            segment: Segment::This(fi),
+                            segment: Segment::This,
+                            index: fi,
                         },
                     );
                     fi += 1;
@@ -286,9 +232,9 @@ impl VMGenerator {
         self.prep_function(sd, data, 0)?;
 
         data.w
-            .push(Segment::Constant(data.tree.root.fields.len() as i32))?;
-        data.w.call("Memory", "alloc", 1)?;
-        data.w.pop(Segment::Pointer(0))?;
+            .push(Segment::Constant, data.tree.root.fields.len() as i32);
+        data.w.call("Memory", "alloc", 1);
+        data.w.pop(Segment::Pointer, 0);
 
         Ok(())
     }
@@ -305,7 +251,8 @@ impl VMGenerator {
                 data.tree.get_id(v.name),
                 SymbolData {
                     stype: v.p_type,
-                    segment: Segment::Argument(arg),
+                    segment: Segment::Argument,
+                    index: arg,
                 },
             );
 
@@ -317,7 +264,8 @@ impl VMGenerator {
                 data.tree.get_id(vd.name),
                 SymbolData {
                     stype: vd.var_type,
-                    segment: Segment::Local(i as i32),
+                    segment: Segment::Local,
+                    index: i as i32,
                 },
             );
         }
@@ -326,7 +274,7 @@ impl VMGenerator {
             &data.tree.filename,
             data.tree.get_id(sd.name),
             sd.body.var_decs.len() as i32,
-        )?;
+        );
 
         Ok(())
     }
@@ -336,14 +284,15 @@ impl VMGenerator {
             SyntaxTree::get_this(),
             SymbolData {
                 stype: data.tree.get_type(),
-                segment: Segment::Argument(0),
+                segment: Segment::Argument,
+                index: 0,
             },
         );
 
         self.prep_function(sd, data, 1)?;
 
-        data.w.push(Segment::Argument(0))?;
-        data.w.pop(Segment::Pointer(0))?;
+        data.w.push(Segment::Argument, 0);
+        data.w.pop(Segment::Pointer, 0);
 
         Ok(())
     }
@@ -362,6 +311,11 @@ impl VMGenerator {
                 Statement::While(ws) => self.gen_while(ws, data)?,
                 Statement::Do(ds) => self.gen_do(ds, data)?,
                 Statement::Return(rs) => self.gen_ret(rs, data)?,
+                Statement::Error => {
+                    return Err(Box::new(VMGeneratorError {
+                        str: format!("Statement with syntax errors in {}", data.tree.filename),
+                    }))
+                }
             }
         }
 
@@ -369,20 +323,20 @@ impl VMGenerator {
     }
 
     fn gen_let(&self, ls: &LetStmt, data: &mut GenData) -> VMGeneratorResult {
-        let seg = data.get_var(ls.name, data.tree)?.segment;
+        let var = data.get_var(ls.name, data.tree)?;
         if let Some(expr) = &ls.idx {
-            data.w.push(seg)?;
+            data.w.push(var.segment, var.index);
             self.gen_expression(expr, data)?;
-            data.w.arith(Op::Add)?;
+            data.w.arith(Op::Add);
 
             self.gen_expression(&ls.eq_to, data)?;
-            data.w.pop(Segment::Temp(0))?;
-            data.w.pop(Segment::Pointer(1))?;
-            data.w.push(Segment::Temp(0))?;
-            data.w.pop(Segment::That)?;
+            data.w.pop(Segment::Temp, 0);
+            data.w.pop(Segment::Pointer, 1);
+            data.w.push(Segment::Temp, 0);
+            data.w.pop(Segment::That, 0);
         } else {
             self.gen_expression(&ls.eq_to, data)?;
-            data.w.pop(seg)?;
+            data.w.pop(var.segment, var.index);
         }
 
         Ok(())
@@ -390,23 +344,23 @@ impl VMGenerator {
 
     fn gen_if(&self, is: &IfStmt, data: &mut GenData) -> VMGeneratorResult {
         self.gen_expression(&is.cond, data)?;
-        data.w.arith(Op::Not)?;
+        data.w.arith(Op::Not);
         let ifnot_label = data.get_label();
         let after_else = if !is.else_body.is_empty() {
             data.get_label()
         } else {
             String::new()
         };
-        data.w.if_goto(&ifnot_label)?;
+        data.w.if_goto(&ifnot_label);
         self.gen_stmts(&is.body, data)?;
         if !is.else_body.is_empty() {
-            data.w.goto(&after_else)?;
+            data.w.goto(&after_else);
         }
 
-        data.w.label(&ifnot_label)?;
+        data.w.label(&ifnot_label);
         if !is.else_body.is_empty() {
             self.gen_stmts(&is.else_body, data)?;
-            data.w.label(&after_else)?;
+            data.w.label(&after_else);
         }
 
         Ok(())
@@ -416,23 +370,23 @@ impl VMGenerator {
         let loop_label = data.get_label();
         let after_loop = data.get_label();
 
-        data.w.label(&loop_label)?;
+        data.w.label(&loop_label);
 
         self.gen_expression(&ws.cond, data)?;
-        data.w.arith(Op::Not)?;
-        data.w.if_goto(&after_loop)?;
+        data.w.arith(Op::Not);
+        data.w.if_goto(&after_loop);
 
         self.gen_stmts(&ws.body, data)?;
-        data.w.goto(&loop_label)?;
+        data.w.goto(&loop_label);
 
-        data.w.label(&after_loop)?;
+        data.w.label(&after_loop);
 
         Ok(())
     }
 
     fn gen_do(&self, ds: &DoStmt, data: &mut GenData) -> VMGeneratorResult {
         self.gen_subroutine_call(&ds.call, data)?;
-        data.w.pop(Segment::Temp(0))?;
+        data.w.pop(Segment::Temp, 0);
 
         Ok(())
     }
@@ -441,10 +395,10 @@ impl VMGenerator {
         if let Some(expr) = &rs.ret_val {
             self.gen_expression(expr, data)?;
         } else {
-            data.w.push(Segment::Constant(0))?;
+            data.w.push(Segment::Constant, 0);
         }
 
-        data.w.ret()?;
+        data.w.ret();
 
         Ok(())
     }
@@ -454,7 +408,7 @@ impl VMGenerator {
 
         for (op, term) in &expr.ops {
             self.gen_term(*term, data)?;
-            data.w.arith(op.into())?;
+            data.w.binary(op);
         }
 
         Ok(())
@@ -466,7 +420,7 @@ impl VMGenerator {
             if let Ok(sd) = data.get_var(c, data.tree) {
                 match sd.stype {
                     Type::ClassName(typ) => {
-                        data.w.push(sd.segment)?;
+                        data.w.push(sd.segment, sd.index);
                         (data.tree.get_id(typ), 1)
                     }
                     _ => unreachable!(),
@@ -477,7 +431,7 @@ impl VMGenerator {
                 (data.tree.get_id(c), 0)
             }
         } else {
-            data.w.push(Segment::Pointer(0))?;
+            data.w.push(Segment::Pointer, 0);
             (data.tree.filename.as_str(), 1)
         };
 
@@ -486,7 +440,7 @@ impl VMGenerator {
         }
 
         nargs += call.args.len();
-        data.w.call(caller, name, nargs as i32)?;
+        data.w.call(caller, name, nargs as i32);
 
         Ok(())
     }
@@ -494,21 +448,23 @@ impl VMGenerator {
     fn gen_term(&self, term: usize, data: &mut GenData) -> VMGeneratorResult {
         match &data.tree.terms[term] {
             Term::Int(i) => {
-                data.w.push(Segment::Constant(*i))?;
+                data.w.push(Segment::Constant, *i);
             }
             Term::String(s) => {
                 let s = data.tree.get_id(*s);
 
-                data.w.push(Segment::Constant(s.len() as i32))?;
-                data.w.call("String", "new", 1)?;
+                data.w.push(Segment::Constant, s.chars().count() as i32);
+                data.w.call("String", "new", 1);
                 for c in s.chars() {
-                    data.w.push(Segment::Constant(c as i32))?;
-                    data.w.call("String", "appendChar", 2)?;
+                    // `\n` in the source is Jack's newline
+                    let c = if c == '\n' { JACK_NEWLINE } else { c as i32 };
+                    data.w.push(Segment::Constant, c);
+                    data.w.call("String", "appendChar", 2);
                 }
             }
             Term::VarName(name) => {
-                let s = data.get_var(*name, data.tree)?.segment;
-                data.w.push(s)?;
+                let var = data.get_var(*name, data.tree)?;
+                data.w.push(var.segment, var.index);
             }
             Term::KeywordConstant(kw) => match kw {
                 KeywordConstant::Unknown => {
@@ -517,26 +473,26 @@ impl VMGenerator {
                     }))
                 }
                 KeywordConstant::True => {
-                    data.w.push(Segment::Constant(0))?;
-                    data.w.arith(Op::Not)?;
+                    data.w.push(Segment::Constant, 0);
+                    data.w.arith(Op::Not);
                 }
                 KeywordConstant::False => {
-                    data.w.push(Segment::Constant(0))?;
+                    data.w.push(Segment::Constant, 0);
                 }
                 KeywordConstant::Null => {
-                    data.w.push(Segment::Constant(0))?;
+                    data.w.push(Segment::Constant, 0);
                 }
                 KeywordConstant::This => {
-                    data.w.push(Segment::Pointer(0))?;
+                    data.w.push(Segment::Pointer, 0);
                 }
             },
             Term::ArrayAccess(arr) => {
-                let s = data.get_var(arr.var, data.tree)?.segment;
-                data.w.push(s)?;
+                let var = data.get_var(arr.var, data.tree)?;
+                data.w.push(var.segment, var.index);
                 self.gen_expression(&arr.idx, data)?;
-                data.w.arith(Op::Add)?;
-                data.w.pop(Segment::Pointer(1))?;
-                data.w.push(Segment::That)?;
+                data.w.arith(Op::Add);
+                data.w.pop(Segment::Pointer, 1);
+                data.w.push(Segment::That, 0);
             }
             Term::Call(call) => {
                 self.gen_subroutine_call(call, data)?;
@@ -546,7 +502,7 @@ impl VMGenerator {
             }
             Term::Unary(term) => {
                 self.gen_term(term.term, data)?;
-                data.w.arith(term.op.into())?;
+                data.w.unary(term.op);
             }
         }
 
@@ -297,6 +297,7 @@ impl XMLAnalyzer {
             Statement::While(whily) => self.write_while(d, whily, terms),
             Statement::Do(ds) => self.write_do(d, ds, terms),
             Statement::Return(ret) => self.write_return(d, ret, terms),
+            Statement::Error => Ok(()),
         }
     }
 
@@ -0,0 +1,198 @@
+use std::fmt::{Display, Write};
+
+use super::tokens::Span;
+
+// Error codes, lexical errors are E00xx, syntax errors E01xx,
+// semantic errors E02xx, type errors E03xx and errors across
+// classes E04xx, warnings have codes of their own.
+pub(crate) const INTERNAL_ERROR: &str = "E0000";
+pub(crate) const INVALID_CHARACTER: &str = "E0001";
+pub(crate) const UNTERMINATED_STRING: &str = "E0002";
+pub(crate) const UNTERMINATED_COMMENT: &str = "E0003";
+pub(crate) const INTEGER_TOO_LARGE: &str = "E0004";
+pub(crate) const INVALID_IDENTIFIER: &str = "E0005";
+pub(crate) const UNEXPECTED_TOKEN: &str = "E0100";
+pub(crate) const UNEXPECTED_EOF: &str = "E0101";
+pub(crate) const INVALID_TYPE: &str = "E0102";
+pub(crate) const INVALID_TERM: &str = "E0103";
+pub(crate) const UNDECLARED_VARIABLE: &str = "E0200";
+pub(crate) const DUPLICATE_DECLARATION: &str = "E0201";
+pub(crate) const THIS_IN_FUNCTION: &str = "E0202";
+pub(crate) const FIELD_IN_FUNCTION: &str = "E0203";
+pub(crate) const METHOD_WITHOUT_OBJECT: &str = "E0204";
+pub(crate) const TYPE_MISMATCH: &str = "E0300";
+pub(crate) const INVALID_OPERAND: &str = "E0301";
+pub(crate) const NOT_AN_ARRAY: &str = "E0302";
+pub(crate) const RETURN_VALUE: &str = "E0303";
+pub(crate) const UNKNOWN_CLASS: &str = "E0400";
+pub(crate) const UNKNOWN_SUBROUTINE: &str = "E0401";
+pub(crate) const WRONG_CALL_KIND: &str = "E0402";
+pub(crate) const ARGUMENT_COUNT: &str = "E0403";
+pub(crate) const NOT_AN_OBJECT: &str = "E0404";
+pub(crate) const UNREACHABLE_CODE: &str = "W0001";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A span of the source with an optional explanation.
+#[derive(Debug, Clone)]
+pub(crate) struct Label {
+    pub(crate) span: Span,
+    pub(crate) message: String,
+}
+
+///
+/// An error or warning about a source file. The primary label
+/// points at the offending code, the secondary ones at related
+/// code in the same file.
+///
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub file: String,
+    pub(crate) primary: Label,
+    pub(crate) secondary: Vec<Label>,
+}
+
+fn json_string(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+
+    res
+}
+
+// Tabs shown as 4 spaces, in the source line and the underline alike
+fn expand_tabs(s: &str) -> String {
+    s.replace('\t', "    ")
+}
+
+impl Diagnostic {
+    pub(crate) fn new(severity: Severity, code: &'static str, message: impl Into<String>, file: &str, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            file: file.to_string(),
+            primary: Label { span, message: String::new() },
+            secondary: Vec::new(),
+        }
+    }
+
+    pub(crate) fn error(code: &'static str, message: impl Into<String>, file: &str, span: Span) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, message, file, span)
+    }
+
+    pub(crate) fn warning(code: &'static str, message: impl Into<String>, file: &str, span: Span) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, code, message, file, span)
+    }
+
+    /// Explain the primary span.
+    pub(crate) fn with_label(mut self, message: impl Into<String>) -> Diagnostic {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub(crate) fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.secondary.push(Label { span, message: message.into() });
+        self
+    }
+
+    ///
+    /// Render like rustc: the message, the location and every
+    /// labelled line of `source` with the primary span underlined
+    /// by `^` and the secondary ones by `-`.
+    ///
+    pub fn render(&self, source: &str) -> String {
+        let lines: Vec<&str> = source.lines().collect();
+        let mut labels: Vec<(&Label, char)> = vec![(&self.primary, '^')];
+        labels.extend(self.secondary.iter().map(|l| (l, '-')));
+        labels.sort_by_key(|(l, _)| (l.span.line, l.span.col));
+
+        let width = labels.iter().map(|(l, _)| l.span.line.to_string().len()).max().unwrap_or(1);
+        let gutter = " ".repeat(width);
+
+        let mut res = String::new();
+        let _ = writeln!(res, "{}[{}]: {}", self.severity, self.code, self.message);
+        let _ = writeln!(res, "{}--> {}:{}", gutter, self.file, self.primary.span);
+        let _ = writeln!(res, "{} |", gutter);
+        let mut last_line = 0;
+        for (label, marker) in labels {
+            let line = lines.get(label.span.line.wrapping_sub(1)).copied().unwrap_or("");
+            if label.span.line != last_line {
+                let _ = writeln!(res, "{:>width$} | {}", label.span.line, expand_tabs(line));
+                last_line = label.span.line;
+            }
+            let col = label.span.col.max(1) - 1;
+            let before: String = line.chars().take(col).collect();
+            let on_line = line.chars().count().saturating_sub(col);
+            let len = source.get(label.span.start..label.span.end).map_or(0, |s| s.chars().take_while(|c| *c != '\n').count());
+
+            let underline = marker.to_string().repeat(len.min(on_line).max(1));
+            let _ = write!(res, "{} | {}{}", gutter, " ".repeat(expand_tabs(&before).chars().count()), underline);
+            if !label.message.is_empty() {
+                let _ = write!(res, " {}", label.message);
+            }
+            res.push('\n');
+        }
+
+        res
+    }
+
+    /// One line of JSON for editors.
+    pub fn to_json(&self) -> String {
+        let mut spans = vec![(&self.primary, true)];
+        spans.extend(self.secondary.iter().map(|l| (l, false)));
+        let spans: Vec<String> = spans
+            .into_iter()
+            .map(|(l, primary)| {
+                format!(
+                    "{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"primary\":{},\"label\":{}}}",
+                    l.span.start,
+                    l.span.end,
+                    l.span.line,
+                    l.span.col,
+                    primary,
+                    json_string(&l.message)
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"severity\":{},\"code\":{},\"message\":{},\"file\":{},\"spans\":[{}]}}",
+            json_string(&self.severity.to_string()),
+            json_string(self.code),
+            json_string(&self.message),
+            json_string(&self.file),
+            spans.join(",")
+        )
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: {} at {}:{}", self.severity, self.code, self.message, self.file, self.primary.span)
+    }
+}
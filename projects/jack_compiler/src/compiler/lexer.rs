@@ -1,11 +1,130 @@
+use std::borrow::Cow;
+
 use crate::compiler::tokens::Token;
 
-use super::tokens::{Keyword, Symbol, TokenData};
+use super::diagnostics::{
+    Diagnostic, INTEGER_TOO_LARGE, INVALID_CHARACTER, INVALID_IDENTIFIER, UNTERMINATED_COMMENT,
+    UNTERMINATED_STRING,
+};
+use super::tokens::{Keyword, Span, Symbol, TokenData};
+
+const MAX_INT: i32 = 32767;
 
 pub(crate) struct Lexer {
     pub(crate) filename: String,
 }
 
+// Position in the source being scanned
+struct Cursor<'a> {
+    source: &'a str,
+    pos: usize,
+    line: usize,
+    col: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.source[self.pos..].chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+
+        Some(c)
+    }
+
+    fn eat_while(&mut self, pred: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&pred) {
+            self.bump();
+        }
+    }
+
+    // Span from `start` up to the current position
+    fn span(&self, start: &Span) -> Span {
+        Span { end: self.pos, ..*start }
+    }
+
+    fn start(&self) -> Span {
+        Span {
+            start: self.pos,
+            end: self.pos,
+            line: self.line,
+            col: self.col,
+        }
+    }
+}
+
+fn symbol(c: char) -> Option<Symbol> {
+    let sym = match c {
+        '}' => Symbol::RightCurly,
+        '{' => Symbol::LeftCurly,
+        ')' => Symbol::RightRound,
+        '(' => Symbol::LeftRound,
+        ']' => Symbol::RightSquare,
+        '[' => Symbol::LeftSquare,
+        '.' => Symbol::Dot,
+        ',' => Symbol::Comma,
+        ';' => Symbol::Semicolon,
+        '+' => Symbol::Plus,
+        '-' => Symbol::Minus,
+        '*' => Symbol::Multiply,
+        '/' => Symbol::Divide,
+        '&' => Symbol::And,
+        '|' => Symbol::Or,
+        '=' => Symbol::Equal,
+        '~' => Symbol::Not,
+        '<' => Symbol::Less,
+        '>' => Symbol::Greater,
+        _ => return None,
+    };
+
+    Some(sym)
+}
+
+fn keyword(word: &str) -> Option<Keyword> {
+    let kw = match word {
+        "class" => Keyword::Class,
+        "constructor" => Keyword::Constructor,
+        "function" => Keyword::Function,
+        "method" => Keyword::Method,
+        "field" => Keyword::Field,
+        "static" => Keyword::Static,
+        "var" => Keyword::Var,
+        "int" => Keyword::Int,
+        "char" => Keyword::Char,
+        "boolean" => Keyword::Boolean,
+        "void" => Keyword::Void,
+        "true" => Keyword::True,
+        "false" => Keyword::False,
+        "null" => Keyword::Null,
+        "this" => Keyword::This,
+        "let" => Keyword::Let,
+        "do" => Keyword::Do,
+        "if" => Keyword::If,
+        "else" => Keyword::Else,
+        "while" => Keyword::While,
+        "return" => Keyword::Return,
+        _ => return None,
+    };
+
+    Some(kw)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
 impl Lexer {
     pub(crate) fn new(filename: String) -> Lexer {
         Lexer {
@@ -13,166 +132,158 @@ impl Lexer {
         }
     }
 
-    pub(crate) fn lex<'a>(&'a self, source: &'a [String]) -> Vec<Token<'a>> {
+    ///
+    /// Split `source` into tokens, skipping whitespace and all
+    /// three forms of comments. Scanning goes on after an error so
+    /// that all of them are reported.
+    ///
+    pub(crate) fn lex<'a>(&'a self, source: &'a str) -> Result<Vec<Token<'a>>, Vec<Diagnostic>> {
         let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+
+        let mut cur = Cursor {
+            source,
+            pos: 0,
+            line: 1,
+            col: 1,
+        };
+        while let Some(c) = cur.peek() {
+            let start = cur.start();
 
-        let mut in_comment = false;
-        for (lineno, line) in source.iter().enumerate() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with("//") {
+            if c.is_whitespace() {
+                cur.bump();
                 continue;
             }
 
-            if line.starts_with("/**") {
-                in_comment = true;
+            // `//` up to the end of the line, `/* */` and `/** */`
+            // anywhere, even within a line
+            if c == '/' && cur.peek_second() == Some('/') {
+                cur.eat_while(|c| c != '\n');
+                continue;
             }
-
-            let cmt_split = line.split("//").collect::<Vec<&str>>();
-            let line = cmt_split[0];
-            let line = line.trim();
-
-            if line.ends_with("*/") {
-                in_comment = false;
+            if c == '/' && cur.peek_second() == Some('*') {
+                cur.bump();
+                cur.bump();
+                if !self.block_comment(&mut cur) {
+                    // Only the opening `/*` is underlined
+                    errors.push(
+                        Diagnostic::error(UNTERMINATED_COMMENT, "unterminated block comment", &self.filename, Span { end: start.start + 2, ..start })
+                            .with_label("comment starts here"),
+                    );
+                }
                 continue;
             }
 
-            if in_comment {
+            let data = if c == '"' {
+                cur.bump();
+                match self.string(&mut cur) {
+                    Some(s) => TokenData::String(s),
+                    None => {
+                        errors.push(
+                            Diagnostic::error(UNTERMINATED_STRING, "unterminated string constant", &self.filename, cur.span(&start))
+                                .with_label("missing closing `\"` on this line"),
+                        );
+                        continue;
+                    }
+                }
+            } else if let Some(sym) = symbol(c) {
+                cur.bump();
+                TokenData::Symbol(sym)
+            } else if is_word_char(c) {
+                cur.eat_while(is_word_char);
+                let word = &source[start.start..cur.pos];
+                if c.is_ascii_digit() {
+                    let error = if !word.bytes().all(|b| b.is_ascii_digit()) {
+                        Diagnostic::error(INVALID_IDENTIFIER, format!("identifier `{}` starts with a digit", word), &self.filename, cur.span(&start))
+                    } else {
+                        match word.parse::<i32>() {
+                            Ok(i) if i <= MAX_INT => {
+                                tokens.push(Token {
+                                    data: TokenData::Int(i),
+                                    file: &self.filename,
+                                    span: cur.span(&start),
+                                });
+                                continue;
+                            }
+                            _ => Diagnostic::error(
+                                INTEGER_TOO_LARGE,
+                                format!("integer constant {} is larger than {}", word, MAX_INT),
+                                &self.filename,
+                                cur.span(&start),
+                            ),
+                        }
+                    };
+                    errors.push(error);
+                    continue;
+                } else {
+                    keyword(word).map_or(TokenData::Identifier(word), TokenData::Keyword)
+                }
+            } else {
+                cur.bump();
+                errors.push(Diagnostic::error(INVALID_CHARACTER, format!("invalid character {:?}", c), &self.filename, cur.span(&start)));
                 continue;
-            }
+            };
 
-            let match_idx = line.match_indices(|c: char| {
-                matches!(
-                    c,
-                    '{' | '}'
-                        | '('
-                        | ')'
-                        | '['
-                        | ']'
-                        | '.'
-                        | ','
-                        | ';'
-                        | '+'
-                        | '-'
-                        | '*'
-                        | '/'
-                        | '&'
-                        | '|'
-                        | '='
-                        | '~'
-                        | '<'
-                        | '>'
-                        | ' '
-                        | '"'
-                )
+            tokens.push(Token {
+                data,
+                file: &self.filename,
+                span: cur.span(&start),
             });
+        }
 
-            let mut toks = Vec::new();
-            let mut last = 0;
-            for (idx, matched) in match_idx {
-                if last != idx {
-                    toks.push(&line[last..idx]);
-                }
-                toks.push(matched);
-                last = idx + matched.len();
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Skip the rest of a comment after its `/*`, false if
+    // the source ends before `*/`
+    fn block_comment(&self, cur: &mut Cursor) -> bool {
+        while let Some(c) = cur.bump() {
+            if c == '*' && cur.peek() == Some('/') {
+                cur.bump();
+                return true;
             }
+        }
 
-            let mut i = 0;
-            // keeping len of read characters so that
-            // we can substring any string tokens we find along the way
-            let mut read = 0;
-            let mut str_s;
-            while i < toks.len() {
-                if i > 0 {
-                    read += toks[i - 1].len();
-                }
+        false
+    }
 
-                if toks[i].is_empty() || toks[i] == " " {
-                    i += 1;
-                    continue;
+    // The rest of a string constant after its opening quote,
+    // `None` if the line ends first
+    fn string<'a>(&self, cur: &mut Cursor<'a>) -> Option<Cow<'a, str>> {
+        let start = cur.pos;
+        let mut unescaped: Option<String> = None;
+        loop {
+            let end = cur.pos;
+            match cur.peek() {
+                None | Some('\n') | Some('\r') => return None,
+                Some('"') => {
+                    cur.bump();
+                    return Some(match unescaped {
+                        Some(s) => Cow::Owned(s),
+                        None => Cow::Borrowed(&cur.source[start..end]),
+                    });
                 }
-
-                if toks[i] == "\"" {
-                    read += 1;
-                    str_s = read;
-                    let mut j = i + 1;
-                    while toks[j] != "\"" {
-                        read += toks[j].len();
-                        j += 1;
-                    }
-
-                    let token = Token {
-                        data: TokenData::String(&line[str_s..read]),
-                        file: &self.filename,
-                        line: lineno,
+                // Only \" \\ and \n, any other backslash is kept
+                // as it is since Jack itself has no escapes
+                Some('\\') if matches!(cur.peek_second(), Some('"' | '\\' | 'n')) => {
+                    cur.bump();
+                    let c = match cur.bump() {
+                        Some('n') => '\n',
+                        c => c.unwrap_or_default(),
                     };
-                    tokens.push(token);
-
-                    i = j + 1;
-                    continue;
+                    unescaped.get_or_insert_with(|| cur.source[start..end].to_string()).push(c);
                 }
-
-                let token = match toks[i] {
-                    "}" => TokenData::Symbol(Symbol::RightCurly),
-                    "{" => TokenData::Symbol(Symbol::LeftCurly),
-                    ")" => TokenData::Symbol(Symbol::RightRound),
-                    "(" => TokenData::Symbol(Symbol::LeftRound),
-                    "]" => TokenData::Symbol(Symbol::RightSquare),
-                    "[" => TokenData::Symbol(Symbol::LeftSquare),
-                    "." => TokenData::Symbol(Symbol::Dot),
-                    "," => TokenData::Symbol(Symbol::Comma),
-                    ";" => TokenData::Symbol(Symbol::Semicolon),
-                    "+" => TokenData::Symbol(Symbol::Plus),
-                    "-" => TokenData::Symbol(Symbol::Minus),
-                    "*" => TokenData::Symbol(Symbol::Multiply),
-                    "/" => TokenData::Symbol(Symbol::Divide),
-                    "&" => TokenData::Symbol(Symbol::And),
-                    "|" => TokenData::Symbol(Symbol::Or),
-                    "=" => TokenData::Symbol(Symbol::Equal),
-                    "~" => TokenData::Symbol(Symbol::Not),
-                    "<" => TokenData::Symbol(Symbol::Less),
-                    ">" => TokenData::Symbol(Symbol::Greater),
-                    "class" => TokenData::Keyword(Keyword::Class),
-                    "constructor" => TokenData::Keyword(Keyword::Constructor),
-                    "function" => TokenData::Keyword(Keyword::Function),
-                    "method" => TokenData::Keyword(Keyword::Method),
-                    "field" => TokenData::Keyword(Keyword::Field),
-                    "static" => TokenData::Keyword(Keyword::Static),
-                    "var" => TokenData::Keyword(Keyword::Var),
-                    "int" => TokenData::Keyword(Keyword::Int),
-                    "char" => TokenData::Keyword(Keyword::Char),
-                    "boolean" => TokenData::Keyword(Keyword::Boolean),
-                    "void" => TokenData::Keyword(Keyword::Void),
-                    "true" => TokenData::Keyword(Keyword::True),
-                    "false" => TokenData::Keyword(Keyword::False),
-                    "null" => TokenData::Keyword(Keyword::Null),
-                    "this" => TokenData::Keyword(Keyword::This),
-                    "let" => TokenData::Keyword(Keyword::Let),
-                    "do" => TokenData::Keyword(Keyword::Do),
-                    "if" => TokenData::Keyword(Keyword::If),
-                    "else" => TokenData::Keyword(Keyword::Else),
-                    "while" => TokenData::Keyword(Keyword::While),
-                    "return" => TokenData::Keyword(Keyword::Return),
-
-                    sym => {
-                        if sym.parse::<i32>().is_ok() {
-                            TokenData::Int(sym.parse::<i32>().unwrap())
-                        } else {
-                            TokenData::Identifier(sym)
-                        }
+                Some(c) => {
+                    cur.bump();
+                    if let Some(s) = &mut unescaped {
+                        s.push(c);
                     }
-                };
-
-                let token = Token {
-                    data: token,
-                    file: &self.filename,
-                    line: lineno,
-                };
-                tokens.push(token);
-
-                i += 1;
+                }
             }
         }
-
-        tokens
     }
 }
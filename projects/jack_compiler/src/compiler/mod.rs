@@ -4,4 +4,5 @@ mod tokens;
 
 pub mod analyzer;
 pub mod analyzers;
+pub mod diagnostics;
 pub mod parser;
@@ -1,89 +1,143 @@
-use std::error::Error;
-use std::fmt::Display;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
 use std::path::PathBuf;
 
+use super::diagnostics::{
+    Diagnostic, Severity, INTERNAL_ERROR, INVALID_TERM, INVALID_TYPE, UNEXPECTED_EOF, UNEXPECTED_TOKEN,
+    UNREACHABLE_CODE,
+};
 use super::lexer::Lexer;
 use super::syntax::{
     ArrayAccess, ClassNode, ClassVarDec, ClassVarKind, Expression, IdentifierId, KeywordConstant,
     Op, Param, Statement, SubroutineBody, SubroutineCall, SubroutineDec, SubroutineKind,
     SubroutineType, SyntaxTree, Term, Type, UnaryTerm, VarDec,
 };
-use super::tokens::{Keyword, Symbol, Token, TokenData, TokenKind};
+use super::tokens::{Keyword, Span, Symbol, Token, TokenData, TokenKind};
 
 #[macro_export]
 macro_rules! return_internal {
     () => {
-        return Err(ParseError {
-            message: format!("Internal error at {}:{}", file!(), line!()),
-        })
+        return Err(Diagnostic::error(
+            INTERNAL_ERROR,
+            format!("internal error at {}:{}", file!(), line!()),
+            "",
+            Span::default(),
+        )
+        .into())
     };
 }
 
-#[derive(Debug)]
-pub struct ParseError {
-    message: String,
+// Boxed to keep the `Ok` path small
+type ParseResult<T> = Result<T, Box<Diagnostic>>;
+
+struct ParserData<'a> {
+    tokens: Vec<Token<'a>>,
+    terms: Vec<Term>,
+    term_spans: Vec<Span>,
+    ptr: usize,
+    /// Warnings and the errors recovered from
+    diagnostics: Vec<Diagnostic>,
 }
 
-impl Display for ParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+impl<'a> ParserData<'a> {
+    // The current token, `None` at the end of the file
+    fn peek(&self) -> Option<&TokenData<'a>> {
+        self.tokens.get(self.ptr).map(|t| &t.data)
+    }
+
+    // Record an error recovered from, unless it is one already
+    // reported at the same token by an enclosing construct
+    fn record(&mut self, e: Diagnostic) {
+        let dup = self
+            .diagnostics
+            .iter()
+            .rev()
+            .find(|d| d.severity == Severity::Error)
+            .is_some_and(|d| d.primary.span.start == e.primary.span.start);
+        if !dup {
+            self.diagnostics.push(e);
+        }
     }
 }
 
-impl Error for ParseError {}
+// Keywords which only start a class level declaration
+fn is_declaration(kw: Keyword) -> bool {
+    matches!(
+        kw,
+        Keyword::Class | Keyword::Constructor | Keyword::Function | Keyword::Method | Keyword::Field | Keyword::Static
+    )
+}
 
-struct ParserData<'a> {
-    tokens: Vec<Token<'a>>,
-    terms: Vec<Term>,
-    ptr: usize,
+// Keywords which start a statement or a local declaration
+fn is_statement(kw: Keyword) -> bool {
+    matches!(
+        kw,
+        Keyword::Var | Keyword::Let | Keyword::If | Keyword::While | Keyword::Do | Keyword::Return
+    )
 }
 
 pub struct Parser {
     pub filename: String,
 
-    source: Vec<String>,
+    /// The file as given, for diagnostics
+    path: String,
+    source: String,
     lexer: Lexer,
     //tokens: Vec<Token>,
 }
 
 impl Parser {
     pub fn new(path: PathBuf) -> Parser {
-        let filename = path.file_stem().unwrap().to_str().unwrap();
+        let source = fs::read_to_string(&path).unwrap();
 
-        let mut source = Vec::new();
-        let in_file = File::open(&path).unwrap();
-        let reader = BufReader::new(in_file);
-        for line in reader.lines() {
-            let line = line.unwrap_or("".to_string());
-            source.push(line);
-        }
+        Parser::from_source(path, source)
+    }
+
+    /// Parse `source` as if it was read from `path`.
+    pub fn from_source(path: PathBuf, source: String) -> Parser {
+        let filename = path.file_stem().unwrap().to_str().unwrap();
 
         Parser {
             filename: filename.to_string(),
+            path: path.display().to_string(),
             source,
-            lexer: Lexer::new(filename.to_string()),
+            lexer: Lexer::new(path.display().to_string()),
             //tokens: Default::default(),
         }
     }
 
-    pub fn parse(&mut self) -> Result<SyntaxTree, ParseError> {
+    ///
+    /// Parse the file into a syntax tree, going on after syntax
+    /// errors so that all of them are reported. Statements that
+    /// failed to parse are `Statement::Error` nodes and other
+    /// broken declarations are left out. The tree is `None` if
+    /// the file could not be lexed or lacks a class header.
+    ///
+    pub fn parse(&self) -> (Option<SyntaxTree>, Vec<Diagnostic>) {
+        let tokens = match self.lexer.lex(&self.source) {
+            Ok(tokens) => tokens,
+            Err(errors) => return (None, errors),
+        };
         let mut d = ParserData {
-            tokens: self.lexer.lex(&self.source),
+            tokens,
             terms: Vec::new(),
+            term_spans: Vec::new(),
             ptr: 0,
+            diagnostics: Vec::new(),
         };
         let root_node_res = self.parse_root(&mut d);
 
         let mut tree = SyntaxTree::new();
         tree.filename = self.filename.clone();
-        tree.root = if let Ok(node) = root_node_res {
-            node
-        } else {
-            return Err(root_node_res.err().unwrap());
+        tree.root = match root_node_res {
+            Ok(node) => node,
+            Err(e) => {
+                d.record(*e);
+                return (None, d.diagnostics);
+            }
         };
+        let diagnostics = d.diagnostics;
         tree.terms = d.terms;
+        tree.term_spans = d.term_spans;
         tree.tokens = d.tokens;
 
         // println!("{:?}", tree.root);
@@ -94,10 +148,15 @@ impl Parser {
         //     println!("{i} - {tok:?}");
         // }
 
-        Ok(tree)
+        (Some(tree), diagnostics)
     }
 
-    fn parse_root(&self, d: &mut ParserData) -> Result<ClassNode, ParseError> {
+    /// The source of the file, to render diagnostics with.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn parse_root(&self, d: &mut ParserData) -> ParseResult<ClassNode> {
         self.expect_keyword(Keyword::Class, d)?;
         self.expect(TokenKind::Identifier, d)?;
         let class_name = if let TokenData::Identifier(_name) = d.tokens[d.ptr - 1].data {
@@ -107,8 +166,11 @@ impl Parser {
         };
         self.expect_symbol(Symbol::LeftCurly, d)?;
 
-        let var_dec = self.parse_classvardec(d)?;
-        let subroutine_dec = self.parse_subroutinedec(d)?;
+        let var_dec = self.parse_classvardec(d);
+        let subroutine_dec = self.parse_subroutinedec(d);
+        if let Err(e) = self.expect_symbol(Symbol::RightCurly, d) {
+            d.record(*e);
+        }
 
         Ok(ClassNode {
             name: class_name,
@@ -117,8 +179,11 @@ impl Parser {
         })
     }
 
-    fn parse_type(&self, d: &mut ParserData) -> Result<Type, ParseError> {
-        self.expect_any(vec![TokenKind::Identifier, TokenKind::Keyword], d)?;
+    fn parse_type(&self, d: &mut ParserData) -> ParseResult<Type> {
+        if !matches!(d.peek(), Some(TokenData::Identifier(_) | TokenData::Keyword(_))) {
+            return Err(self.unexpected(d, d.ptr, "a type"));
+        }
+        d.ptr += 1;
         let curr_tok = &d.tokens[d.ptr - 1];
         let var_type = match curr_tok.data {
             TokenData::Identifier(_id) => Type::ClassName(d.ptr - 1),
@@ -127,12 +192,16 @@ impl Parser {
                 Keyword::Char => Type::Char,
                 Keyword::Int => Type::Int,
                 _ => {
-                    return Err(ParseError {
-                        message: format!(
-                            "Unexpected keyword {:?} in type at {}:{}",
-                            kw, curr_tok.file, curr_tok.line
-                        ),
-                    });
+                    let e = Diagnostic::error(
+                        INVALID_TYPE,
+                        format!("expected a type, found keyword `{}`", kw),
+                        curr_tok.file,
+                        curr_tok.span,
+                    )
+                    .with_label("not a type")
+                    .into();
+                    self.revert(d)?;
+                    return Err(e);
                 }
             },
             _ => {
@@ -143,7 +212,7 @@ impl Parser {
         Ok(var_type)
     }
 
-    fn parse_vardec(&self, d: &mut ParserData) -> Result<Vec<VarDec>, ParseError> {
+    fn parse_vardec(&self, d: &mut ParserData) -> ParseResult<Vec<VarDec>> {
         let mut res = Vec::new();
 
         let var_type = self.parse_type(d)?;
@@ -152,119 +221,104 @@ impl Parser {
 
             res.push(VarDec { var_type, name });
 
-            if let TokenData::Symbol(Symbol::Semicolon) = d.tokens[d.ptr].data {
+            if self.expect_separator(Symbol::Comma, Symbol::Semicolon, d)? {
                 break;
             }
-            self.expect_symbol(Symbol::Comma, d)?;
         }
 
-        self.expect_symbol(Symbol::Semicolon, d)?;
-
         Ok(res)
     }
 
-    fn parse_classvardec(&self, d: &mut ParserData) -> Result<Vec<ClassVarDec>, ParseError> {
+    fn parse_classvardec(&self, d: &mut ParserData) -> Vec<ClassVarDec> {
         let mut res = Vec::new();
 
-        let mut tok = &d.tokens[d.ptr].data;
-        if let TokenData::Symbol(Symbol::RightCurly) = tok {
-            return Ok(res);
-        }
-
-        while let TokenData::Keyword(kw) = tok {
-            if *kw == Keyword::Constructor || *kw == Keyword::Method || *kw == Keyword::Function {
-                break;
-            }
-
-            match *kw {
-                Keyword::Constructor | Keyword::Method | Keyword::Function => {
-                    break;
+        while let Some(TokenData::Keyword(Keyword::Field | Keyword::Static)) = d.peek() {
+            let start = d.ptr;
+            match self.parse_classvar(d) {
+                Ok(mut var_decs) => res.append(&mut var_decs),
+                Err(e) => {
+                    d.record(*e);
+                    self.synchronize(d, start, false);
                 }
-                _ => (),
             }
+        }
 
-            let kind_tok = self.expect_any_keyword(vec![Keyword::Field, Keyword::Static], d)?;
-            let curr_tok = &d.tokens[d.ptr - 1];
-            let kind = match kind_tok {
-                Keyword::Field => ClassVarKind::Field,
-                Keyword::Static => ClassVarKind::Static,
-                _ => {
-                    return Err(ParseError {
-                        message: format!(
-                            "Unexpected keyword {:?} in class var dec at {}:{}",
-                            kind_tok, curr_tok.file, curr_tok.line
-                        ),
-                    });
-                }
-            };
-            let var_decs = self.parse_vardec(d)?;
-            for var_dec in var_decs {
-                res.push(ClassVarDec { kind, var_dec });
+        res
+    }
+
+    fn parse_classvar(&self, d: &mut ParserData) -> ParseResult<Vec<ClassVarDec>> {
+        let kind_tok = self.expect_any_keyword(vec![Keyword::Field, Keyword::Static], d)?;
+        let kind = match kind_tok {
+            Keyword::Field => ClassVarKind::Field,
+            Keyword::Static => ClassVarKind::Static,
+            _ => {
+                return_internal!();
             }
-            tok = &d.tokens[d.ptr].data;
-        }
+        };
+        let var_decs = self.parse_vardec(d)?;
 
-        Ok(res)
+        Ok(var_decs.into_iter().map(|var_dec| ClassVarDec { kind, var_dec }).collect())
     }
 
-    fn parse_subroutinedec(&self, d: &mut ParserData) -> Result<Vec<SubroutineDec>, ParseError> {
+    fn parse_subroutinedec(&self, d: &mut ParserData) -> Vec<SubroutineDec> {
         let mut res = Vec::new();
 
-        let mut tok = &d.tokens[d.ptr].data;
-        loop {
-            if let TokenData::Symbol(Symbol::RightCurly) = tok {
-                break;
-            }
-
-            let func_kind = self.expect_any_keyword(
-                vec![Keyword::Constructor, Keyword::Function, Keyword::Method],
-                d,
-            )?;
-            let kind = match func_kind {
-                Keyword::Constructor => SubroutineKind::Constructor,
-                Keyword::Function => SubroutineKind::Function,
-                Keyword::Method => SubroutineKind::Method,
-                _ => {
-                    return_internal!();
+        while d.peek().is_some_and(|t| *t != TokenData::Symbol(Symbol::RightCurly)) {
+            let start = d.ptr;
+            match self.parse_subroutine(d) {
+                Ok(sub) => res.push(sub),
+                Err(e) => {
+                    d.record(*e);
+                    self.synchronize(d, start, false);
                 }
-            };
+            }
+        }
 
-            let f_type = {
-                if let Ok(t) = self.parse_type(d) {
-                    SubroutineType::Type(t)
-                } else {
-                    self.revert(d)?;
-                    self.expect_keyword(Keyword::Void, d)?;
-                    SubroutineType::Void
-                }
-            };
+        res
+    }
 
-            let name = self.parse_name(d)?;
+    fn parse_subroutine(&self, d: &mut ParserData) -> ParseResult<SubroutineDec> {
+        let func_kind = self.expect_any_keyword(
+            vec![Keyword::Constructor, Keyword::Function, Keyword::Method],
+            d,
+        )?;
+        let kind = match func_kind {
+            Keyword::Constructor => SubroutineKind::Constructor,
+            Keyword::Function => SubroutineKind::Function,
+            Keyword::Method => SubroutineKind::Method,
+            _ => {
+                return_internal!();
+            }
+        };
 
-            self.expect_symbol(Symbol::LeftRound, d)?;
-            let params = self.parse_parameter_list(d)?;
-            self.expect_symbol(Symbol::RightRound, d)?;
+        let f_type = if let Some(TokenData::Keyword(Keyword::Void)) = d.peek() {
+            self.advance(d)?;
+            SubroutineType::Void
+        } else {
+            SubroutineType::Type(self.parse_type(d)?)
+        };
 
-            let body = self.parse_subroutine_body(d)?;
+        let name = self.parse_name(d)?;
 
-            res.push(SubroutineDec {
-                kind,
-                f_type,
-                name,
-                params,
-                body,
-            });
+        self.expect_symbol(Symbol::LeftRound, d)?;
+        let params = self.parse_parameter_list(d)?;
+        self.expect_symbol(Symbol::RightRound, d)?;
 
-            tok = &d.tokens[d.ptr].data;
-        }
+        let body = self.parse_subroutine_body(d)?;
 
-        Ok(res)
+        Ok(SubroutineDec {
+            kind,
+            f_type,
+            name,
+            params,
+            body,
+        })
     }
 
-    fn parse_parameter_list(&self, d: &mut ParserData) -> Result<Vec<Param>, ParseError> {
+    fn parse_parameter_list(&self, d: &mut ParserData) -> ParseResult<Vec<Param>> {
         let mut res = Vec::new();
 
-        if let TokenData::Symbol(Symbol::RightRound) = d.tokens[d.ptr].data {
+        if let Some(TokenData::Symbol(Symbol::RightRound)) = d.peek() {
             return Ok(res);
         }
 
@@ -274,7 +328,7 @@ impl Parser {
 
             res.push(Param { p_type, name });
 
-            if let TokenData::Symbol(Symbol::Comma) = d.tokens[d.ptr].data {
+            if let Some(TokenData::Symbol(Symbol::Comma)) = d.peek() {
                 self.advance(d)?;
                 continue;
             }
@@ -285,56 +339,82 @@ impl Parser {
         Ok(res)
     }
 
-    fn parse_statements(&self, d: &mut ParserData) -> Result<Vec<Statement>, ParseError> {
+    fn parse_statements(&self, d: &mut ParserData) -> Vec<Statement> {
         let mut stmts = Vec::new();
-
-        while let TokenData::Keyword(kw) = &d.tokens[d.ptr].data {
-            match *kw {
-                Keyword::Let => {
-                    stmts.push(self.parse_let(d)?);
-                }
-                Keyword::If => {
-                    stmts.push(self.parse_if(d)?);
-                }
-                Keyword::While => {
-                    stmts.push(self.parse_while(d)?);
-                }
-                Keyword::Do => {
-                    stmts.push(self.parse_do(d)?);
-                }
-                Keyword::Return => {
-                    stmts.push(self.parse_return(d)?);
+        // The first `return` of the block, and whether the
+        // statements after it were warned about
+        let mut returned: Option<Span> = None;
+        let mut warned = false;
+
+        while let Some(tok) = d.tokens.get(d.ptr) {
+            let kw = match tok.data {
+                TokenData::Symbol(Symbol::RightCurly) => break,
+                TokenData::Keyword(kw) if is_declaration(kw) => break,
+                TokenData::Keyword(kw) => Some(kw),
+                _ => None,
+            };
+            let is_stmt = matches!(kw, Some(Keyword::Let | Keyword::If | Keyword::While | Keyword::Do | Keyword::Return));
+            match returned {
+                Some(ret) if is_stmt && !warned => {
+                    d.diagnostics.push(
+                        Diagnostic::warning(UNREACHABLE_CODE, "unreachable statement", tok.file, tok.span)
+                            .with_label("unreachable statement")
+                            .with_secondary(ret, "any code following this return is unreachable"),
+                    );
+                    warned = true;
                 }
-                _ => {
-                    break;
+                None if kw == Some(Keyword::Return) => returned = Some(tok.span),
+                _ => {}
+            }
+
+            let start = d.ptr;
+            let stmt = match kw {
+                Some(Keyword::Let) => self.parse_let(d),
+                Some(Keyword::If) => self.parse_if(d),
+                Some(Keyword::While) => self.parse_while(d),
+                Some(Keyword::Do) => self.parse_do(d),
+                Some(Keyword::Return) => self.parse_return(d),
+                _ => Err(self.unexpected(d, start, "a statement")),
+            };
+            match stmt {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => {
+                    d.record(*e);
+                    self.synchronize(d, start, true);
+                    stmts.push(Statement::Error);
                 }
             }
         }
 
-        Ok(stmts)
+        stmts
     }
 
-    fn parse_subroutine_body(&self, d: &mut ParserData) -> Result<SubroutineBody, ParseError> {
+    fn parse_subroutine_body(&self, d: &mut ParserData) -> ParseResult<SubroutineBody> {
         self.expect_symbol(Symbol::LeftCurly, d)?;
 
         let mut var_decs = Vec::new();
-        while let TokenData::Keyword(Keyword::Var) = d.tokens[d.ptr].data {
+        while let Some(TokenData::Keyword(Keyword::Var)) = d.peek() {
+            let start = d.ptr;
             self.advance(d)?;
-            let mut vars = self.parse_vardec(d)?;
-            var_decs.append(&mut vars);
+            match self.parse_vardec(d) {
+                Ok(mut vars) => var_decs.append(&mut vars),
+                Err(e) => {
+                    d.record(*e);
+                    self.synchronize(d, start, true);
+                }
+            }
         }
 
-        let stmts = self.parse_statements(d)?;
+        let stmts = self.parse_statements(d);
 
         self.expect_symbol(Symbol::RightCurly, d)?;
         Ok(SubroutineBody { var_decs, stmts })
     }
-
-    fn parse_let(&self, d: &mut ParserData) -> Result<Statement, ParseError> {
+    fn parse_let(&self, d: &mut ParserData) -> ParseResult<Statement> {
         self.expect_keyword(Keyword::Let, d)?;
         let name = self.parse_name(d)?;
 
-        let idx = if d.tokens[d.ptr].data == TokenData::Symbol(Symbol::LeftSquare) {
+        let idx = if let Some(TokenData::Symbol(Symbol::LeftSquare)) = d.peek() {
             self.advance(d)?;
             let expr = self.parse_expression(d)?;
             self.expect_symbol(Symbol::RightSquare, d)?;
@@ -353,19 +433,19 @@ impl Parser {
         Ok(Statement::Let(super::syntax::LetStmt { name, idx, eq_to }))
     }
 
-    fn parse_if(&self, d: &mut ParserData) -> Result<Statement, ParseError> {
+    fn parse_if(&self, d: &mut ParserData) -> ParseResult<Statement> {
         self.expect_keyword(Keyword::If, d)?;
         self.expect_symbol(Symbol::LeftRound, d)?;
         let cond = self.parse_expression(d)?;
         self.expect_symbol(Symbol::RightRound, d)?;
         self.expect_symbol(Symbol::LeftCurly, d)?;
-        let body = self.parse_statements(d)?;
+        let body = self.parse_statements(d);
         self.expect_symbol(Symbol::RightCurly, d)?;
 
-        let else_body = if let TokenData::Keyword(Keyword::Else) = d.tokens[d.ptr].data {
+        let else_body = if let Some(TokenData::Keyword(Keyword::Else)) = d.peek() {
             self.advance(d)?;
             self.expect_symbol(Symbol::LeftCurly, d)?;
-            let stmts = self.parse_statements(d)?;
+            let stmts = self.parse_statements(d);
             self.expect_symbol(Symbol::RightCurly, d)?;
 
             stmts
@@ -380,19 +460,19 @@ impl Parser {
         }))
     }
 
-    fn parse_while(&self, d: &mut ParserData) -> Result<Statement, ParseError> {
+    fn parse_while(&self, d: &mut ParserData) -> ParseResult<Statement> {
         self.expect_keyword(Keyword::While, d)?;
         self.expect_symbol(Symbol::LeftRound, d)?;
         let cond = self.parse_expression(d)?;
         self.expect_symbol(Symbol::RightRound, d)?;
         self.expect_symbol(Symbol::LeftCurly, d)?;
-        let body = self.parse_statements(d)?;
+        let body = self.parse_statements(d);
         self.expect_symbol(Symbol::RightCurly, d)?;
 
         Ok(Statement::While(super::syntax::WhileStmt { cond, body }))
     }
 
-    fn parse_do(&self, d: &mut ParserData) -> Result<Statement, ParseError> {
+    fn parse_do(&self, d: &mut ParserData) -> ParseResult<Statement> {
         self.expect_keyword(Keyword::Do, d)?;
         let call = self.parse_subroutine_call(d)?;
         self.expect_symbol(Symbol::Semicolon, d)?;
@@ -400,23 +480,25 @@ impl Parser {
         Ok(Statement::Do(super::syntax::DoStmt { call }))
     }
 
-    fn parse_return(&self, d: &mut ParserData) -> Result<Statement, ParseError> {
+    fn parse_return(&self, d: &mut ParserData) -> ParseResult<Statement> {
         self.expect_keyword(Keyword::Return, d)?;
-        if let TokenData::Symbol(Symbol::Semicolon) = d.tokens[d.ptr].data {
+        let span = d.tokens[d.ptr - 1].span;
+        if let Some(TokenData::Symbol(Symbol::Semicolon)) = d.peek() {
             self.advance(d)?;
             return Ok(Statement::Return(super::syntax::ReturnStmt {
                 ret_val: None,
+                span,
             }));
         }
 
         let ret_val = Some(self.parse_expression(d)?);
         self.expect_symbol(Symbol::Semicolon, d)?;
-        Ok(Statement::Return(super::syntax::ReturnStmt { ret_val }))
+        Ok(Statement::Return(super::syntax::ReturnStmt { ret_val, span }))
     }
 
-    fn parse_subroutine_call(&self, d: &mut ParserData) -> Result<SubroutineCall, ParseError> {
+    fn parse_subroutine_call(&self, d: &mut ParserData) -> ParseResult<SubroutineCall> {
         let name = self.parse_name(d)?;
-        let (name, caller) = if let TokenData::Symbol(Symbol::Dot) = d.tokens[d.ptr].data {
+        let (name, caller) = if let Some(TokenData::Symbol(Symbol::Dot)) = d.peek() {
             self.advance(d)?;
             let n = self.parse_name(d)?;
             (n, Some(name))
@@ -426,23 +508,21 @@ impl Parser {
         self.expect_symbol(Symbol::LeftRound, d)?;
 
         let mut args = Vec::new();
-        if let TokenData::Symbol(Symbol::RightRound) = d.tokens[d.ptr].data {
+        if let Some(TokenData::Symbol(Symbol::RightRound)) = d.peek() {
             self.advance(d)?;
             return Ok(SubroutineCall { caller, name, args });
         }
         loop {
             args.push(self.parse_expression(d)?);
-            if let TokenData::Symbol(Symbol::RightRound) = d.tokens[d.ptr].data {
+            if self.expect_separator(Symbol::Comma, Symbol::RightRound, d)? {
                 break;
             }
-            self.expect_symbol(Symbol::Comma, d)?;
         }
-        self.expect_symbol(Symbol::RightRound, d)?;
 
         Ok(SubroutineCall { caller, name, args })
     }
 
-    fn parse_expression(&self, d: &mut ParserData) -> Result<Expression, ParseError> {
+    fn parse_expression(&self, d: &mut ParserData) -> ParseResult<Expression> {
         let init_term = self.parse_term(d)?;
 
         let mut ops = Vec::new();
@@ -454,11 +534,9 @@ impl Parser {
         Ok(Expression { init_term, ops })
     }
 
-    fn is_op(&self, d: &mut ParserData) -> Result<bool, ParseError> {
+    fn is_op(&self, d: &mut ParserData) -> ParseResult<bool> {
         if d.ptr >= d.tokens.len() {
-            return Err(ParseError {
-                message: "Reading tokens after EOF!".to_string(),
-            });
+            return Err(self.unexpected(d, d.ptr, "`;`"));
         }
 
         if let TokenData::Symbol(s) = d.tokens[d.ptr].data {
@@ -479,11 +557,9 @@ impl Parser {
         }
     }
 
-    fn parse_op(&self, d: &mut ParserData) -> Result<Op, ParseError> {
+    fn parse_op(&self, d: &mut ParserData) -> ParseResult<Op> {
         if d.ptr >= d.tokens.len() {
-            return Err(ParseError {
-                message: "Reading tokens after EOF!".to_string(),
-            });
+            return_internal!();
         }
 
         let curr = d.ptr;
@@ -500,18 +576,27 @@ impl Parser {
         }
     }
 
-    fn parse_term(&self, d: &mut ParserData) -> Result<usize, ParseError> {
+    fn parse_term(&self, d: &mut ParserData) -> ParseResult<usize> {
+        if d.ptr >= d.tokens.len() {
+            return Err(self.unexpected(d, d.ptr, "an expression"));
+        }
+        let start = d.ptr;
         self.advance(d)?;
         let curr_token = &d.tokens[d.ptr - 1];
         match curr_token.data {
             TokenData::Keyword(kw) => {
                 if let KeywordConstant::Unknown = kw.into() {
-                    return Err(ParseError {
-                        message: format!(
-                            "Unexpected keyword {:?} in term at {}:{}",
-                            kw, curr_token.file, curr_token.line
-                        ),
-                    });
+                    let e = Diagnostic::error(
+                        INVALID_TERM,
+                        format!("expected an expression, found keyword `{}`", kw),
+                        curr_token.file,
+                        curr_token.span,
+                    )
+                    .with_label("not allowed in an expression")
+                    .into();
+                    // Leave the token for error recovery
+                    self.revert(d)?;
+                    return Err(e);
                 } else {
                     d.terms.push(Term::KeywordConstant(kw.into()));
                 }
@@ -529,19 +614,23 @@ impl Parser {
                         d.terms.push(Term::BracketExpression(expr));
                     }
                     _ => {
-                        return Err(ParseError {
-                            message: format!(
-                                "Unexpected symbol {:?} in expression at {}:{}",
-                                s, curr_token.file, curr_token.line
-                            ),
-                        })
+                        let e = Diagnostic::error(
+                            INVALID_TERM,
+                            format!("expected an expression, found `{}`", s),
+                            curr_token.file,
+                            curr_token.span,
+                        )
+                        .with_label("expected an expression")
+                        .into();
+                        self.revert(d)?;
+                        return Err(e);
                     }
                 };
             }
             TokenData::Int(i) => {
                 d.terms.push(Term::Int(i));
             }
-            TokenData::String(_s) => {
+            TokenData::String(_) => {
                 d.terms.push(Term::String(d.ptr - 1));
             }
             TokenData::Identifier(_id) => {
@@ -567,16 +656,20 @@ impl Parser {
                         }
                     }
                 } else {
-                    self.revert(d)?;
                     d.terms.push(Term::VarName(id));
                 }
             }
         }
 
+        // Sub-terms were pushed before this one, so the spans
+        // stay in step with the terms
+        let span = d.tokens[start].span.to(&d.tokens[d.ptr - 1].span);
+        d.term_spans.push(span);
+
         Ok(d.terms.len() - 1)
     }
 
-    fn parse_name(&self, d: &mut ParserData) -> Result<IdentifierId, ParseError> {
+    fn parse_name(&self, d: &mut ParserData) -> ParseResult<IdentifierId> {
         self.expect(TokenKind::Identifier, d)?;
         if let TokenData::Identifier(_id) = d.tokens[d.ptr - 1].data {
             Ok(d.ptr - 1)
@@ -585,199 +678,193 @@ impl Parser {
         }
     }
 
-    // Token iteration:
-    fn expect(&self, kind: TokenKind, d: &mut ParserData) -> Result<(), ParseError> {
-        if d.ptr >= d.tokens.len() {
-            return Err(ParseError {
-                message: format!("Expected {:?}, after reaching end of file", kind),
-            });
+    // Just after the last token, for errors at the end of the file
+    fn eof_span(&self, d: &ParserData) -> Span {
+        match d.tokens.last() {
+            Some(tok) => {
+                let len = self.source[tok.span.start..tok.span.end].chars().count();
+                Span {
+                    start: tok.span.end,
+                    end: tok.span.end,
+                    line: tok.span.line,
+                    col: tok.span.col + len,
+                }
+            }
+            None => Span {
+                line: 1,
+                col: 1,
+                ..Default::default()
+            },
         }
+    }
 
-        let curr = d.ptr;
-        d.ptr += 1;
+    // The innermost bracket closed by `close` still open before `idx`
+    fn unclosed(&self, d: &ParserData, idx: usize, close: Symbol) -> Option<Span> {
+        let open = match close {
+            Symbol::RightRound => Symbol::LeftRound,
+            Symbol::RightSquare => Symbol::LeftSquare,
+            Symbol::RightCurly => Symbol::LeftCurly,
+            _ => return None,
+        };
 
-        let tk: TokenKind = (&d.tokens[curr].data).into();
-        if tk != kind {
-            return Err(ParseError {
-                message: format!(
-                    "Expected {:?}, but got {:?} at {}:{}",
-                    kind, d.tokens[curr].data, d.tokens[curr].file, d.tokens[curr].line
-                ),
-            });
+        let mut depth = 0;
+        for tok in d.tokens[..idx.min(d.tokens.len())].iter().rev() {
+            match tok.data {
+                TokenData::Symbol(s) if s == close => depth += 1,
+                TokenData::Symbol(s) if s == open => {
+                    if depth == 0 {
+                        return Some(tok.span);
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
         }
 
-        Ok(())
+        None
     }
 
-    fn expect_any(
-        &self,
-        kinds: Vec<TokenKind>,
-        d: &mut ParserData,
-    ) -> Result<TokenKind, ParseError> {
-        assert!(!kinds.is_empty());
-
-        if d.ptr >= d.tokens.len() {
-            return Err(ParseError {
-                message: format!("Expected one of {:?}, after reaching end of file", kinds),
-            });
+    // Error for the token at `idx`, or the end of the file, when
+    // `expected` should have been there
+    fn unexpected(&self, d: &ParserData, idx: usize, expected: &str) -> Box<Diagnostic> {
+        match d.tokens.get(idx) {
+            Some(tok) => Diagnostic::error(
+                UNEXPECTED_TOKEN,
+                format!("expected {}, found {}", expected, tok.data),
+                tok.file,
+                tok.span,
+            ),
+            None => Diagnostic::error(
+                UNEXPECTED_EOF,
+                format!("expected {}, found end of file", expected),
+                &self.path,
+                self.eof_span(d),
+            ),
         }
+        .with_label(format!("expected {}", expected))
+        .into()
+    }
 
+    // Token iteration:
+    fn expect(&self, kind: TokenKind, d: &mut ParserData) -> ParseResult<()> {
         let curr = d.ptr;
-        d.ptr += 1;
-
-        let tk: TokenKind = (&d.tokens[curr].data).into();
-        for kind in kinds.iter() {
-            if tk == *kind {
-                return Ok(tk);
-            }
+        let tk: Option<TokenKind> = d.tokens.get(curr).map(|t| (&t.data).into());
+        if tk != Some(kind) {
+            return Err(self.unexpected(d, curr, &kind.to_string()));
         }
+        d.ptr += 1;
 
-        let tok = &d.tokens[curr];
-        Err(ParseError {
-            message: format!(
-                "Expected one of {:?}, got {:?} in {}:{}",
-                kinds, tk, tok.file, tok.line
-            ),
-        })
+        Ok(())
     }
 
-    fn expect_any_keyword(
-        &self,
-        kws: Vec<Keyword>,
-        d: &mut ParserData,
-    ) -> Result<Keyword, ParseError> {
+    fn expect_any_keyword(&self, kws: Vec<Keyword>, d: &mut ParserData) -> ParseResult<Keyword> {
         if kws.is_empty() {
             return_internal!();
         }
 
-        if d.ptr >= d.tokens.len() {
-            return Err(ParseError {
-                message: format!("Expected one of {:?}, after reaching end of file", kws),
-            });
-        }
-
         let curr = d.ptr;
-        d.ptr += 1;
-
-        if let TokenData::Keyword(k) = d.tokens[curr].data {
-            for kw in kws.iter() {
-                if k == *kw {
-                    return Ok(k);
-                }
+        match d.tokens.get(curr).map(|t| &t.data) {
+            Some(TokenData::Keyword(k)) if kws.contains(k) => {
+                d.ptr += 1;
+                Ok(*k)
+            }
+            _ => {
+                let kws: Vec<String> = kws.iter().map(|k| format!("`{}`", k)).collect();
+                Err(self.unexpected(d, curr, &kws.join(" or ")))
             }
         }
-
-        let tok = &d.tokens[curr];
-        Err(ParseError {
-            message: format!(
-                "Expected any of {:?}, got {:?} in {}:{}",
-                kws, tok.data, tok.file, tok.line
-            ),
-        })
     }
 
-    fn expect_any_symbol(
-        &self,
-        symbols: Vec<Symbol>,
-        d: &mut ParserData,
-    ) -> Result<Symbol, ParseError> {
+    fn expect_any_symbol(&self, symbols: Vec<Symbol>, d: &mut ParserData) -> ParseResult<Symbol> {
         if symbols.is_empty() {
             return_internal!();
         }
 
-        if d.ptr >= d.tokens.len() {
-            return Err(ParseError {
-                message: format!("Expected one of {:?}, after reaching end of file", symbols),
-            });
-        }
-
         let curr = d.ptr;
-        d.ptr += 1;
-
-        if let TokenData::Symbol(s) = d.tokens[curr].data {
-            for sym in symbols.iter() {
-                if s == *sym {
-                    return Ok(s);
-                }
+        match d.tokens.get(curr).map(|t| &t.data) {
+            Some(TokenData::Symbol(s)) if symbols.contains(s) => {
+                d.ptr += 1;
+                Ok(*s)
+            }
+            _ => {
+                let symbols: Vec<String> = symbols.iter().map(|s| format!("`{}`", s)).collect();
+                Err(self.unexpected(d, curr, &symbols.join(" or ")))
             }
         }
-
-        let tok = &d.tokens[curr];
-        Err(ParseError {
-            message: format!(
-                "Expected any of {:?}, got {:?} in {}:{}",
-                symbols, tok.data, tok.file, tok.line
-            ),
-        })
     }
 
-    fn expect_keyword(&self, kw: Keyword, d: &mut ParserData) -> Result<(), ParseError> {
-        if d.ptr >= d.tokens.len() {
-            return Err(ParseError {
-                message: format!("Expected {:?}, after reaching end of file", kw),
-            });
-        }
+    fn expect_keyword(&self, kw: Keyword, d: &mut ParserData) -> ParseResult<()> {
+        self.expect_any_keyword(vec![kw], d)?;
 
-        let curr = d.ptr;
-        d.ptr += 1;
+        Ok(())
+    }
 
-        if let TokenData::Keyword(w) = d.tokens[curr].data {
-            if w != kw {
-                return Err(ParseError {
-                    message: format!(
-                        "Expected {:?}, but got {:?} at {}:{}",
-                        kw, d.tokens[curr].data, d.tokens[curr].file, d.tokens[curr].line
-                    ),
-                });
-            }
-        } else {
-            return Err(ParseError {
-                message: format!(
-                    "Expected {:?}, but got {:?} at {}:{}",
-                    kw, d.tokens[curr].data, d.tokens[curr].file, d.tokens[curr].line
-                ),
+    fn expect_symbol(&self, sym: Symbol, d: &mut ParserData) -> ParseResult<()> {
+        let curr = d.ptr;
+        if let Err(e) = self.expect_any_symbol(vec![sym], d) {
+            return Err(match self.unclosed(d, curr, sym) {
+                Some(open) => e.with_secondary(open, "unclosed delimiter").into(),
+                None => e,
             });
         }
 
         Ok(())
     }
 
-    fn expect_symbol(&self, sym: Symbol, d: &mut ParserData) -> Result<(), ParseError> {
-        if d.ptr >= d.tokens.len() {
-            return Err(ParseError {
-                message: format!("Expected {:?}, after reaching end of file", sym),
-            });
+    ///
+    /// Skip the rest of a construct starting at `start` after an
+    /// error in it, at least one token. Stops before the next
+    /// declaration keyword or an unmatched `}`, and in a
+    /// subroutine body also after a `;` or a block and before a
+    /// statement keyword.
+    ///
+    fn synchronize(&self, d: &mut ParserData, start: usize, in_body: bool) {
+        if d.ptr == start && d.ptr < d.tokens.len() {
+            d.ptr += 1;
+        }
+
+        let mut depth = 0;
+        while let Some(tok) = d.peek() {
+            match *tok {
+                TokenData::Keyword(kw) if is_declaration(kw) => return,
+                TokenData::Keyword(kw) if in_body && depth == 0 && is_statement(kw) => return,
+                TokenData::Symbol(Symbol::Semicolon) if in_body && depth == 0 => {
+                    d.ptr += 1;
+                    return;
+                }
+                TokenData::Symbol(Symbol::LeftCurly) => depth += 1,
+                TokenData::Symbol(Symbol::RightCurly) => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                    if in_body && depth == 0 {
+                        d.ptr += 1;
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            d.ptr += 1;
         }
+    }
 
+    // `sep` between the items of a list or `close` after the
+    // last one, true at the end of the list
+    fn expect_separator(&self, sep: Symbol, close: Symbol, d: &mut ParserData) -> ParseResult<bool> {
         let curr = d.ptr;
-        d.ptr += 1;
-
-        if let TokenData::Symbol(s) = d.tokens[curr].data {
-            if s != sym {
-                return Err(ParseError {
-                    message: format!(
-                        "Expected {:?}, but got {:?} at {}:{}",
-                        sym, d.tokens[curr].data, d.tokens[curr].file, d.tokens[curr].line
-                    ),
-                });
-            }
-        } else {
-            return Err(ParseError {
-                message: format!(
-                    "Expected {:?}, but got {:?} at {}:{}",
-                    sym, d.tokens[curr].data, d.tokens[curr].file, d.tokens[curr].line
-                ),
-            });
+        match self.expect_any_symbol(vec![sep, close], d) {
+            Ok(s) => Ok(s == close),
+            Err(e) => Err(match self.unclosed(d, curr, close) {
+                Some(open) => e.with_secondary(open, "unclosed delimiter").into(),
+                None => e,
+            }),
         }
-
-        Ok(())
     }
 
-    fn advance(&self, d: &mut ParserData) -> Result<(), ParseError> {
+    fn advance(&self, d: &mut ParserData) -> ParseResult<()> {
         if d.ptr >= d.tokens.len() {
-            return Err(ParseError {
-                message: "Advance tokens after EOF.".to_string(),
-            });
+            return Err(self.unexpected(d, d.ptr, "more tokens"));
         }
 
         d.ptr += 1;
@@ -785,11 +872,9 @@ impl Parser {
         Ok(())
     }
 
-    fn revert(&self, d: &mut ParserData) -> Result<(), ParseError> {
+    fn revert(&self, d: &mut ParserData) -> ParseResult<()> {
         if d.ptr == 0 {
-            return Err(ParseError {
-                message: "Revert first token".to_string(),
-            });
+            return_internal!();
         }
 
         d.ptr -= 1;
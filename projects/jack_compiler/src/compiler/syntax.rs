@@ -1,4 +1,4 @@
-use super::tokens::{Keyword, Symbol, Token, TokenData};
+use super::tokens::{Keyword, Span, Symbol, Token, TokenData};
 
 pub(crate) type TermId = usize;
 pub(crate) type IdentifierId = usize;
@@ -19,7 +19,7 @@ impl ToString for ClassVarKind {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum SubroutineKind {
     Constructor,
     Function,
@@ -111,6 +111,9 @@ pub(crate) enum Statement {
     While(WhileStmt),
     Do(DoStmt),
     Return(ReturnStmt),
+    /// A statement with syntax errors, skipped up to where
+    /// parsing could go on
+    Error,
 }
 
 #[derive(Debug)]
@@ -141,6 +144,8 @@ pub(crate) struct DoStmt {
 #[derive(Debug)]
 pub(crate) struct ReturnStmt {
     pub(crate) ret_val: Option<Expression>,
+    /// The `return` keyword
+    pub(crate) span: Span,
 }
 
 #[derive(Debug)]
@@ -300,6 +305,8 @@ pub(crate) struct ClassNode {
 pub struct SyntaxTree<'a> {
     pub(crate) filename: String,
     pub(crate) terms: Vec<Term>,
+    /// Where each of `terms` is in the source
+    pub(crate) term_spans: Vec<Span>,
     pub(crate) root: ClassNode,
     pub(crate) tokens: Vec<Token<'a>>,
 }
@@ -322,7 +329,7 @@ impl<'a> SyntaxTree<'a> {
             return "this";
         }
 
-        match self.tokens[id].data {
+        match &self.tokens[id].data {
             TokenData::String(s) => s,
             TokenData::Identifier(s) => s,
             _ => panic!(
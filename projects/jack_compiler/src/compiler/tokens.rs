@@ -12,11 +12,16 @@
     integerConstant: a decimal number in the range 0 ... 32767
     StringConstant: '"' a sequence of Unicode characters,
     not including double quote or newline '"'
+    (the escapes \" \\ and \n stand for a double quote,
+    a backslash and Jack's newline)
 
     identifier: a sequence of letters, digits, and
     underscore ( '_' ) not starting with a digit.
 */
 
+use std::borrow::Cow;
+use std::fmt::Display;
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub(crate) enum Keyword {
     Class,
@@ -42,6 +47,35 @@ pub(crate) enum Keyword {
     Return,
 }
 
+impl Display for Keyword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kw = match self {
+            Keyword::Class => "class",
+            Keyword::Constructor => "constructor",
+            Keyword::Function => "function",
+            Keyword::Method => "method",
+            Keyword::Field => "field",
+            Keyword::Static => "static",
+            Keyword::Var => "var",
+            Keyword::Int => "int",
+            Keyword::Char => "char",
+            Keyword::Boolean => "boolean",
+            Keyword::Void => "void",
+            Keyword::True => "true",
+            Keyword::False => "false",
+            Keyword::Null => "null",
+            Keyword::This => "this",
+            Keyword::Let => "let",
+            Keyword::Do => "do",
+            Keyword::If => "if",
+            Keyword::Else => "else",
+            Keyword::While => "while",
+            Keyword::Return => "return",
+        };
+        write!(f, "{}", kw)
+    }
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub(crate) enum Symbol {
     RightCurly,
@@ -65,6 +99,33 @@ pub(crate) enum Symbol {
     Greater,
 }
 
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sym = match self {
+            Symbol::RightCurly => '}',
+            Symbol::LeftCurly => '{',
+            Symbol::RightRound => ')',
+            Symbol::LeftRound => '(',
+            Symbol::RightSquare => ']',
+            Symbol::LeftSquare => '[',
+            Symbol::Dot => '.',
+            Symbol::Comma => ',',
+            Symbol::Semicolon => ';',
+            Symbol::Plus => '+',
+            Symbol::Minus => '-',
+            Symbol::Multiply => '*',
+            Symbol::Divide => '/',
+            Symbol::And => '&',
+            Symbol::Or => '|',
+            Symbol::Equal => '=',
+            Symbol::Not => '~',
+            Symbol::Less => '<',
+            Symbol::Greater => '>',
+        };
+        write!(f, "{}", sym)
+    }
+}
+
 pub(crate) type Identifier<'a> = &'a str;
 
 #[derive(Debug, PartialEq)]
@@ -72,10 +133,24 @@ pub(crate) enum TokenData<'a> {
     Keyword(Keyword),
     Symbol(Symbol),
     Int(i32),
-    String(&'a str),
+    /// Borrowed from the source unless it contains escapes.
+    String(Cow<'a, str>),
     Identifier(Identifier<'a>),
 }
 
+/// How a token is named in diagnostics, e.g. "keyword `let`".
+impl Display for TokenData<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenData::Keyword(kw) => write!(f, "keyword `{}`", kw),
+            TokenData::Symbol(sym) => write!(f, "`{}`", sym),
+            TokenData::Int(i) => write!(f, "integer constant `{}`", i),
+            TokenData::String(s) => write!(f, "string constant {:?}", s),
+            TokenData::Identifier(id) => write!(f, "identifier `{}`", id),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub(crate) enum TokenKind {
     Keyword,
@@ -85,6 +160,19 @@ pub(crate) enum TokenKind {
     Identifier,
 }
 
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            TokenKind::Keyword => "keyword",
+            TokenKind::Symbol => "symbol",
+            TokenKind::Int => "integer constant",
+            TokenKind::String => "string constant",
+            TokenKind::Identifier => "identifier",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
 impl From<&TokenData<'_>> for TokenKind {
     fn from(value: &TokenData<'_>) -> Self {
         match value {
@@ -97,9 +185,32 @@ impl From<&TokenData<'_>> for TokenKind {
     }
 }
 
+/// Where a token is in its file: the bytes `start..end` of the
+/// source, and the line and column of `start`, both from 1.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub(crate) struct Span {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) line: usize,
+    pub(crate) col: usize,
+}
+
+impl Span {
+    /// From the start of `self` to the end of `other`.
+    pub(crate) fn to(&self, other: &Span) -> Span {
+        Span { end: other.end, ..*self }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Token<'a> {
     pub(crate) data: TokenData<'a>,
     pub(crate) file: &'a str,
-    pub(crate) line: usize,
+    pub(crate) span: Span,
 }
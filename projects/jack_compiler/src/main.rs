@@ -1,19 +1,24 @@
 use std::error::Error;
+use std::fs;
 use std::{path::Path, process::exit};
 
 mod compiler;
 mod utils;
 
 use compiler::analyzer::Analyzer;
+use compiler::analyzers::calls::{CallChecker, Program};
+use compiler::analyzers::semantic::SemanticAnalyzer;
+use compiler::analyzers::type_checker::TypeChecker;
 use compiler::analyzers::vm_generator::VMGenerator;
 use compiler::analyzers::xml::XMLAnalyzer;
+use compiler::diagnostics::{Diagnostic, Severity};
 use compiler::parser::Parser;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 2 {
         println!(
-            "Usage: {} <input_file|dir> [--xml <xml_output_folder>] [--vm <vm_output_folder>]",
+            "Usage: {} <input_file|dir> [--xml <xml_output_folder>] [--vm <vm_output_folder>] [--native-math] [--strict-types] [--error-format=human|json]",
             args[0]
         );
         exit(1);
@@ -21,6 +26,9 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut vm_out = None;
     let mut xml_out = None;
+    let mut native_math = false;
+    let mut json_errors = false;
+    let mut strict_types = false;
     for (i, arg) in args.iter().enumerate() {
         if arg == "--xml" {
             xml_out = Some(args[i + 1].clone());
@@ -29,35 +37,107 @@ fn main() -> Result<(), Box<dyn Error>> {
         if arg == "--vm" {
             vm_out = Some(args[i + 1].clone());
         }
+
+        if arg == "--native-math" {
+            native_math = true;
+        }
+
+        if arg == "--strict-types" {
+            strict_types = true;
+        }
+
+        if let Some(format) = arg.strip_prefix("--error-format=") {
+            json_errors = match format {
+                "human" => false,
+                "json" => true,
+                _ => {
+                    println!("Unknown error format: {}", format);
+                    exit(1);
+                }
+            };
+        }
     }
 
     let input_path = Path::new(&args[1]);
     let mut in_files = Vec::new();
     utils::get_files(input_path, &mut in_files)?;
 
-    for path in in_files {
-        let filename = path.file_stem().unwrap().to_str().unwrap().to_string();
-        let mut parser = Parser::new(path);
-        let parse_res = parser.parse();
-        let tree = if let Ok(t) = parse_res {
-            t
-        } else {
-            println!("Parse error: {}", parse_res.err().unwrap());
+    // All classes are parsed first, calls are checked against the
+    // whole program
+    let parsers: Vec<Parser> = in_files.into_iter().map(Parser::new).collect();
+    let parsed: Vec<_> = parsers.iter().map(|parser| parser.parse()).collect();
+
+    let mut program = Program::new();
+    for (parser, (tree, diagnostics)) in parsers.iter().zip(&parsed) {
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            program.add_incomplete(&parser.filename, tree.as_ref());
+        } else if let Some(tree) = tree {
+            program.add(tree);
+        }
+    }
+
+    let mut failed = false;
+    for (parser, (tree, diagnostics)) in parsers.iter().zip(&parsed) {
+        let filename = parser.filename.clone();
+        failed |= print_diagnostics(diagnostics, parser.source(), json_errors);
+        // A tree with errors is only partial
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            continue;
+        }
+        let Some(tree) = tree else {
             continue;
         };
 
         if let Some(dir) = &xml_out {
             let analyzer = XMLAnalyzer::new(dir);
-            analyzer.analyze(&tree);
+            analyzer.analyze(tree);
         }
 
         if let Some(dir) = &vm_out {
-            let analyzer = VMGenerator::new(dir, &filename);
-            if let Err(e) = analyzer.analyze(&tree) {
-                println!("VMGenerator error: {e}");
+            // XML is written even so, the syntax analyzer tests use
+            // undeclared names
+            let mut diagnostics = SemanticAnalyzer.analyze(tree);
+            if diagnostics.is_empty() {
+                diagnostics = TypeChecker {
+                    program: &program,
+                    strict: strict_types,
+                }.analyze(tree);
+                diagnostics.extend(CallChecker { program: &program }.analyze(tree));
+            }
+            failed |= print_diagnostics(&diagnostics, parser.source(), json_errors);
+            if !diagnostics.is_empty() {
+                continue;
+            }
+
+            match (VMGenerator { native_math }).analyze(tree) {
+                Ok(module) => {
+                    let path = Path::new(dir).join(filename + ".vm");
+                    fs::write(path, module.to_string())?;
+                }
+                Err(e) => {
+                    println!("VMGenerator error: {e}");
+                    failed = true;
+                }
             }
         }
     }
 
+    if failed {
+        exit(1);
+    }
+
     Ok(())
 }
+
+// Print `diagnostics`, return whether there was an error
+fn print_diagnostics(diagnostics: &[Diagnostic], source: &str, json: bool) -> bool {
+    for diagnostic in diagnostics {
+        if json {
+            println!("{}", diagnostic.to_json());
+        } else {
+            println!("{}", diagnostic.render(source));
+        }
+    }
+
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
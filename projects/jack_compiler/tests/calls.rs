@@ -0,0 +1,166 @@
+mod common;
+
+const POINT: &str = "class Point {
+  field int x, y;
+
+  constructor Point new(int ax, int ay) {
+    let x = ax;
+    let y = ay;
+    return this;
+  }
+
+  method int getX() {
+    return x;
+  }
+
+  function Point origin() {
+    return Point.new(0, 0);
+  }
+}
+";
+
+#[test]
+fn accepts_calls_across_classes() {
+    let main = "class Main {
+  function void main() {
+    var Point p;
+    var String s;
+    let p = Point.new(1, 2);
+    let p = Point.origin();
+    do Output.printInt(p.getX());
+    let s = String.new(4);
+    do s.appendChar(65);
+    do Output.printString(s);
+    do Main.helper();
+    return;
+  }
+
+  function void helper() {
+    do Sys.wait(Math.max(1, 2));
+    return;
+  }
+}
+";
+    let common::Output { stdout, vm, .. } = common::compile_files("valid", &[("Main.jack", main), ("Point.jack", POINT)], &[]);
+
+    assert_eq!(stdout, "");
+    assert_eq!(vm.keys().collect::<Vec<_>>(), ["Main.vm", "Point.vm"]);
+}
+
+#[test]
+fn reports_call_errors() {
+    let main = "class Main {
+  function void main() {
+    var Point p;
+    var int n;
+    let p = Line.new();
+    let p = Point.new(1);
+    do p.move(1, 1);
+    do Point.getX();
+    let p = p.origin();
+    do n.getX();
+    do Output.printInt(1, 2);
+    return;
+  }
+
+  method void helper() {
+    do main();
+    return;
+  }
+}
+";
+    let common::Output { stdout, vm, .. } = common::compile_files("errors", &[("Main.jack", main), ("Point.jack", POINT)], &[]);
+
+    assert_eq!(vm.keys().collect::<Vec<_>>(), ["Point.vm"]);
+    assert_eq!(
+        common::errors(&stdout),
+        [
+            "error[E0400]: cannot find class `Line` at 5:13",
+            "error[E0403]: `Point.new` takes 2 arguments but 1 was given at 6:19",
+            "error[E0401]: no subroutine `move` in class `Point` at 7:10",
+            "error[E0402]: method `Point.getX` called on its class at 8:14",
+            "error[E0402]: function `Point.origin` called on an object at 9:15",
+            "error[E0404]: cannot call `getX` on `n`, which is not an object at 10:8",
+            "error[E0403]: `Output.printInt` takes 1 argument but 2 were given at 11:15",
+            "error[E0402]: function `Main.main` called on an object at 16:8",
+        ],
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn reports_unknown_types_at_their_declaration() {
+    let main = "class Main {
+  field Shape shape;
+
+  method Shape get(Shape s) {
+    var Shape a, b;
+    let a = s;
+    let b = a + 1;
+    do a.draw();
+    return Shape.new();
+  }
+}
+";
+    let common::Output { stdout, vm, .. } = common::compile_files("types", &[("Main.jack", main)], &[]);
+
+    assert!(vm.is_empty());
+    assert_eq!(
+        common::errors(&stdout),
+        [
+            "error[E0400]: cannot find class `Shape` at 2:9",
+            "error[E0400]: cannot find class `Shape` at 4:10",
+            "error[E0400]: cannot find class `Shape` at 4:20",
+            "error[E0400]: cannot find class `Shape` at 5:9",
+            "error[E0400]: cannot find class `Shape` at 9:12",
+        ],
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn checks_calls_to_classes_with_syntax_errors() {
+    // A missing `;`, a broken declaration and a missing class header
+    let point = "class Point {
+  field int x;
+
+  constructor Point new(int ax) {
+    let x = ax
+    return this;
+  }
+
+  method int getX() {
+    return x;
+  }
+
+  method void move(int dx {
+    return;
+  }
+}
+";
+    let line = "clas Line {
+}
+";
+    let main = "class Main {
+  function void main() {
+    var Point p;
+    var Line l;
+    let p = Point.new(1);
+    do p.move(1);
+    do Output.printInt(p.getX());
+    let l = Line.new();
+    return;
+  }
+}
+";
+    let common::Output { stdout, vm, .. } =
+        common::compile_files("incomplete", &[("Main.jack", main), ("Point.jack", point), ("Line.jack", line)], &[]);
+
+    // Only the syntax errors, Main compiles
+    assert_eq!(vm.keys().collect::<Vec<_>>(), ["Main.vm"], "{}", stdout);
+    let errors = common::errors(&stdout);
+    assert_eq!(errors.len(), 3, "{}", stdout);
+    assert!(errors.iter().all(|e| e.starts_with("error[E01")), "{}", stdout);
+}
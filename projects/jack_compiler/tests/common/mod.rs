@@ -0,0 +1,95 @@
+// Shared by the test binaries, each of which uses only a part
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// What the compiler printed and wrote.
+pub struct Output {
+    pub stdout: String,
+    /// VM code by file name
+    pub vm: BTreeMap<String, String>,
+    /// Names of the XML files
+    pub xml: Vec<String>,
+}
+
+fn file_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_str().unwrap().to_string())
+        .collect();
+    names.sort();
+
+    names
+}
+
+// Compile the classes in `src_dir` to VM code and XML with `flags`
+pub fn compile_dir(name: &str, src_dir: &Path, flags: &[&str]) -> Output {
+    let dir = std::env::temp_dir().join(format!("jack_compiler_{}_{}", name, std::process::id()));
+    let vm_dir = dir.join("vm");
+    let xml_dir = dir.join("xml");
+    fs::create_dir_all(&vm_dir).unwrap();
+    fs::create_dir_all(&xml_dir).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_jack_compiler"))
+        .arg(src_dir)
+        .arg("--vm")
+        .arg(&vm_dir)
+        .arg("--xml")
+        .arg(&xml_dir)
+        .args(flags)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    // Errors, and only errors, fail the compilation
+    let failed = stdout.lines().any(|l| l.starts_with("error") || l.starts_with("{\"severity\":\"error\""));
+    assert_eq!(output.status.success(), !failed, "{}", stdout);
+
+    let vm = file_names(&vm_dir)
+        .into_iter()
+        .map(|f| {
+            let code = fs::read_to_string(vm_dir.join(&f)).unwrap();
+            (f, code)
+        })
+        .collect();
+    let xml = file_names(&xml_dir);
+    fs::remove_dir_all(&dir).unwrap();
+
+    Output { stdout, vm, xml }
+}
+
+// Compile the classes of `files` as one program
+pub fn compile_files(name: &str, files: &[(&str, &str)], flags: &[&str]) -> Output {
+    let src_dir = std::env::temp_dir().join(format!("jack_compiler_{}_src_{}", name, std::process::id()));
+    fs::create_dir_all(&src_dir).unwrap();
+    for (file, source) in files {
+        fs::write(src_dir.join(file), source).unwrap();
+    }
+
+    let output = compile_dir(name, &src_dir, flags);
+    fs::remove_dir_all(&src_dir).unwrap();
+
+    output
+}
+
+// Compile `Main.jack` with `source`
+pub fn compile(name: &str, source: &str, flags: &[&str]) -> Output {
+    compile_files(name, &[("Main.jack", source)], flags)
+}
+
+// The header and location of each error
+pub fn errors(stdout: &str) -> Vec<String> {
+    let lines: Vec<&str> = stdout.lines().collect();
+    lines
+        .windows(2)
+        .filter(|w| w[0].starts_with("error"))
+        .map(|w| {
+            let mut pos = w[1].rsplitn(3, ':');
+            let col = pos.next().unwrap();
+            let line = pos.next().unwrap();
+            format!("{} at {}:{}", w[0], line, col)
+        })
+        .collect()
+}
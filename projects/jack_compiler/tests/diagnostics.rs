@@ -0,0 +1,144 @@
+mod common;
+
+const UNCLOSED: &str = "class Main {
+  function void main() {
+    do Output.printInt(Math.max(1, 2);
+    return;
+  }
+}
+";
+
+#[test]
+fn renders_source_excerpts() {
+    let common::Output { stdout, vm, .. } = common::compile("excerpt", UNCLOSED, &[]);
+
+    assert!(vm.is_empty());
+    let expected = "\
+error[E0100]: expected `,` or `)`, found `;`
+ --> ";
+    assert!(stdout.starts_with(expected), "{}", stdout);
+    let excerpt = "Main.jack:3:38
+  |
+3 |     do Output.printInt(Math.max(1, 2);
+  |                       - unclosed delimiter
+  |                                      ^ expected `,` or `)`
+";
+    assert!(stdout.contains(excerpt), "{}", stdout);
+}
+
+#[test]
+fn reports_json() {
+    let common::Output { stdout, .. } = common::compile("json", UNCLOSED, &["--error-format=json"]);
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "{}", stdout);
+    assert!(lines[0].starts_with(r#"{"severity":"error","code":"E0100","message":"expected `,` or `)`, found `;`","file":"#), "{}", stdout);
+    assert!(lines[0].contains(r#""spans":[{"start":75,"end":76,"line":3,"column":38,"primary":true,"label":"expected `,` or `)`"},"#), "{}", stdout);
+    assert!(lines[0].ends_with(r#"{"start":60,"end":61,"line":3,"column":23,"primary":false,"label":"unclosed delimiter"}]}"#), "{}", stdout);
+}
+
+#[test]
+fn warns_about_unreachable_statements() {
+    let source = "class Main {
+  function int main() {
+    var int x;
+    return 1;
+    let x = 2;
+    let x = 3;
+  }
+}
+";
+    let common::Output { stdout, vm, .. } = common::compile("unreachable", source, &[]);
+
+    // Only warned about once, and the code is still generated
+    assert!(!vm.is_empty());
+    assert_eq!(stdout.matches("warning[W0001]: unreachable statement").count(), 1, "{}", stdout);
+    let excerpt = "Main.jack:5:5
+  |
+4 |     return 1;
+  |     ------ any code following this return is unreachable
+5 |     let x = 2;
+  |     ^^^ unreachable statement
+";
+    assert!(stdout.contains(excerpt), "{}", stdout);
+}
+
+#[test]
+fn reports_end_of_file() {
+    let common::Output { stdout, vm, .. } = common::compile("eof", "class Main {\n  function void main() {\n    return;\n", &[]);
+
+    assert!(vm.is_empty());
+    assert!(stdout.contains("error[E0101]: expected "), "{}", stdout);
+    assert!(stdout.contains(", found end of file\n"), "{}", stdout);
+    assert!(stdout.contains("Main.jack:3:12\n"), "{}", stdout);
+}
+
+#[test]
+fn recovers_from_syntax_errors() {
+    let source = "class Main {
+  field int a b;
+  static int c;
+
+  function void main() {
+    var int x;
+    let x = 1 +;
+    if (x {
+      let x = 2;
+    }
+    x = 3;
+    while (x) { let x = x - 1 }
+    return;
+  }
+
+  method int broken( {
+    return 1;
+  }
+
+  function void last() {
+    let x = ;
+    return;
+  }
+}
+";
+    let common::Output { stdout, vm, .. } = common::compile("recovery", source, &[]);
+
+    assert!(vm.is_empty());
+    let errors: Vec<&str> = stdout.lines().filter(|l| l.starts_with("error")).collect();
+    assert_eq!(
+        errors,
+        [
+            "error[E0100]: expected `,` or `;`, found identifier `b`",
+            "error[E0103]: expected an expression, found `;`",
+            "error[E0100]: expected `)`, found `{`",
+            "error[E0100]: expected a statement, found identifier `x`",
+            "error[E0100]: expected `;`, found `}`",
+            "error[E0100]: expected a type, found `{`",
+            "error[E0103]: expected an expression, found `;`",
+        ],
+        "{}",
+        stdout
+    );
+    for pos in ["2:15", "7:16", "8:11", "11:5", "12:31", "16:22", "21:13"] {
+        assert!(stdout.contains(&format!("Main.jack:{}\n", pos)), "{}: {}", pos, stdout);
+    }
+}
+
+#[test]
+fn reports_a_missing_brace_once() {
+    let source = "class Main {
+  function void main() {
+    if (true) {
+      do Output.printInt(1);
+    return;
+  }
+
+  function void other() {
+    return;
+  }
+}
+";
+    let common::Output { stdout, .. } = common::compile("brace", source, &[]);
+
+    assert_eq!(stdout.matches("error").count(), 1, "{}", stdout);
+    assert!(stdout.contains("error[E0100]: expected `}`, found keyword `function`"), "{}", stdout);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+// The characters appended to the string constants of `vm`
+fn string_chars(vm: &str) -> Vec<i32> {
+    let lines: Vec<&str> = vm.lines().collect();
+    lines
+        .windows(2)
+        .filter(|w| w[1] == "call String.appendChar 2")
+        .map(|w| w[0].trim_start_matches("push constant ").parse().unwrap())
+        .collect()
+}
+
+#[test]
+fn skips_all_comment_forms() {
+    let source = "\
+/** Doc comment
+ * over several lines */
+class Main { /* inline */ function void main() { // trailing
+    var int /* mid-line */ x;
+    /** one-line doc */ let x = 1 /* before ; */;
+    do Output.printString(\"a // b /* c */\");
+    return;
+} }
+";
+    let common::Output { stdout, vm, .. } = common::compile("comments", source, &[]);
+    let vm = vm.get("Main.vm").unwrap_or_else(|| panic!("{}", stdout));
+
+    assert!(vm.contains("push constant 1\npop local 0"), "{}", vm);
+    let expected: Vec<i32> = "a // b /* c */".chars().map(|c| c as i32).collect();
+    assert_eq!(string_chars(vm), expected);
+}
+
+#[test]
+fn unescapes_strings() {
+    let source = "class Main { function void main() {
+    do Output.printString(\"q\\\"b\\\\n\\n[\\]\");
+    return;
+} }
+";
+    let common::Output { stdout, vm, .. } = common::compile("escapes", source, &[]);
+    let vm = vm.get("Main.vm").unwrap_or_else(|| panic!("{}", stdout));
+
+    // \" \\ and \n are escapes, \] is kept as it is
+    let expected = ['q' as i32, '"' as i32, 'b' as i32, '\\' as i32, 'n' as i32, 128, '[' as i32, '\\' as i32, ']' as i32];
+    assert_eq!(string_chars(vm), expected);
+    assert!(vm.contains(&format!("push constant {}\ncall String.new 1", expected.len())), "{}", vm);
+}
+
+#[test]
+fn reports_lexical_errors_with_positions() {
+    let source = "class Main {
+  function void main() {
+    let x = 40000 # 2;
+    do Output.printString(\"open);
+    return;
+  }
+} /* never closed
+";
+    let common::Output { stdout, vm, .. } = common::compile("errors", source, &[]);
+
+    assert!(vm.is_empty());
+    assert!(stdout.contains("error[E0004]: integer constant 40000 is larger than 32767\n"), "{}", stdout);
+    assert!(stdout.contains("Main.jack:3:13\n"), "{}", stdout);
+    assert!(stdout.contains("error[E0001]: invalid character '#'\n"), "{}", stdout);
+    assert!(stdout.contains("Main.jack:3:19\n"), "{}", stdout);
+    assert!(stdout.contains("error[E0002]: unterminated string constant\n"), "{}", stdout);
+    assert!(stdout.contains("Main.jack:4:27\n"), "{}", stdout);
+    assert!(stdout.contains("error[E0003]: unterminated block comment\n"), "{}", stdout);
+    assert!(stdout.contains("Main.jack:7:3\n"), "{}", stdout);
+}
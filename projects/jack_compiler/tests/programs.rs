@@ -0,0 +1,45 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The programs of projects 09 and 11
+fn programs() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let mut dirs = Vec::new();
+    for project in ["09", "11"] {
+        for dir in fs::read_dir(root.join(project)).unwrap() {
+            let dir = dir.unwrap().path();
+            if dir.is_dir() {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs.sort();
+
+    dirs
+}
+
+#[test]
+fn compiles_the_book_programs() {
+    let dirs = programs();
+    assert!(dirs.len() > 10);
+    for dir in dirs.iter().filter(|d| !d.ends_with("jack_project")) {
+        let name = dir.file_name().unwrap().to_str().unwrap();
+        let output = common::compile_dir(name, dir, &[]);
+        assert_eq!(output.stdout, "", "{}", dir.display());
+    }
+}
+
+#[test]
+fn reports_an_unknown_type_once_per_declaration() {
+    // Declares values as `bool`, which is no class
+    let dir = programs().into_iter().find(|d| d.ends_with("jack_project")).unwrap();
+    let common::Output { stdout, vm, .. } = common::compile_dir("jack_project", &dir, &[]);
+
+    assert!(!vm.contains_key("Map.vm") && !vm.contains_key("Game.vm"));
+    let headers: Vec<&str> = stdout.lines().filter(|l| l.starts_with("error") || l.starts_with("warning")).collect();
+    assert_eq!(headers, ["error[E0400]: cannot find class `bool`"; 9], "{}", stdout);
+    assert_eq!(stdout.matches("Map.jack:").count(), 6, "{}", stdout);
+    assert_eq!(stdout.matches("Game.jack:").count(), 3, "{}", stdout);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+#[test]
+fn reports_name_errors() {
+    let source = "class Main {
+  field int size;
+  static int count, count;
+
+  function void main() {
+    var int x, x;
+    let y = 1;
+    let x = z + size;
+    do draw();
+    do size.grow();
+    do Output.printInt(this);
+    return;
+  }
+
+  method void draw() {
+    let size = count;
+    do draw();
+    return;
+  }
+}
+";
+    let common::Output { stdout, vm, xml } = common::compile("errors", source, &[]);
+
+    assert!(vm.is_empty());
+    assert!(!xml.is_empty());
+    let errors: Vec<&str> = stdout.lines().filter(|l| l.starts_with("error")).collect();
+    assert_eq!(
+        errors,
+        [
+            "error[E0201]: the name `count` is declared more than once",
+            "error[E0201]: the name `x` is declared more than once",
+            "error[E0200]: cannot assign to undeclared variable `y`",
+            "error[E0200]: cannot find variable `z` in this scope",
+            "error[E0203]: cannot access field `size` in a function",
+            "error[E0204]: cannot call method `draw` without an object in a function",
+            "error[E0203]: cannot access field `size` in a function",
+            "error[E0202]: `this` cannot be used in a function",
+        ],
+        "{}",
+        stdout
+    );
+    for pos in ["3:21", "6:16", "7:9", "8:13", "8:17", "9:8", "10:8", "11:24"] {
+        assert!(stdout.contains(&format!("Main.jack:{}\n", pos)), "{}: {}", pos, stdout);
+    }
+    assert!(stdout.contains("  |             ---- field declared here\n"), "{}", stdout);
+}
+
+#[test]
+fn accepts_valid_names() {
+    let source = "class Main {
+  field int size;
+  static Main instance;
+
+  constructor Main new(int size) {
+    let size = size;
+    do grow();
+    let instance = this;
+    return this;
+  }
+
+  method void grow() {
+    var Array a;
+    let a = Array.new(size);
+    let a[0] = size;
+    let size = size + 1;
+    return;
+  }
+
+  function void main() {
+    var Main m;
+    let m = Main.new(1);
+    do m.grow();
+    do instance.grow();
+    return;
+  }
+}
+";
+    let common::Output { stdout, vm, .. } = common::compile("valid", source, &[]);
+
+    assert!(!vm.is_empty(), "{}", stdout);
+    assert_eq!(stdout, "");
+}
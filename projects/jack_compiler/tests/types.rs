@@ -0,0 +1,178 @@
+mod common;
+
+// Jack's weak typing, fine unless strict
+const WEAK: &str = "class Main {
+  function void main() {
+    var int x;
+    var char c;
+    var Array a;
+    var String s;
+    let c = x;
+    let a = 0;
+    let x = a + 1;
+    let s = a;
+    let a = s;
+    let x = s;
+    if (x) {
+      let x = Main.twice(c);
+    }
+    return;
+  }
+
+  function int twice(int n) {
+    return n + n;
+  }
+}
+";
+
+#[test]
+fn lenient_accepts_weak_typing() {
+    let common::Output { stdout, vm, .. } = common::compile("weak", WEAK, &[]);
+
+    assert!(!vm.is_empty(), "{}", stdout);
+    assert_eq!(stdout, "");
+}
+
+#[test]
+fn strict_rejects_weak_typing() {
+    let common::Output { stdout, vm, .. } = common::compile("strict", WEAK, &["--strict-types"]);
+
+    assert!(vm.is_empty());
+    assert_eq!(
+        common::errors(&stdout),
+        [
+            "error[E0300]: mismatched types at 7:13",
+            "error[E0300]: mismatched types at 8:13",
+            "error[E0301]: cannot apply `+` to `Array` and `int` at 9:13",
+            "error[E0300]: mismatched types at 10:13",
+            "error[E0300]: mismatched types at 11:13",
+            "error[E0300]: mismatched types at 12:13",
+            "error[E0300]: mismatched types at 13:9",
+            "error[E0300]: mismatched types at 14:26",
+        ],
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("expected `char`, found `int`"), "{}", stdout);
+    assert!(stdout.contains("expected `boolean`, found `int`"), "{}", stdout);
+}
+
+#[test]
+fn reports_type_errors() {
+    let source = "class Main {
+  function void main() {
+    var int x;
+    var boolean b;
+    var String s;
+    let b = x;
+    let x = 1 + true;
+    while (s) {
+      let x = Main.twice(b);
+    }
+    let b = x[0];
+    do Main.nothing();
+    return 1;
+  }
+
+  function int twice(int n) {
+    if (n > 0) {
+      return;
+    }
+    return n = 2;
+  }
+
+  function void nothing() {
+    return;
+  }
+}
+";
+    let common::Output { stdout, vm, .. } = common::compile("errors", source, &[]);
+
+    assert!(vm.is_empty());
+    assert_eq!(
+        common::errors(&stdout),
+        [
+            "error[E0300]: mismatched types at 6:13",
+            "error[E0301]: cannot apply `+` to `int` and `boolean` at 7:17",
+            "error[E0300]: mismatched types at 8:12",
+            "error[E0300]: mismatched types at 9:26",
+            "error[E0302]: cannot index into a value of type `int` at 11:13",
+            "error[E0303]: `return` with a value in `main`, which returns `void` at 13:12",
+            "error[E0303]: `return` without a value in `twice`, which returns `int` at 18:7",
+            "error[E0300]: mismatched types at 20:12",
+        ],
+        "{}",
+        stdout
+    );
+    let excerpt = "
+16 |   function int twice(int n) {
+   |                          - parameter declared here
+";
+    assert!(stdout.contains(excerpt), "{}", stdout);
+}
+
+#[test]
+fn checks_calls_to_other_classes() {
+    let point = "class Point {
+  field int x;
+
+  constructor Point new(int ax) {
+    let x = ax;
+    return this;
+  }
+
+  method boolean isLeftOf(Point other) {
+    return x < other.getX();
+  }
+
+  method int getX() {
+    return x;
+  }
+}
+";
+    let main = "class Main {
+  function void main() {
+    var Point p;
+    var boolean b;
+    var String s;
+    let p = Point.new(true);
+    let b = p.isLeftOf(p);
+    let b = p.getX();
+    do Output.printString(p);
+    do Output.printInt(s.length());
+    let s = String.new(s);
+    return;
+  }
+}
+";
+    let files = [("Main.jack", main), ("Point.jack", point)];
+    let common::Output { stdout, vm, .. } = common::compile_files("classes", &files, &["--strict-types"]);
+
+    assert_eq!(vm.keys().collect::<Vec<_>>(), ["Point.vm"]);
+    assert_eq!(
+        common::errors(&stdout),
+        [
+            "error[E0300]: mismatched types at 6:23",
+            "error[E0300]: mismatched types at 8:13",
+            "error[E0300]: mismatched types at 9:27",
+            "error[E0300]: mismatched types at 11:24",
+        ],
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("expected `int`, found `boolean`"), "{}", stdout);
+    assert!(stdout.contains("expected `String`, found `Point`"), "{}", stdout);
+
+    // Objects are addresses unless strict, `String.new(s)` passes
+    let common::Output { stdout, .. } = common::compile_files("classes_lenient", &files, &[]);
+    assert_eq!(
+        common::errors(&stdout),
+        [
+            "error[E0300]: mismatched types at 6:23",
+            "error[E0300]: mismatched types at 8:13",
+            "error[E0300]: mismatched types at 9:27",
+        ],
+        "{}",
+        stdout
+    );
+}
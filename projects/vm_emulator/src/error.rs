@@ -0,0 +1,40 @@
+use std::error::Error;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Segment index past the end of the segment,
+    /// or a `this`/`that` address outside of RAM.
+    SegmentOutOfBounds,
+    /// Popping below the working stack of the current function.
+    StackUnderflow,
+    StackOverflow,
+    UnknownFunction,
+    UnknownLabel,
+    /// The step limit of `Vm::run` ran out inside a native call.
+    StepLimit,
+}
+
+///
+/// Error raised while executing, located at the
+/// command that caused it.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}.vm:{}: {}", self.file, self.line, self.message)
+        }
+    }
+}
+
+impl Error for RuntimeError {}
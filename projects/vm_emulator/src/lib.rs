@@ -0,0 +1,15 @@
+//!
+//! Interpreter for Hack VM programs. Runs the parsed
+//! commands directly instead of translated assembly,
+//! using the same RAM layout as the standard mapping.
+//!
+
+mod error;
+mod os;
+mod program;
+mod vm;
+
+pub use error::{ErrorKind, RuntimeError};
+pub use os::is_native;
+pub use program::Program;
+pub use vm::{Frame, Vm, ARG, KBD, LCL, RAM_SIZE, SP, STACK_BASE, TEMP, THAT, THIS};
@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use vm_emulator::{is_native, Vm, ARG, LCL, RAM_SIZE, SP, THAT, THIS};
+
+const DEFAULT_MAX_STEPS: u64 = 100_000_000;
+
+fn get_vm_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    if path.is_file() {
+        if let Some(ext) = path.extension() {
+            if let Some("vm") = ext.to_str() {
+                files.push(path.to_owned());
+            }
+        }
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let path = entry.path();
+        get_vm_files(&path, files)?;
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        println!("Usage: {} <input_file|dir> [--entry=<function>] [--max-steps=<n>] [--set=<addr>=<value>...] [--keys=<text>]", args[0]);
+        exit(1);
+    }
+
+    let mut entry = "Sys.init".to_string();
+    let mut max_steps = DEFAULT_MAX_STEPS;
+    let mut init = Vec::new();
+    let mut keys = String::new();
+    for arg in args.iter().skip(2) {
+        match arg.split_once('=') {
+            Some(("--entry", name)) => entry = name.to_string(),
+            Some(("--max-steps", n)) if n.parse::<u64>().is_ok() => max_steps = n.parse()?,
+            Some(("--keys", text)) => keys.push_str(&text.replace("\\n", "\n")),
+            Some(("--set", cell)) => match cell.split_once('=').map(|(a, v)| (a.parse::<usize>(), v.parse::<i16>())) {
+                Some((Ok(addr), Ok(value))) if addr < RAM_SIZE => init.push((addr, value)),
+                _ => {
+                    println!("Invalid RAM assignment: {}", cell);
+                    exit(1);
+                }
+            },
+            _ => {
+                println!("Unknown option: {}", arg);
+                exit(1);
+            }
+        }
+    }
+
+    let mut in_files = Vec::new();
+    get_vm_files(Path::new(&args[1]), &mut in_files)?;
+    in_files.sort();
+
+    let mut modules = Vec::new();
+    for in_filepath in in_files {
+        let filename = in_filepath.file_stem().unwrap().to_str().unwrap();
+        let source = fs::read_to_string(&in_filepath)?;
+        modules.push(hack_vm::parse(filename, &source)?);
+    }
+
+    // Without the entry function run the commands from the top,
+    // like the project 07 tests. The native Sys.init needs Main.main.
+    let mut vm = Vm::new(&modules);
+    for (addr, value) in init {
+        vm.ram_mut()[addr] = value;
+    }
+    vm.type_text(&keys);
+    let program = vm.program();
+    if program.function(&entry).is_some() || (is_native(&entry) && program.function("Main.main").is_some()) {
+        vm.boot(&entry)?;
+    }
+
+    let result = vm.run(max_steps);
+    let ram = vm.ram();
+    println!(
+        "{} after {} steps: SP={} LCL={} ARG={} THIS={} THAT={}",
+        if vm.is_halted() {
+            "Halted"
+        } else if vm.is_waiting() {
+            "Waiting for keys"
+        } else {
+            "Stopped"
+        },
+        vm.steps(),
+        ram[SP],
+        ram[LCL],
+        ram[ARG],
+        ram[THIS],
+        ram[THAT]
+    );
+
+    if let Some(code) = vm.os_error() {
+        println!("Sys.error({})", code);
+    }
+
+    if let Err(err) = result {
+        println!("Error: {}", err);
+        for frame in vm.frames().iter().rev() {
+            println!("    in {}", frame.function);
+        }
+        exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use super::{sys_error, NativeResult};
+use crate::vm::Vm;
+
+pub fn new(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let size = args[0];
+    if size <= 0 {
+        sys_error(vm, 2)?;
+    }
+
+    vm.invoke("Memory.alloc", &[size])
+}
+
+pub fn dispose(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    vm.invoke("Memory.deAlloc", &[args[0]])?;
+    Ok(0)
+}
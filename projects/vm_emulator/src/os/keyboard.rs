@@ -0,0 +1,63 @@
+// The VM implementation busy-waits on RAM[KBD] for a key to be
+// pressed and released. The native one takes keys from the queue
+// filled by `Vm::type_keys` and blocks while it is empty, leaving
+// the call to be retried once more keys are typed.
+
+use super::{Interrupt, NativeResult, NEWLINE};
+use crate::vm::{Vm, KBD};
+
+pub fn init(_: &mut Vm, _: &[i16]) -> NativeResult {
+    Ok(0)
+}
+
+pub fn key_pressed(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    vm.invoke("Memory.peek", &[KBD as i16])
+}
+
+// Echo the key after briefly showing the cursor
+pub fn read_char(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    let Some(c) = vm.os.keyboard.pop_front() else {
+        return Err(Interrupt::Blocked);
+    };
+
+    vm.invoke("Output.printChar", &[0])?;
+    let back_space = vm.invoke("String.backSpace", &[])?;
+    vm.invoke("Output.printChar", &[back_space])?;
+    vm.invoke("Output.printChar", &[c])?;
+
+    Ok(c)
+}
+
+// Only starts once a whole line has been typed, so that blocking
+// never leaves a line half read
+pub fn read_line(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    if !vm.os.keyboard.contains(&NEWLINE) {
+        return Err(Interrupt::Blocked);
+    }
+
+    let mut s = vm.invoke("String.new", &[80])?;
+    vm.invoke("Output.printString", &[args[0]])?;
+    let newline = vm.invoke("String.newLine", &[])?;
+    let back_space = vm.invoke("String.backSpace", &[])?;
+    loop {
+        let c = read_char(vm, &[])?;
+        if c == newline {
+            break;
+        }
+        if c == back_space {
+            vm.invoke("String.eraseLastChar", &[s])?;
+        } else {
+            s = vm.invoke("String.appendChar", &[s, c])?;
+        }
+    }
+
+    Ok(s)
+}
+
+pub fn read_int(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let s = read_line(vm, args)?;
+    let value = vm.invoke("String.intValue", &[s])?;
+    vm.invoke("String.dispose", &[s])?;
+
+    Ok(value)
+}
@@ -0,0 +1,126 @@
+use super::{sys_error, Interrupt, NativeResult};
+use crate::vm::Vm;
+
+#[derive(Debug, Default)]
+pub struct Statics {
+    /// Array of 2^i for i in 0..16
+    two_to_the: i16,
+    /// Array of doubled divisors used by `divide`
+    multiples: i16,
+}
+
+fn at(vm: &Vm, arr: i16, i: i16) -> Result<i16, Interrupt> {
+    vm.peek(arr.wrapping_add(i))
+}
+
+pub fn init(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    vm.os.math.multiples = vm.invoke("Array.new", &[16])?;
+    vm.os.math.two_to_the = vm.invoke("Array.new", &[16])?;
+
+    let two_to_the = vm.os.math.two_to_the;
+    vm.poke(two_to_the, 1)?;
+    for i in 1..16 {
+        let prev = at(vm, two_to_the, i - 1)?;
+        vm.poke(two_to_the.wrapping_add(i), prev.wrapping_add(prev))?;
+    }
+
+    Ok(0)
+}
+
+pub fn abs(_: &mut Vm, args: &[i16]) -> NativeResult {
+    Ok(if args[0] < 0 { args[0].wrapping_neg() } else { args[0] })
+}
+
+fn opposite_signs(x: i16, y: i16) -> bool {
+    ((x < 0) & (y > 0)) | ((x > 0) & (y < 0))
+}
+
+// Shift and add over the bits of the smaller operand
+pub fn multiply(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let negative = opposite_signs(args[0], args[1]);
+    let mut x = abs(vm, &args[0..1])?;
+    let mut y = abs(vm, &args[1..2])?;
+    if x < y {
+        (x, y) = (y, x);
+    }
+
+    let two_to_the = vm.os.math.two_to_the;
+    let (mut sum, mut done, mut bit) = (0i16, 0i16, 0i16);
+    while done.wrapping_sub(1) < y.wrapping_sub(1) {
+        let mask = at(vm, two_to_the, bit)?;
+        if mask & y != 0 {
+            sum = sum.wrapping_add(x);
+            done = done.wrapping_add(mask);
+        }
+        x = x.wrapping_add(x);
+        bit = bit.wrapping_add(1);
+    }
+
+    Ok(if negative { sum.wrapping_neg() } else { sum })
+}
+
+// Long division against the divisor doubled up to 15 times
+pub fn divide(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    if args[1] == 0 {
+        sys_error(vm, 3)?;
+    }
+
+    let negative = opposite_signs(args[0], args[1]);
+    let multiples = vm.os.math.multiples;
+    let two_to_the = vm.os.math.two_to_the;
+    let divisor = abs(vm, &args[1..2])?;
+    vm.poke(multiples, divisor)?;
+    let mut x = abs(vm, &args[0..1])?;
+
+    let (mut i, mut quotient, mut stop) = (0i16, 0i16, false);
+    while (i < 15) & !stop {
+        let m = at(vm, multiples, i)?.wrapping_sub(1);
+        stop = 32767i16.wrapping_sub(m) < m;
+        if !stop {
+            let m = at(vm, multiples, i)?;
+            vm.poke(multiples.wrapping_add(i + 1), m.wrapping_add(m))?;
+            stop = at(vm, multiples, i + 1)?.wrapping_sub(1) > x.wrapping_sub(1);
+            if !stop {
+                i += 1;
+            }
+        }
+    }
+
+    while i > -1 {
+        if at(vm, multiples, i)?.wrapping_sub(1) <= x.wrapping_sub(1) {
+            quotient = quotient.wrapping_add(at(vm, two_to_the, i)?);
+            x = x.wrapping_sub(at(vm, multiples, i)?);
+        }
+        i -= 1;
+    }
+
+    Ok(if negative { quotient.wrapping_neg() } else { quotient })
+}
+
+// Binary search over the 8 result bits
+pub fn sqrt(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let x = args[0];
+    if x < 0 {
+        sys_error(vm, 4)?;
+    }
+
+    let two_to_the = vm.os.math.two_to_the;
+    let mut res = 0i16;
+    for i in (0..8).rev() {
+        let guess = res.wrapping_add(at(vm, two_to_the, i)?);
+        let square = multiply(vm, &[guess, guess])?;
+        if (square <= x) & (square >= 0) {
+            res = guess;
+        }
+    }
+
+    Ok(res)
+}
+
+pub fn max(_: &mut Vm, args: &[i16]) -> NativeResult {
+    Ok(if args[0] > args[1] { args[0] } else { args[1] })
+}
+
+pub fn min(_: &mut Vm, args: &[i16]) -> NativeResult {
+    Ok(if args[0] < args[1] { args[0] } else { args[1] })
+}
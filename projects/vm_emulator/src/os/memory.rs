@@ -0,0 +1,92 @@
+// Free list of blocks in 2048..16383. A block at `b` has its
+// free size in RAM[b] (0 once allocated) and the address where
+// its successor starts in RAM[b + 1], the user part starts at b + 2.
+
+use super::{sys_error, NativeResult};
+use crate::vm::Vm;
+
+const HEAP_BASE: i16 = 2048;
+const HEAP_END: i16 = 16383;
+
+pub fn init(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    vm.poke(HEAP_BASE, 14334)?;
+    vm.poke(HEAP_BASE + 1, HEAP_BASE + 2)?;
+    Ok(0)
+}
+
+pub fn peek(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    vm.peek(args[0])
+}
+
+pub fn poke(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    vm.poke(args[0], args[1])?;
+    Ok(0)
+}
+
+// Make the free block `b` absorb its successor `next`
+fn merge(vm: &mut Vm, b: i16, next: i16) -> Result<(), super::Interrupt> {
+    let size = vm.peek(b.wrapping_add(1))?.wrapping_sub(b).wrapping_add(vm.peek(next)?);
+    vm.poke(b, size)?;
+    let after = if vm.peek(next.wrapping_add(1))? == next.wrapping_add(2) {
+        b.wrapping_add(2)
+    } else {
+        vm.peek(next.wrapping_add(1))?
+    };
+    vm.poke(b.wrapping_add(1), after)
+}
+
+pub fn alloc(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let mut size = args[0];
+    if size < 0 {
+        sys_error(vm, 5)?;
+    }
+    if size == 0 {
+        size = 1;
+    }
+
+    // First fit, merging free neighbours on the way
+    let mut b = HEAP_BASE;
+    while (b < HEAP_END) & (vm.peek(b)? < size) {
+        let next = vm.peek(b.wrapping_add(1))?;
+        let used = vm.peek(b)? == 0;
+        let past_end = next > HEAP_END - 1;
+        if used | past_end | (vm.peek(next)? == 0) {
+            b = next;
+        } else {
+            merge(vm, b, next)?;
+        }
+    }
+
+    if b.wrapping_add(size) > HEAP_END - 4 {
+        sys_error(vm, 6)?;
+    }
+
+    // Split off the rest as a new free block
+    if vm.peek(b)? > size.wrapping_add(2) {
+        let rest = size.wrapping_add(2).wrapping_add(b);
+        vm.poke(rest, vm.peek(b)?.wrapping_sub(size).wrapping_sub(2))?;
+        let after = if vm.peek(b.wrapping_add(1))? == b.wrapping_add(2) {
+            b.wrapping_add(size).wrapping_add(4)
+        } else {
+            vm.peek(b.wrapping_add(1))?
+        };
+        vm.poke(rest.wrapping_add(1), after)?;
+        vm.poke(b.wrapping_add(1), b.wrapping_add(size).wrapping_add(2))?;
+    }
+
+    vm.poke(b, 0)?;
+    Ok(b.wrapping_add(2))
+}
+
+pub fn de_alloc(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let b = args[0].wrapping_sub(2);
+    let next = vm.peek(b.wrapping_add(1))?;
+    if vm.peek(next)? == 0 {
+        let size = vm.peek(b.wrapping_add(1))?.wrapping_sub(b).wrapping_sub(2);
+        vm.poke(b, size)?;
+    } else {
+        merge(vm, b, next)?;
+    }
+
+    Ok(0)
+}
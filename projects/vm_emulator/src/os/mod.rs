@@ -0,0 +1,152 @@
+//!
+//! Native implementations of the Jack OS, used for calls to OS
+//! functions no `.vm` file defines. Each one follows the code of
+//! tools/OS/*.vm step by step: objects live in RAM, the same heap
+//! blocks get allocated and errors go through `Sys.error` with the
+//! same codes. Calls to other classes go through `Vm::invoke`, so
+//! any class can be replaced by its VM implementation.
+//!
+
+use std::collections::VecDeque;
+
+use crate::error::RuntimeError;
+use crate::vm::Vm;
+
+mod array;
+mod font;
+mod keyboard;
+mod math;
+mod memory;
+mod output;
+mod screen;
+mod string;
+mod sys;
+
+pub const NEWLINE: i16 = 128;
+pub const BACKSPACE: i16 = 129;
+pub const DOUBLE_QUOTE: i16 = 34;
+
+///
+/// Ways a native function can leave other than returning.
+///
+#[derive(Debug)]
+pub(crate) enum Interrupt {
+    Halt,
+    /// Waiting for keyboard input, nothing has been done yet
+    Blocked,
+    /// Continue with a call of the given function from VM code
+    TailCall(String),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Interrupt {
+    fn from(err: RuntimeError) -> Self {
+        Interrupt::Error(err)
+    }
+}
+
+pub(crate) type NativeResult = Result<i16, Interrupt>;
+pub(crate) type Native = fn(&mut Vm, &[i16]) -> NativeResult;
+
+// Jack booleans
+fn bool(b: bool) -> i16 {
+    -(b as i16)
+}
+
+// do Sys.error(code), which normally halts
+fn sys_error(vm: &mut Vm, code: i16) -> Result<(), Interrupt> {
+    vm.invoke("Sys.error", &[code])?;
+    Ok(())
+}
+
+///
+/// Static variables of the native classes.
+///
+#[derive(Debug, Default)]
+pub(crate) struct OsState {
+    pub math: math::Statics,
+    pub screen: screen::Statics,
+    pub output: output::Statics,
+    pub keyboard: VecDeque<i16>,
+}
+
+///
+/// Whether `name` is an OS function with a native implementation.
+///
+pub fn is_native(name: &str) -> bool {
+    native(name).is_some()
+}
+
+pub(crate) fn native(name: &str) -> Option<Native> {
+    let f: Native = match name {
+        "Array.new" => array::new,
+        "Array.dispose" => array::dispose,
+
+        "Keyboard.init" => keyboard::init,
+        "Keyboard.keyPressed" => keyboard::key_pressed,
+        "Keyboard.readChar" => keyboard::read_char,
+        "Keyboard.readLine" => keyboard::read_line,
+        "Keyboard.readInt" => keyboard::read_int,
+
+        "Math.init" => math::init,
+        "Math.abs" => math::abs,
+        "Math.multiply" => math::multiply,
+        "Math.divide" => math::divide,
+        "Math.sqrt" => math::sqrt,
+        "Math.max" => math::max,
+        "Math.min" => math::min,
+
+        "Memory.init" => memory::init,
+        "Memory.peek" => memory::peek,
+        "Memory.poke" => memory::poke,
+        "Memory.alloc" => memory::alloc,
+        "Memory.deAlloc" => memory::de_alloc,
+
+        "Output.init" => output::init,
+        "Output.initMap" => output::init_map,
+        "Output.create" => output::create,
+        "Output.createShiftedMap" => output::create_shifted_map,
+        "Output.getMap" => output::get_map,
+        "Output.drawChar" => output::draw_char,
+        "Output.moveCursor" => output::move_cursor,
+        "Output.printChar" => output::print_char,
+        "Output.printString" => output::print_string,
+        "Output.printInt" => output::print_int,
+        "Output.println" => output::println,
+        "Output.backSpace" => output::back_space,
+
+        "Screen.init" => screen::init,
+        "Screen.clearScreen" => screen::clear_screen,
+        "Screen.updateLocation" => screen::update_location,
+        "Screen.setColor" => screen::set_color,
+        "Screen.drawPixel" => screen::draw_pixel,
+        "Screen.drawConditional" => screen::draw_conditional,
+        "Screen.drawLine" => screen::draw_line,
+        "Screen.drawRectangle" => screen::draw_rectangle,
+        "Screen.drawHorizontal" => screen::draw_horizontal,
+        "Screen.drawSymetric" => screen::draw_symetric,
+        "Screen.drawCircle" => screen::draw_circle,
+
+        "String.new" => string::new,
+        "String.dispose" => string::dispose,
+        "String.length" => string::length,
+        "String.charAt" => string::char_at,
+        "String.setCharAt" => string::set_char_at,
+        "String.appendChar" => string::append_char,
+        "String.eraseLastChar" => string::erase_last_char,
+        "String.intValue" => string::int_value,
+        "String.setInt" => string::set_int,
+        "String.newLine" => string::new_line,
+        "String.backSpace" => string::back_space,
+        "String.doubleQuote" => string::double_quote,
+
+        "Sys.init" => sys::init,
+        "Sys.halt" => sys::halt,
+        "Sys.wait" => sys::wait,
+        "Sys.error" => sys::error,
+
+        _ => return None,
+    };
+
+    Some(f)
+}
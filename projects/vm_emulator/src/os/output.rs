@@ -0,0 +1,199 @@
+// The cursor is kept as the screen word it is in plus which half
+// of that word, so characters are 8 pixels wide and 11 rows high.
+
+use super::font::GLYPHS;
+use super::{sys_error, Interrupt, NativeResult};
+use crate::vm::Vm;
+
+#[derive(Debug, Default)]
+pub struct Statics {
+    /// Cursor column divided by 2
+    column: i16,
+    /// Offset of the cursor's screen word
+    word: i16,
+    /// Whether the cursor is in the left half of its word
+    left: bool,
+    /// String used by `printInt`
+    int_string: i16,
+    /// Base address of the screen memory map
+    screen: i16,
+    /// Array of glyphs for the left half of a word
+    map: i16,
+    /// Array of glyphs shifted to the right half of a word
+    shifted_map: i16,
+}
+
+fn at(vm: &Vm, arr: i16, i: i16) -> Result<i16, Interrupt> {
+    vm.peek(arr.wrapping_add(i))
+}
+
+pub fn init(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    vm.os.output.screen = 16384;
+    vm.os.output.left = true;
+    vm.os.output.word = 32;
+    vm.os.output.column = 0;
+    vm.os.output.int_string = vm.invoke("String.new", &[6])?;
+    init_map(vm, &[])?;
+    create_shifted_map(vm, &[])?;
+
+    Ok(0)
+}
+
+pub fn init_map(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    vm.os.output.map = vm.invoke("Array.new", &[127])?;
+    for (c, rows) in GLYPHS {
+        let mut args = vec![c];
+        args.extend(rows);
+        create(vm, &args)?;
+    }
+
+    Ok(0)
+}
+
+// Store the 11 rows following the character code as its glyph
+pub fn create(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let glyph = vm.invoke("Array.new", &[11])?;
+    vm.poke(vm.os.output.map.wrapping_add(args[0]), glyph)?;
+    for (i, row) in (0..).zip(&args[1..]) {
+        vm.poke(glyph.wrapping_add(i), *row)?;
+    }
+
+    Ok(0)
+}
+
+pub fn create_shifted_map(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    vm.os.output.shifted_map = vm.invoke("Array.new", &[127])?;
+    for c in (0..1).chain(32..127) {
+        let glyph = at(vm, vm.os.output.map, c)?;
+        let shifted = vm.invoke("Array.new", &[11])?;
+        vm.poke(vm.os.output.shifted_map.wrapping_add(c), shifted)?;
+        for i in 0..11 {
+            let row = vm.invoke("Math.multiply", &[at(vm, glyph, i)?, 256])?;
+            vm.poke(shifted.wrapping_add(i), row)?;
+        }
+    }
+
+    Ok(0)
+}
+
+// Glyph of `c` for the current half, the black box for unknown characters
+pub fn get_map(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let mut c = args[0];
+    if !(32..=126).contains(&c) {
+        c = 0;
+    }
+
+    let map = if vm.os.output.left { vm.os.output.map } else { vm.os.output.shifted_map };
+    at(vm, map, c)
+}
+
+// Draw `c` at the cursor without moving it
+pub fn draw_char(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let glyph = get_map(vm, args)?;
+    let screen = vm.os.output.screen;
+    let mut word = vm.os.output.word;
+    for i in 0..11 {
+        let addr = screen.wrapping_add(word);
+        let kept = if vm.os.output.left { vm.peek(addr)? & -256 } else { vm.peek(addr)? & 255 };
+        vm.poke(addr, at(vm, glyph, i)? | kept)?;
+        word = word.wrapping_add(32);
+    }
+
+    Ok(0)
+}
+
+pub fn move_cursor(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (i, j) = (args[0], args[1]);
+    if !(0..=22).contains(&i) | !(0..=63).contains(&j) {
+        sys_error(vm, 20)?;
+    }
+
+    let column = vm.invoke("Math.divide", &[j, 2])?;
+    vm.os.output.column = column;
+    let row = vm.invoke("Math.multiply", &[i, 352])?;
+    vm.os.output.word = row.wrapping_add(32).wrapping_add(column);
+    vm.os.output.left = j == vm.invoke("Math.multiply", &[column, 2])?;
+    draw_char(vm, &[' ' as i16])?;
+
+    Ok(0)
+}
+
+pub fn print_char(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let c = args[0];
+    if c == vm.invoke("String.newLine", &[])? {
+        println(vm, &[])?;
+    } else if c == vm.invoke("String.backSpace", &[])? {
+        back_space(vm, &[])?;
+    } else {
+        draw_char(vm, &[c])?;
+        let output = &mut vm.os.output;
+        if !output.left {
+            output.column = output.column.wrapping_add(1);
+            output.word = output.word.wrapping_add(1);
+        }
+        if output.column == 32 {
+            println(vm, &[])?;
+        } else {
+            output.left = !output.left;
+        }
+    }
+
+    Ok(0)
+}
+
+pub fn print_string(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let s = args[0];
+    let len = vm.invoke("String.length", &[s])?;
+    let mut i = 0;
+    while i < len {
+        let c = vm.invoke("String.charAt", &[s, i])?;
+        print_char(vm, &[c])?;
+        i += 1;
+    }
+
+    Ok(0)
+}
+
+pub fn print_int(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let s = vm.os.output.int_string;
+    vm.invoke("String.setInt", &[s, args[0]])?;
+    print_string(vm, &[s])?;
+
+    Ok(0)
+}
+
+// Next line, back to the top after the last one
+pub fn println(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    let output = &mut vm.os.output;
+    output.word = output.word.wrapping_add(352).wrapping_sub(output.column);
+    output.column = 0;
+    output.left = true;
+    if output.word == 8128 {
+        output.word = 32;
+    }
+
+    Ok(0)
+}
+
+// Move back one character, wrapping to the previous line, and erase it
+pub fn back_space(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    let output = &mut vm.os.output;
+    if output.left {
+        if output.column > 0 {
+            output.column = output.column.wrapping_sub(1);
+            output.word = output.word.wrapping_sub(1);
+        } else {
+            output.column = 31;
+            if output.word == 32 {
+                output.word = 8128;
+            }
+            output.word = output.word.wrapping_sub(321);
+        }
+        output.left = false;
+    } else {
+        output.left = true;
+    }
+    draw_char(vm, &[' ' as i16])?;
+
+    Ok(0)
+}
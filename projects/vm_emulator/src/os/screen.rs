@@ -0,0 +1,256 @@
+use super::{sys_error, Interrupt, NativeResult};
+use crate::vm::Vm;
+
+#[derive(Debug, Default)]
+pub struct Statics {
+    /// Array of 2^i for i in 0..17
+    two_to_the: i16,
+    /// Base address of the screen memory map
+    screen: i16,
+    /// Current drawing color, true for black
+    color: bool,
+}
+
+fn at(vm: &Vm, arr: i16, i: i16) -> Result<i16, Interrupt> {
+    vm.peek(arr.wrapping_add(i))
+}
+
+fn divide(vm: &mut Vm, x: i16, y: i16) -> NativeResult {
+    vm.invoke("Math.divide", &[x, y])
+}
+
+fn multiply(vm: &mut Vm, x: i16, y: i16) -> NativeResult {
+    vm.invoke("Math.multiply", &[x, y])
+}
+
+pub fn init(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    vm.os.screen.screen = 16384;
+    vm.os.screen.color = true;
+    vm.os.screen.two_to_the = vm.invoke("Array.new", &[17])?;
+
+    let two_to_the = vm.os.screen.two_to_the;
+    vm.poke(two_to_the, 1)?;
+    for i in 1..17 {
+        let prev = at(vm, two_to_the, i - 1)?;
+        vm.poke(two_to_the.wrapping_add(i), prev.wrapping_add(prev))?;
+    }
+
+    Ok(0)
+}
+
+pub fn clear_screen(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    let screen = vm.os.screen.screen;
+    for i in 0..8192 {
+        vm.poke(screen.wrapping_add(i), 0)?;
+    }
+
+    Ok(0)
+}
+
+// Set or clear the bits of `mask` in the screen word at `addr`
+pub fn update_location(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let addr = vm.os.screen.screen.wrapping_add(args[0]);
+    let word = vm.peek(addr)?;
+    let word = if vm.os.screen.color { word | args[1] } else { word & !args[1] };
+    vm.poke(addr, word)?;
+
+    Ok(0)
+}
+
+fn update(vm: &mut Vm, addr: i16, mask: i16) -> Result<(), Interrupt> {
+    update_location(vm, &[addr, mask])?;
+    Ok(())
+}
+
+pub fn set_color(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    vm.os.screen.color = args[0] != 0;
+    Ok(0)
+}
+
+pub fn draw_pixel(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (x, y) = (args[0], args[1]);
+    if !(0..=511).contains(&x) | !(0..=255).contains(&y) {
+        sys_error(vm, 7)?;
+    }
+
+    let word = divide(vm, x, 16)?;
+    let bit = x.wrapping_sub(multiply(vm, word, 16)?);
+    let addr = multiply(vm, y, 32)?.wrapping_add(word);
+    let mask = at(vm, vm.os.screen.two_to_the, bit)?;
+    update(vm, addr, mask)?;
+
+    Ok(0)
+}
+
+// Draw (a, b), or (b, a) when the axes are swapped
+pub fn draw_conditional(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (a, b, swapped) = (args[0], args[1], args[2]);
+    if swapped != 0 {
+        draw_pixel(vm, &[b, a])
+    } else {
+        draw_pixel(vm, &[a, b])
+    }
+}
+
+// Bresenham, stepping along the longer axis
+pub fn draw_line(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (mut x1, mut y1, mut x2, mut y2) = (args[0], args[1], args[2], args[3]);
+    if (x1 < 0) | (x2 > 511) | (y1 < 0) | (y2 > 255) {
+        sys_error(vm, 8)?;
+    }
+
+    let mut dx = vm.invoke("Math.abs", &[x2.wrapping_sub(x1)])?;
+    let mut dy = vm.invoke("Math.abs", &[y2.wrapping_sub(y1)])?;
+    let steep = dx < dy;
+    if (steep & (y2 < y1)) | (!steep & (x2 < x1)) {
+        (x1, x2) = (x2, x1);
+        (y1, y2) = (y2, y1);
+    }
+
+    let (mut a, mut b, end, decreasing);
+    if steep {
+        (dx, dy) = (dy, dx);
+        a = y1;
+        b = x1;
+        end = y2;
+        decreasing = x1 > x2;
+    } else {
+        a = x1;
+        b = y1;
+        end = x2;
+        decreasing = y1 > y2;
+    }
+
+    let mut err = multiply(vm, 2, dy)?.wrapping_sub(dx);
+    let straight = multiply(vm, 2, dy)?;
+    let diagonal = multiply(vm, 2, dy.wrapping_sub(dx))?;
+    let steep = super::bool(steep);
+    draw_conditional(vm, &[a, b, steep])?;
+    while a < end {
+        if err < 0 {
+            err = err.wrapping_add(straight);
+        } else {
+            err = err.wrapping_add(diagonal);
+            b = if decreasing { b.wrapping_sub(1) } else { b.wrapping_add(1) };
+        }
+        a = a.wrapping_add(1);
+        draw_conditional(vm, &[a, b, steep])?;
+    }
+
+    Ok(0)
+}
+
+// Masks for the bits from `x` to the end of its word and from the
+// start of its word up to `x`
+fn left_mask(vm: &Vm, bit: i16) -> Result<i16, Interrupt> {
+    Ok(!at(vm, vm.os.screen.two_to_the, bit)?.wrapping_sub(1))
+}
+
+fn right_mask(vm: &Vm, bit: i16) -> Result<i16, Interrupt> {
+    Ok(at(vm, vm.os.screen.two_to_the, bit.wrapping_add(1))?.wrapping_sub(1))
+}
+
+// Fill the words from `addr` to `addr + width`, masking the two ends
+fn fill_row(vm: &mut Vm, mut addr: i16, width: i16, left: i16, right: i16) -> Result<i16, Interrupt> {
+    let last = addr.wrapping_add(width);
+    if width == 0 {
+        update(vm, addr, right & left)?;
+    } else {
+        update(vm, addr, left)?;
+        addr = addr.wrapping_add(1);
+        while addr < last {
+            update(vm, addr, -1)?;
+            addr = addr.wrapping_add(1);
+        }
+        update(vm, last, right)?;
+    }
+
+    Ok(last)
+}
+
+pub fn draw_rectangle(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (x1, mut y1, x2, y2) = (args[0], args[1], args[2], args[3]);
+    if (x1 > x2) | (y1 > y2) | (x1 < 0) | (x2 > 511) | (y1 < 0) | (y2 > 255) {
+        sys_error(vm, 9)?;
+    }
+
+    let first = divide(vm, x1, 16)?;
+    let first_bit = x1.wrapping_sub(multiply(vm, first, 16)?);
+    let last = divide(vm, x2, 16)?;
+    let last_bit = x2.wrapping_sub(multiply(vm, last, 16)?);
+    let left = left_mask(vm, first_bit)?;
+    let right = right_mask(vm, last_bit)?;
+    let mut addr = multiply(vm, y1, 32)?.wrapping_add(first);
+    let width = last.wrapping_sub(first);
+
+    while y1 <= y2 {
+        let end = fill_row(vm, addr, width, left, right)?;
+        y1 = y1.wrapping_add(1);
+        addr = end.wrapping_add(32).wrapping_sub(width);
+    }
+
+    Ok(0)
+}
+
+// Draw the row `y` from `xa` to `xb`, clipped to the screen
+pub fn draw_horizontal(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (y, xa, xb) = (args[0], args[1], args[2]);
+    let mut x1 = vm.invoke("Math.min", &[xa, xb])?;
+    let mut x2 = vm.invoke("Math.max", &[xa, xb])?;
+    if (y > -1) & (y < 256) & (x1 < 512) & (x2 > -1) {
+        x1 = vm.invoke("Math.max", &[x1, 0])?;
+        x2 = vm.invoke("Math.min", &[x2, 511])?;
+
+        let first = divide(vm, x1, 16)?;
+        let first_bit = x1.wrapping_sub(multiply(vm, first, 16)?);
+        let last = divide(vm, x2, 16)?;
+        let last_bit = x2.wrapping_sub(multiply(vm, last, 16)?);
+        let left = left_mask(vm, first_bit)?;
+        let right = right_mask(vm, last_bit)?;
+        let addr = multiply(vm, y, 32)?.wrapping_add(first);
+        fill_row(vm, addr, last.wrapping_sub(first), left, right)?;
+    }
+
+    Ok(0)
+}
+
+// Fill the four rows of a circle given by one of its octant points
+pub fn draw_symetric(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (x, y, a, b) = (args[0], args[1], args[2], args[3]);
+    draw_horizontal(vm, &[y.wrapping_sub(b), x.wrapping_add(a), x.wrapping_sub(a)])?;
+    draw_horizontal(vm, &[y.wrapping_add(b), x.wrapping_add(a), x.wrapping_sub(a)])?;
+    draw_horizontal(vm, &[y.wrapping_sub(a), x.wrapping_sub(b), x.wrapping_add(b)])?;
+    draw_horizontal(vm, &[y.wrapping_add(a), x.wrapping_sub(b), x.wrapping_add(b)])?;
+
+    Ok(0)
+}
+
+// Midpoint circle over one octant
+pub fn draw_circle(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (x, y, r) = (args[0], args[1], args[2]);
+    if !(0..=511).contains(&x) | !(0..=255).contains(&y) {
+        sys_error(vm, 12)?;
+    }
+    if (x.wrapping_sub(r) < 0)
+        | (x.wrapping_add(r) > 511)
+        | (y.wrapping_sub(r) < 0)
+        | (y.wrapping_add(r) > 255)
+    {
+        sys_error(vm, 13)?;
+    }
+
+    let (mut a, mut b, mut err) = (0i16, r, 1i16.wrapping_sub(r));
+    draw_symetric(vm, &[x, y, a, b])?;
+    while b > a {
+        if err < 0 {
+            err = err.wrapping_add(multiply(vm, 2, a)?).wrapping_add(3);
+        } else {
+            err = err.wrapping_add(multiply(vm, 2, a.wrapping_sub(b))?).wrapping_add(5);
+            b = b.wrapping_sub(1);
+        }
+        a = a.wrapping_add(1);
+        draw_symetric(vm, &[x, y, a, b])?;
+    }
+
+    Ok(0)
+}
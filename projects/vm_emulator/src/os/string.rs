@@ -0,0 +1,193 @@
+// A string object holds its capacity, its character array
+// (only allocated for a capacity above 0) and its length.
+
+use super::{sys_error, Interrupt, NativeResult, BACKSPACE, DOUBLE_QUOTE, NEWLINE};
+use crate::vm::Vm;
+
+const CAPACITY: i16 = 0;
+const CHARS: i16 = 1;
+const LENGTH: i16 = 2;
+
+fn field(vm: &Vm, this: i16, f: i16) -> Result<i16, Interrupt> {
+    vm.peek(this.wrapping_add(f))
+}
+
+fn set_field(vm: &mut Vm, this: i16, f: i16, value: i16) -> Result<(), Interrupt> {
+    vm.poke(this.wrapping_add(f), value)
+}
+
+fn char_addr(vm: &Vm, this: i16, i: i16) -> Result<i16, Interrupt> {
+    Ok(i.wrapping_add(field(vm, this, CHARS)?))
+}
+
+pub fn new(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let capacity = args[0];
+    let this = vm.invoke("Memory.alloc", &[3])?;
+    if capacity < 0 {
+        sys_error(vm, 14)?;
+    }
+    if capacity > 0 {
+        let chars = vm.invoke("Array.new", &[capacity])?;
+        set_field(vm, this, CHARS, chars)?;
+    }
+    set_field(vm, this, CAPACITY, capacity)?;
+    set_field(vm, this, LENGTH, 0)?;
+
+    Ok(this)
+}
+
+pub fn dispose(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let this = args[0];
+    if field(vm, this, CAPACITY)? > 0 {
+        let chars = field(vm, this, CHARS)?;
+        vm.invoke("Array.dispose", &[chars])?;
+    }
+    vm.invoke("Memory.deAlloc", &[this])?;
+
+    Ok(0)
+}
+
+pub fn length(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    field(vm, args[0], LENGTH)
+}
+
+fn check_index(vm: &mut Vm, this: i16, i: i16, code: i16) -> Result<(), Interrupt> {
+    let len = field(vm, this, LENGTH)?;
+    if (i < 0) | (i > len) | (i == len) {
+        sys_error(vm, code)?;
+    }
+
+    Ok(())
+}
+
+pub fn char_at(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (this, i) = (args[0], args[1]);
+    check_index(vm, this, i, 15)?;
+
+    let addr = char_addr(vm, this, i)?;
+    vm.peek(addr)
+}
+
+pub fn set_char_at(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (this, i, c) = (args[0], args[1], args[2]);
+    check_index(vm, this, i, 16)?;
+
+    let addr = char_addr(vm, this, i)?;
+    vm.poke(addr, c)?;
+    Ok(0)
+}
+
+pub fn append_char(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (this, c) = (args[0], args[1]);
+    if field(vm, this, LENGTH)? == field(vm, this, CAPACITY)? {
+        sys_error(vm, 17)?;
+    }
+
+    let len = field(vm, this, LENGTH)?;
+    let addr = char_addr(vm, this, len)?;
+    vm.poke(addr, c)?;
+    set_field(vm, this, LENGTH, len.wrapping_add(1))?;
+
+    Ok(this)
+}
+
+pub fn erase_last_char(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let this = args[0];
+    if field(vm, this, LENGTH)? == 0 {
+        sys_error(vm, 18)?;
+    }
+
+    let len = field(vm, this, LENGTH)?;
+    set_field(vm, this, LENGTH, len.wrapping_sub(1))?;
+    Ok(0)
+}
+
+// Value of the leading digits, after an optional '-'
+pub fn int_value(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let this = args[0];
+    if field(vm, this, LENGTH)? == 0 {
+        return Ok(0);
+    }
+
+    let (mut i, mut value, mut digits, mut negative) = (0i16, 0i16, true, false);
+    let first = char_addr(vm, this, 0)?;
+    if vm.peek(first)? == '-' as i16 {
+        negative = true;
+        i = 1;
+    }
+
+    while (i < field(vm, this, LENGTH)?) & digits {
+        let addr = char_addr(vm, this, i)?;
+        let d = vm.peek(addr)?.wrapping_sub('0' as i16);
+        digits = (0..=9).contains(&d);
+        if digits {
+            value = vm.invoke("Math.multiply", &[value, 10])?.wrapping_add(d);
+            i = i.wrapping_add(1);
+        }
+    }
+
+    Ok(if negative { value.wrapping_neg() } else { value })
+}
+
+pub fn set_int(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    let (this, mut n) = (args[0], args[1]);
+    if field(vm, this, CAPACITY)? == 0 {
+        sys_error(vm, 19)?;
+    }
+
+    // Digits in reverse order
+    let digits = vm.invoke("Array.new", &[6])?;
+    let mut negative = false;
+    if n < 0 {
+        negative = true;
+        n = n.wrapping_neg();
+    }
+
+    let (mut len, mut rest) = (0i16, n);
+    while rest > 0 {
+        rest = vm.invoke("Math.divide", &[n, 10])?;
+        let addr = len.wrapping_add(digits);
+        let tens = vm.invoke("Math.multiply", &[rest, 10])?;
+        vm.poke(addr, ('0' as i16).wrapping_add(n.wrapping_sub(tens)))?;
+        len = len.wrapping_add(1);
+        n = rest;
+    }
+    if negative {
+        vm.poke(len.wrapping_add(digits), '-' as i16)?;
+        len = len.wrapping_add(1);
+    }
+
+    if field(vm, this, CAPACITY)? < len {
+        sys_error(vm, 19)?;
+    }
+
+    if len == 0 {
+        let addr = char_addr(vm, this, 0)?;
+        vm.poke(addr, '0' as i16)?;
+        set_field(vm, this, LENGTH, 1)?;
+    } else {
+        set_field(vm, this, LENGTH, 0)?;
+        while field(vm, this, LENGTH)? < len {
+            let i = field(vm, this, LENGTH)?;
+            let addr = char_addr(vm, this, i)?;
+            let c = vm.peek(len.wrapping_sub(i.wrapping_add(1)).wrapping_add(digits))?;
+            vm.poke(addr, c)?;
+            set_field(vm, this, LENGTH, i.wrapping_add(1))?;
+        }
+    }
+    vm.invoke("Array.dispose", &[digits])?;
+
+    Ok(0)
+}
+
+pub fn new_line(_: &mut Vm, _: &[i16]) -> NativeResult {
+    Ok(NEWLINE)
+}
+
+pub fn back_space(_: &mut Vm, _: &[i16]) -> NativeResult {
+    Ok(BACKSPACE)
+}
+
+pub fn double_quote(_: &mut Vm, _: &[i16]) -> NativeResult {
+    Ok(DOUBLE_QUOTE)
+}
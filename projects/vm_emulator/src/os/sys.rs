@@ -0,0 +1,35 @@
+use super::{Interrupt, NativeResult};
+use crate::vm::Vm;
+
+pub fn init(vm: &mut Vm, _: &[i16]) -> NativeResult {
+    for class in ["Memory", "Math", "Screen", "Output", "Keyboard"] {
+        vm.invoke(&format!("{}.init", class), &[])?;
+    }
+
+    // Returning from Main.main leaves Sys.init, which halts
+    Err(Interrupt::TailCall("Main.main".to_string()))
+}
+
+pub fn halt(_: &mut Vm, _: &[i16]) -> NativeResult {
+    Err(Interrupt::Halt)
+}
+
+// No time passes in the emulator, only the argument is checked
+pub fn wait(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    if args[0] < 0 {
+        super::sys_error(vm, 1)?;
+    }
+
+    Ok(0)
+}
+
+// Prints "ERR<code>" and halts
+pub fn error(vm: &mut Vm, args: &[i16]) -> NativeResult {
+    for c in "ERR".chars() {
+        vm.invoke("Output.printChar", &[c as i16])?;
+    }
+    vm.invoke("Output.printInt", &[args[0]])?;
+    vm.invoke("Sys.halt", &[])?;
+
+    Ok(0)
+}
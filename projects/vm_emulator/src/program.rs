@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use hack_vm::{Command, Module, Op, Segment};
+
+pub const STATIC_BASE: i32 = 16;
+
+///
+/// All modules of a program flattened into one command list
+/// with jump and call targets resolved up front.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub(crate) commands: Vec<Command>,
+    /// Index into `files` for every command
+    pub(crate) file_of: Vec<usize>,
+    /// Resolved `goto`/`if-goto`/`call` target of every command
+    pub(crate) targets: Vec<Option<usize>>,
+    pub(crate) files: Vec<String>,
+    /// First RAM address of the static segment of every file
+    pub(crate) static_base: Vec<i32>,
+    /// One past the last RAM address of the static segment of every file
+    pub(crate) static_end: Vec<i32>,
+    pub(crate) functions: HashMap<String, usize>,
+}
+
+impl Program {
+    pub fn new(modules: &[Module]) -> Program {
+        let mut prog = Program::default();
+
+        // Statics are laid out file after file like the assembler
+        // allocates the `File.i` variables of translated code
+        let mut next_static = STATIC_BASE;
+        let mut labels = HashMap::new();
+        for (file, module) in modules.iter().enumerate() {
+            prog.files.push(module.name.clone());
+            prog.static_base.push(next_static);
+
+            let mut curr_fun = module.name.clone();
+            for c in &module.commands {
+                let pc = prog.commands.len();
+                match &c.op {
+                    Op::Function(name, _) => {
+                        prog.functions.insert(name.clone(), pc);
+                        curr_fun = name.clone();
+                    }
+                    Op::Label(label) => {
+                        labels.insert((curr_fun.clone(), label.clone()), pc);
+                    }
+                    Op::Push(Segment::Static, idx) | Op::Pop(Segment::Static, idx) => {
+                        next_static = next_static.max(prog.static_base[file] + idx + 1);
+                    }
+                    _ => {}
+                }
+
+                prog.commands.push(c.clone());
+                prog.file_of.push(file);
+            }
+
+            prog.static_end.push(next_static);
+        }
+
+        let mut curr_fun = String::new();
+        for (pc, c) in prog.commands.iter().enumerate() {
+            if pc == 0 || prog.file_of[pc] != prog.file_of[pc - 1] {
+                curr_fun = prog.files[prog.file_of[pc]].clone();
+            }
+
+            let target = match &c.op {
+                Op::Function(name, _) => {
+                    curr_fun = name.clone();
+                    None
+                }
+                Op::Goto(label) | Op::IfGoto(label) => labels.get(&(curr_fun.clone(), label.clone())).copied(),
+                Op::Call(name, _) => prog.functions.get(name).copied(),
+                _ => None,
+            };
+            prog.targets.push(target);
+        }
+
+        prog
+    }
+
+    pub fn function(&self, name: &str) -> Option<usize> {
+        self.functions.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// File name and line of the command at `pc`.
+    pub fn location(&self, pc: usize) -> Option<(&str, usize)> {
+        let c = self.commands.get(pc)?;
+        Some((&self.files[self.file_of[pc]], c.line))
+    }
+}
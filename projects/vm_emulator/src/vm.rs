@@ -0,0 +1,571 @@
+use std::rc::Rc;
+
+use hack_vm::{Index, Module, Op, Segment};
+
+use crate::error::{ErrorKind, RuntimeError};
+use crate::os::{self, Interrupt, OsState};
+use crate::program::Program;
+
+pub const RAM_SIZE: usize = 32768;
+pub const STACK_BASE: i16 = 256;
+pub const STATIC_END: i32 = 256;
+
+pub const SP: usize = 0;
+pub const LCL: usize = 1;
+pub const ARG: usize = 2;
+pub const THIS: usize = 3;
+pub const THAT: usize = 4;
+pub const TEMP: usize = 5;
+pub const KBD: usize = 24576;
+
+///
+/// Activation record of a called function. The frame itself lives
+/// in RAM as with translated code, this only keeps what the VM
+/// needs to check segment accesses and to return.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub function: String,
+    /// None for the entry function called by `boot`
+    pub return_pc: Option<usize>,
+    pub nargs: i32,
+    pub nlocals: i32,
+}
+
+///
+/// Interpreter running VM commands directly, with the RAM
+/// layout of the standard VM mapping on the Hack platform.
+///
+/// Calls to OS functions without VM code run the native
+/// implementations in `os`. Calling `Sys.halt` halts.
+///
+pub struct Vm {
+    program: Rc<Program>,
+    ram: Vec<i16>,
+    pc: usize,
+    frames: Vec<Frame>,
+    halted: bool,
+    waiting: bool,
+    steps: u64,
+    /// Steps after which nested calls made by native code give up
+    limit: u64,
+    os_error: Option<i16>,
+    pub(crate) os: OsState,
+}
+
+impl Vm {
+    pub fn new(modules: &[Module]) -> Vm {
+        let mut vm = Vm {
+            program: Rc::new(Program::new(modules)),
+            ram: vec![0; RAM_SIZE],
+            pc: 0,
+            frames: Vec::new(),
+            halted: false,
+            waiting: false,
+            steps: 0,
+            limit: u64::MAX,
+            os_error: None,
+            os: OsState::default(),
+        };
+        vm.ram[SP] = STACK_BASE;
+        vm.halted = vm.program.is_empty();
+
+        vm
+    }
+
+    ///
+    /// Start execution at `entry` like the bootstrap code does:
+    /// SP = 256 and `call entry 0`. Returning from `entry` halts.
+    ///
+    pub fn boot(&mut self, entry: &str) -> Result<(), RuntimeError> {
+        let target = self.program.function(entry);
+        let native = os::native(entry);
+        if target.is_none() && native.is_none() {
+            return Err(RuntimeError {
+                kind: ErrorKind::UnknownFunction,
+                file: String::new(),
+                line: 0,
+                message: format!("unknown entry function {}", entry),
+            });
+        }
+
+        self.ram[SP] = STACK_BASE;
+        self.halted = false;
+        self.push_frame(entry, 0, None)?;
+        if let Some(target) = target {
+            self.pc = target;
+            return Ok(());
+        }
+
+        match native.unwrap()(self, &[]) {
+            // Without VM code there is nothing to resume once keys arrive
+            Ok(_) | Err(Interrupt::Halt) | Err(Interrupt::Blocked) => self.halted = true,
+            Err(Interrupt::TailCall(name)) => {
+                let Some(target) = self.program.function(&name) else {
+                    return Err(self.error(ErrorKind::UnknownFunction, format!("unknown function {}", name)));
+                };
+                self.push_frame(&name, 0, None)?;
+                self.pc = target;
+            }
+            Err(Interrupt::Error(err)) => return Err(err),
+        }
+
+        Ok(())
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [i16] {
+        &mut self.ram
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Call stack, innermost frame last.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Whether the last step waited for keyboard input.
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// Error code of the last `Sys.error` call.
+    pub fn os_error(&self) -> Option<i16> {
+        self.os_error
+    }
+
+    ///
+    /// Queue key codes for the native `Keyboard.read*` functions.
+    /// `keyPressed` only sees RAM[KBD], see `ram_mut`.
+    ///
+    pub fn type_keys(&mut self, keys: &[i16]) {
+        self.os.keyboard.extend(keys);
+    }
+
+    /// Queue `text`, with '\n' typed as the newline key.
+    pub fn type_text(&mut self, text: &str) {
+        let keys: Vec<i16> = text.chars().map(|c| if c == '\n' { os::NEWLINE } else { c as i16 }).collect();
+        self.type_keys(&keys);
+    }
+
+    /// Number of commands executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// File name and line of the next command.
+    pub fn location(&self) -> Option<(&str, usize)> {
+        self.program.location(self.pc)
+    }
+
+    /// Current value of `segment[idx]`, None if out of bounds.
+    pub fn segment(&self, seg: Segment, idx: Index) -> Option<i16> {
+        match seg {
+            Segment::Constant => Some(idx as i16),
+            _ => self.address(seg, idx).ok().map(|addr| self.ram[addr]),
+        }
+    }
+
+    /// Working stack of the current function, bottom first.
+    pub fn stack(&self) -> &[i16] {
+        let sp = (self.ram[SP].max(0) as usize).min(RAM_SIZE);
+        &self.ram[self.stack_base().clamp(0, sp as i32) as usize..sp]
+    }
+
+    ///
+    /// Execute up to `max_steps` commands, stopping early when the
+    /// program halts or waits for keyboard input. Returns the
+    /// number of commands executed.
+    ///
+    pub fn run(&mut self, max_steps: u64) -> Result<u64, RuntimeError> {
+        let start = self.steps;
+        self.limit = start.saturating_add(max_steps);
+        self.waiting = false;
+        while !self.halted && !self.waiting && self.steps < self.limit {
+            self.step()?;
+        }
+        self.limit = u64::MAX;
+
+        Ok(self.steps - start)
+    }
+
+    ///
+    /// Execute the next command. The program halts when it runs
+    /// past its last command, returns from the entry function
+    /// or enters a `label L; goto L` loop.
+    ///
+    pub fn step(&mut self) -> Result<(), RuntimeError> {
+        if self.halted {
+            return Ok(());
+        }
+        self.waiting = false;
+
+        let pc = self.pc;
+        let program = Rc::clone(&self.program);
+        let op = &program.commands[pc].op;
+        let mut next = pc + 1;
+
+        match op {
+            Op::Add => self.binary(op, |x, y| x.wrapping_add(y))?,
+            Op::Sub => self.binary(op, |x, y| x.wrapping_sub(y))?,
+            Op::And => self.binary(op, |x, y| x & y)?,
+            Op::Or => self.binary(op, |x, y| x | y)?,
+            Op::Eq => self.binary(op, |x, y| -((x == y) as i16))?,
+            Op::Gt => self.binary(op, |x, y| -((x > y) as i16))?,
+            Op::Lt => self.binary(op, |x, y| -((x < y) as i16))?,
+            Op::Mul => self.binary(op, |x, y| x.wrapping_mul(y))?,
+            Op::Div => self.divide(op, |x, y| x.wrapping_div(y))?,
+            Op::Mod => self.divide(op, |x, y| x.wrapping_rem(y))?,
+            Op::Shl => self.binary(op, |x, y| if (0..16).contains(&y) { ((x as u16) << y) as i16 } else { 0 })?,
+            Op::Shr => self.binary(op, |x, y| if (0..16).contains(&y) { ((x as u16) >> y) as i16 } else { 0 })?,
+            Op::Neg => {
+                let x = self.pop(op)?;
+                self.push(x.wrapping_neg())?;
+            }
+            Op::Not => {
+                let x = self.pop(op)?;
+                self.push(!x)?;
+            }
+            Op::Push(seg, idx) => {
+                let value = match seg {
+                    Segment::Constant => *idx as i16,
+                    _ => self.ram[self.address(*seg, *idx)?],
+                };
+                self.push(value)?;
+            }
+            Op::Pop(seg, idx) => {
+                let addr = self.address(*seg, *idx)?;
+                let value = self.pop(op)?;
+                self.ram[addr] = value;
+            }
+            Op::Label(_) => {}
+            Op::Goto(label) => {
+                let target = self.target(label, ErrorKind::UnknownLabel)?;
+                let is_loop = target == pc || (target + 1 == pc && matches!(self.program.commands[target].op, Op::Label(_)));
+                if is_loop {
+                    self.halted = true;
+                }
+                next = target;
+            }
+            Op::IfGoto(label) => {
+                if self.pop(op)? != 0 {
+                    next = self.target(label, ErrorKind::UnknownLabel)?;
+                }
+            }
+            Op::Function(_, nlocals) => {
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.nlocals = *nlocals;
+                }
+                for _ in 0..*nlocals {
+                    self.push(0)?;
+                }
+            }
+            Op::Call(name, nargs) => {
+                if (self.ram[SP] as i32) - nargs < self.stack_base() {
+                    return Err(self.error(ErrorKind::StackUnderflow, format!("stack underflow in {}", op)));
+                }
+                let sp = self.ram[SP] as usize;
+                let args = self.ram[sp - *nargs as usize..sp].to_vec();
+                if self.intercept(name, &args) {
+                    self.halted = true;
+                } else if let Some(target) = self.program.targets[pc] {
+                    self.push_frame(name, *nargs, Some(pc + 1))?;
+                    next = target;
+                } else {
+                    next = self.call_native(name, &args)?.unwrap_or(pc);
+                }
+            }
+            Op::Return => match self.ret()? {
+                Some(return_pc) => next = return_pc,
+                None => self.halted = true,
+            },
+        }
+
+        self.steps += 1;
+        if !self.halted {
+            self.pc = next;
+            self.halted = next >= self.program.len();
+        }
+
+        Ok(())
+    }
+
+    fn error(&self, kind: ErrorKind, message: String) -> RuntimeError {
+        let (file, line) = self.location().unwrap_or(("", 0));
+        RuntimeError {
+            kind,
+            file: file.to_string(),
+            line,
+            message,
+        }
+    }
+
+    fn target(&self, name: &str, kind: ErrorKind) -> Result<usize, RuntimeError> {
+        self.program.targets[self.pc].ok_or_else(|| {
+            let what = if kind == ErrorKind::UnknownLabel { "label" } else { "function" };
+            self.error(kind, format!("unknown {} {}", what, name))
+        })
+    }
+
+    // Lowest SP the current function may pop down to
+    fn stack_base(&self) -> i32 {
+        match self.frames.last() {
+            Some(frame) => self.ram[LCL] as i32 + frame.nlocals,
+            None => STACK_BASE as i32,
+        }
+    }
+
+    fn address(&self, seg: Segment, idx: Index) -> Result<usize, RuntimeError> {
+        let frame = self.frames.last();
+        let out_of_bounds = |len: i32| {
+            self.error(
+                ErrorKind::SegmentOutOfBounds,
+                format!("{} {} out of bounds, segment has {} entries", seg, idx, len),
+            )
+        };
+
+        let addr = match seg {
+            Segment::Local => {
+                if let Some(f) = frame.filter(|f| idx >= f.nlocals) {
+                    return Err(out_of_bounds(f.nlocals));
+                }
+                self.ram[LCL] as i32 + idx
+            }
+            Segment::Argument => {
+                if let Some(f) = frame.filter(|f| idx >= f.nargs) {
+                    return Err(out_of_bounds(f.nargs));
+                }
+                self.ram[ARG] as i32 + idx
+            }
+            Segment::This => self.ram[THIS] as i32 + idx,
+            Segment::That => self.ram[THAT] as i32 + idx,
+            Segment::Pointer => (THIS as i32) + idx,
+            Segment::Temp => (TEMP as i32) + idx,
+            Segment::Static => {
+                let file = self.program.file_of[self.pc];
+                let addr = self.program.static_base[file] + idx;
+                if addr >= STATIC_END {
+                    return Err(self.error(
+                        ErrorKind::SegmentOutOfBounds,
+                        format!("static {} out of bounds, the static segments exceed RAM[{}]", idx, STATIC_END - 1),
+                    ));
+                }
+                addr
+            }
+            Segment::Constant => unreachable!("constant has no address"),
+        };
+
+        if !(0..RAM_SIZE as i32).contains(&addr) {
+            return Err(self.error(
+                ErrorKind::SegmentOutOfBounds,
+                format!("{} {} at address {} is outside of RAM", seg, idx, addr),
+            ));
+        }
+
+        Ok(addr as usize)
+    }
+
+    fn push(&mut self, value: i16) -> Result<(), RuntimeError> {
+        // SP must stay a valid address after the push
+        let sp = self.ram[SP] as u16 as usize;
+        if sp + 1 >= RAM_SIZE {
+            return Err(self.error(ErrorKind::StackOverflow, "stack overflow".to_string()));
+        }
+        self.ram[sp] = value;
+        self.ram[SP] += 1;
+
+        Ok(())
+    }
+
+    fn pop(&mut self, op: &Op) -> Result<i16, RuntimeError> {
+        if (self.ram[SP] as i32) <= self.stack_base() {
+            return Err(self.error(ErrorKind::StackUnderflow, format!("stack underflow in {}", op)));
+        }
+        self.ram[SP] -= 1;
+
+        Ok(self.ram[self.ram[SP] as usize])
+    }
+
+    fn binary(&mut self, op: &Op, f: impl Fn(i16, i16) -> i16) -> Result<(), RuntimeError> {
+        let y = self.pop(op)?;
+        let x = self.pop(op)?;
+        self.push(f(x, y))
+    }
+
+    // Like Math.divide, division by zero calls Sys.error(3) and halts
+    fn divide(&mut self, op: &Op, f: impl Fn(i16, i16) -> i16) -> Result<(), RuntimeError> {
+        let y = self.pop(op)?;
+        let x = self.pop(op)?;
+        if y == 0 {
+            if let Err(Interrupt::Error(err)) = self.invoke("Sys.error", &[3]) {
+                return Err(err);
+            }
+            self.halted = true;
+            return Ok(());
+        }
+
+        self.push(f(x, y))
+    }
+
+    // Pop the arguments and run a native function called from VM code,
+    // returns where to continue or None to retry when waiting for input
+    fn call_native(&mut self, name: &str, args: &[i16]) -> Result<Option<usize>, RuntimeError> {
+        let Some(native) = os::native(name) else {
+            return Err(self.error(ErrorKind::UnknownFunction, format!("unknown function {}", name)));
+        };
+
+        let pc = self.pc;
+        self.ram[SP] -= args.len() as i16;
+        match native(self, args) {
+            Ok(value) => {
+                self.push(value)?;
+                Ok(Some(pc + 1))
+            }
+            Err(Interrupt::Halt) => {
+                self.halted = true;
+                Ok(Some(pc))
+            }
+            Err(Interrupt::Blocked) => {
+                self.ram[SP] += args.len() as i16;
+                self.waiting = true;
+                Ok(None)
+            }
+            Err(Interrupt::TailCall(callee)) => {
+                let Some(target) = self.program.function(&callee) else {
+                    return Err(self.error(ErrorKind::UnknownFunction, format!("unknown function {}", callee)));
+                };
+                self.push_frame(&callee, 0, Some(pc + 1))?;
+                Ok(Some(target))
+            }
+            Err(Interrupt::Error(err)) => Err(err),
+        }
+    }
+
+    // Calls the emulator handles itself, returns whether to halt
+    fn intercept(&mut self, name: &str, args: &[i16]) -> bool {
+        match name {
+            "Sys.halt" => true,
+            "Sys.error" => {
+                self.os_error = args.first().copied();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    ///
+    /// Call `name` from native code and return its result, running
+    /// VM code until it returns if the function has any.
+    ///
+    pub(crate) fn invoke(&mut self, name: &str, args: &[i16]) -> Result<i16, Interrupt> {
+        if self.intercept(name, args) {
+            return Err(Interrupt::Halt);
+        }
+
+        let Some(target) = self.program.function(name) else {
+            let Some(native) = os::native(name) else {
+                return Err(self.error(ErrorKind::UnknownFunction, format!("unknown function {}", name)).into());
+            };
+            return match native(self, args) {
+                Err(Interrupt::TailCall(callee)) => self.invoke(&callee, &[]),
+                res => res,
+            };
+        };
+
+        let pc = self.pc;
+        let depth = self.frames.len();
+        for arg in args {
+            self.push(*arg)?;
+        }
+        self.push_frame(name, args.len() as i32, Some(pc))?;
+        self.pc = target;
+
+        while self.frames.len() > depth {
+            if self.halted {
+                return Err(Interrupt::Halt);
+            }
+            if self.steps >= self.limit {
+                self.pc = pc;
+                let message = format!("step limit reached in {} called by native code", name);
+                return Err(self.error(ErrorKind::StepLimit, message).into());
+            }
+            self.step()?;
+        }
+
+        self.pc = pc;
+        Ok(self.pop(&Op::Return)?)
+    }
+
+    /// RAM[addr] as accessed through `that` by the OS code.
+    pub(crate) fn peek(&self, addr: i16) -> Result<i16, Interrupt> {
+        if addr < 0 {
+            let message = format!("address {} is outside of RAM", addr);
+            return Err(self.error(ErrorKind::SegmentOutOfBounds, message).into());
+        }
+
+        Ok(self.ram[addr as usize])
+    }
+
+    pub(crate) fn poke(&mut self, addr: i16, value: i16) -> Result<(), Interrupt> {
+        self.peek(addr)?;
+        self.ram[addr as usize] = value;
+
+        Ok(())
+    }
+
+    fn push_frame(&mut self, name: &str, nargs: i32, return_pc: Option<usize>) -> Result<(), RuntimeError> {
+        self.push(return_pc.unwrap_or(0) as i16)?;
+        for reg in [LCL, ARG, THIS, THAT] {
+            self.push(self.ram[reg])?;
+        }
+        self.ram[ARG] = self.ram[SP].wrapping_sub(nargs as i16).wrapping_sub(5);
+        self.ram[LCL] = self.ram[SP];
+
+        self.frames.push(Frame {
+            function: name.to_string(),
+            return_pc,
+            nargs,
+            nlocals: 0,
+        });
+
+        Ok(())
+    }
+
+    // Returns where to continue, None when leaving the entry function
+    fn ret(&mut self) -> Result<Option<usize>, RuntimeError> {
+        let frame = self.ram[LCL] as i32;
+        if frame < 5 {
+            return Err(self.error(ErrorKind::StackUnderflow, "return without a frame".to_string()));
+        }
+
+        let value = self.pop(&Op::Return)?;
+        let arg = self.ram[ARG] as u16 as usize;
+        if arg >= RAM_SIZE {
+            return Err(self.error(ErrorKind::SegmentOutOfBounds, format!("argument 0 at address {} is outside of RAM", arg)));
+        }
+        self.ram[arg] = value;
+        self.ram[SP] = self.ram[ARG].wrapping_add(1);
+
+        let frame = frame as usize;
+        self.ram[THAT] = self.ram[frame - 1];
+        self.ram[THIS] = self.ram[frame - 2];
+        self.ram[ARG] = self.ram[frame - 3];
+        self.ram[LCL] = self.ram[frame - 4];
+
+        Ok(self.frames.pop().and_then(|f| f.return_pc))
+    }
+}
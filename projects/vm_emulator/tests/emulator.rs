@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::Path;
+
+use hack_vm::{parse, Module, Segment};
+use vm_emulator::{ErrorKind, Vm, SP};
+
+fn load_dir(dir: &str) -> Vec<Module> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(dir);
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("vm"))
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|p| {
+            let name = p.file_stem().unwrap().to_str().unwrap();
+            parse(name, &fs::read_to_string(p).unwrap()).unwrap()
+        })
+        .collect()
+}
+
+fn boot(modules: &[Module]) -> Vm {
+    let mut vm = Vm::new(modules);
+    vm.boot("Sys.init").unwrap();
+    vm.run(1_000_000).unwrap();
+    assert!(vm.is_halted());
+
+    vm
+}
+
+#[test]
+fn runs_project_08_programs() {
+    let vm = boot(&load_dir("08/FunctionCalls/FibonacciElement"));
+    assert_eq!(vm.ram()[SP], 262);
+    assert_eq!(vm.ram()[261], 3);
+
+    let vm = boot(&load_dir("08/FunctionCalls/StaticsTest"));
+    assert_eq!(vm.ram()[SP], 263);
+    assert_eq!(&vm.ram()[261..263], &[-2, 8]);
+}
+
+#[test]
+fn exposes_frames_and_segments() {
+    let src = "\
+function Sys.init 0
+push constant 7
+push constant 8
+call Main.f 2
+label END
+goto END
+function Main.f 2
+push argument 1
+pop local 1
+label BREAK
+push local 1
+return
+";
+    let mut vm = Vm::new(&[parse("Sys", src).unwrap()]);
+    vm.boot("Sys.init").unwrap();
+    while vm.location() != Some(("Sys", 10)) {
+        vm.step().unwrap();
+    }
+
+    let frames: Vec<_> = vm.frames().iter().map(|f| f.function.as_str()).collect();
+    assert_eq!(frames, ["Sys.init", "Main.f"]);
+    assert_eq!(vm.frames()[1].nargs, 2);
+    assert_eq!(vm.frames()[1].nlocals, 2);
+    assert_eq!(vm.segment(Segment::Argument, 0), Some(7));
+    assert_eq!(vm.segment(Segment::Local, 1), Some(8));
+    assert_eq!(vm.segment(Segment::Local, 2), None);
+    assert!(vm.stack().is_empty());
+
+    vm.run(100).unwrap();
+    assert!(vm.is_halted());
+    assert_eq!(vm.frames().len(), 1);
+    assert_eq!(vm.stack(), &[8]);
+}
+
+fn error(src: &str) -> (ErrorKind, usize) {
+    let mut vm = Vm::new(&[parse("Main", src).unwrap()]);
+    vm.boot("Main.main").unwrap();
+    let err = vm.run(1_000_000).unwrap_err();
+    assert_eq!(err.file, "Main");
+
+    (err.kind, err.line)
+}
+
+#[test]
+fn reports_runtime_errors() {
+    assert_eq!(error("function Main.main 0\nadd\n"), (ErrorKind::StackUnderflow, 2));
+    assert_eq!(error("function Main.main 1\npush local 1\n"), (ErrorKind::SegmentOutOfBounds, 2));
+    assert_eq!(error("function Main.main 0\npush argument 0\n"), (ErrorKind::SegmentOutOfBounds, 2));
+    assert_eq!(
+        error("function Main.main 0\npush constant 0\nnot\npop pointer 1\npush that 0\n"),
+        (ErrorKind::SegmentOutOfBounds, 5)
+    );
+    assert_eq!(error("function Main.main 0\n\ncall Foo.bar 0\n"), (ErrorKind::UnknownFunction, 3));
+    assert_eq!(error("function Main.main 0\ngoto NOWHERE\n"), (ErrorKind::UnknownLabel, 2));
+    assert_eq!(error("function Main.main 0\ncall Main.main 0\nreturn\n"), (ErrorKind::StackOverflow, 2));
+    assert_eq!(error("function Main.main 0\nlabel L\npush constant 1\ngoto L\n"), (ErrorKind::StackOverflow, 3));
+}
@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::Path;
+
+use hack_vm::{parse, Module};
+use vm_emulator::{Vm, TEMP};
+
+const CLASSES: [&str; 8] = ["Array", "Keyboard", "Math", "Memory", "Output", "Screen", "String", "Sys"];
+
+const MAIN: &str = "\
+function Main.main 1
+push constant 12
+call String.new 1
+pop local 0
+push local 0
+push constant 4321
+neg
+call String.setInt 2
+pop temp 0
+push local 0
+call Output.printString 1
+pop temp 0
+push constant 181
+call Math.sqrt 1
+push constant 7
+neg
+call Math.multiply 2
+push constant 3
+call Math.divide 2
+call Output.printInt 1
+pop temp 0
+call Output.println 0
+pop temp 0
+call Output.backSpace 0
+pop temp 0
+push constant 100
+push constant 100
+push constant 40
+call Screen.drawCircle 3
+pop temp 0
+push constant 10
+push constant 200
+push constant 300
+push constant 20
+call Screen.drawLine 4
+pop temp 0
+push constant 0
+call Screen.setColor 1
+pop temp 0
+push constant 70
+push constant 90
+push constant 130
+push constant 110
+call Screen.drawRectangle 4
+pop temp 0
+push local 0
+call String.dispose 1
+pop temp 0
+push constant 0
+return
+";
+
+// tools/OS minus the classes to run natively
+fn os_modules(native: &[&str]) -> Vec<Module> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tools/OS");
+    CLASSES
+        .iter()
+        .filter(|class| !native.contains(class))
+        .map(|class| parse(class, &fs::read_to_string(dir.join(format!("{}.vm", class))).unwrap()).unwrap())
+        .collect()
+}
+
+fn run(main: &str, native: &[&str]) -> Vm {
+    let mut modules = vec![parse("Main", main).unwrap()];
+    modules.extend(os_modules(native));
+
+    let mut vm = Vm::new(&modules);
+    vm.boot("Sys.init").unwrap();
+    vm.run(10_000_000).unwrap();
+    assert!(vm.is_halted());
+
+    vm
+}
+
+// Heap and screen
+fn memory(vm: &Vm) -> &[i16] {
+    &vm.ram()[2048..24576]
+}
+
+#[test]
+fn native_classes_match_vm_code() {
+    let expected = run(MAIN, &[]);
+    assert!(memory(&expected).iter().any(|&w| w != 0));
+
+    let all = run(MAIN, &CLASSES);
+    assert!(memory(&all) == memory(&expected), "all classes native");
+    assert!(all.steps() < 1000);
+
+    for class in CLASSES {
+        let vm = run(MAIN, &[class]);
+        assert!(memory(&vm) == memory(&expected), "native {}", class);
+    }
+}
+
+#[test]
+fn reports_sys_error_codes() {
+    let divide = "function Main.main 0\npush constant 1\npush constant 0\ncall Math.divide 2\nreturn\n";
+    let char_at = "function Main.main 0\npush constant 3\ncall String.new 1\npush constant 0\ncall String.charAt 2\nreturn\n";
+    for (main, code) in [(divide, 3), (char_at, 15)] {
+        let expected = run(main, &[]);
+        assert_eq!(expected.os_error(), Some(code));
+
+        let vm = run(main, &CLASSES);
+        assert_eq!(vm.os_error(), Some(code));
+        assert!(memory(&vm) == memory(&expected));
+    }
+}
+
+// `x cmd y` into Main.0, RAM[16]
+fn binary(x: i16, y: i16, cmd: &str) -> String {
+    let push = |v: i16| if v < 0 { format!("push constant {}\nneg", -v) } else { format!("push constant {}", v) };
+    format!("function Main.main 0\n{}\n{}\n{}\npop static 0\npush constant 0\nreturn\n", push(x), push(y), cmd)
+}
+
+#[test]
+fn extension_commands_match_math() {
+    let pairs = [(7, 3), (-7, 3), (7, -3), (-32767, -1), (32767, 2), (-12345, 100), (0, 5)];
+    for (op, function) in [("mul", "Math.multiply"), ("div", "Math.divide")] {
+        for (x, y) in pairs {
+            let expected = run(&binary(x, y, &format!("call {} 2", function)), &[]);
+            let vm = run(&binary(x, y, op), &CLASSES);
+            assert_eq!(vm.ram()[16], expected.ram()[16], "{} {} {}", x, op, y);
+        }
+    }
+
+    let vm = run(&binary(1, 0, "mod"), &CLASSES);
+    assert_eq!(vm.os_error(), Some(3));
+}
+
+#[test]
+fn waits_for_typed_keys() {
+    let main = "\
+function Main.main 0
+push constant 0
+call String.new 1
+call Keyboard.readInt 1
+pop temp 0
+push constant 0
+return
+";
+    let mut vm = Vm::new(&[parse("Main", main).unwrap()]);
+    vm.boot("Sys.init").unwrap();
+    vm.type_text("-1");
+    vm.run(1000).unwrap();
+    assert!(vm.is_waiting());
+    assert!(!vm.is_halted());
+
+    vm.type_keys(&[50, 129, 51]);
+    vm.type_text("\n");
+    vm.run(1000).unwrap();
+    assert!(vm.is_halted());
+    assert_eq!(vm.ram()[TEMP], -13);
+}
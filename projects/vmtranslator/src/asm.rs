@@ -0,0 +1,112 @@
+use std::fmt;
+use std::io::{self, Write};
+
+///
+/// Where the code starting at ROM `address` comes from. `file`
+/// is empty for code that no VM command was translated to, like
+/// the bootstrap code and the shared routines.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLoc {
+    pub address: i32,
+    pub file: String,
+    pub line: usize,
+    pub function: String,
+}
+
+impl fmt::Display for SourceLoc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file = if self.file.is_empty() { "-".to_string() } else { format!("{}.vm", self.file) };
+        let function = if self.function.is_empty() { "-" } else { &self.function };
+        write!(f, "{} {} {} {}", self.address, file, self.line, function)
+    }
+}
+
+///
+/// Sink for generated assembly. Keeps count of the emitted
+/// instructions, skipping comments and label declarations,
+/// so the templates don't have to.
+///
+/// `writeln!` works on it directly through `write_fmt`.
+///
+pub struct AsmWriter {
+    out: Box<dyn Write>,
+    pub instr_cnt: i32,
+    /// Write a `// File.vm:line` comment for every VM command.
+    pub annotate: bool,
+    /// Origin of the code from each address on, by address.
+    pub source_map: Vec<SourceLoc>,
+    /// Copy of the assembly written, kept to assemble it
+    /// in memory.
+    pub text: Option<String>,
+}
+
+impl AsmWriter {
+    pub fn new(out: Box<dyn Write>) -> AsmWriter {
+        AsmWriter {
+            out,
+            instr_cnt: 0,
+            annotate: false,
+            source_map: Vec::new(),
+            text: None,
+        }
+    }
+
+    pub fn write_fmt(&mut self, args: fmt::Arguments) -> io::Result<()> {
+        let line = fmt::format(args);
+        let instr = line.trim();
+        if !(instr.is_empty() || instr.starts_with("//") || instr.starts_with('(')) {
+            self.instr_cnt += 1;
+        }
+        if let Some(text) = &mut self.text {
+            text.push_str(&line);
+        }
+
+        self.out.write_all(line.as_bytes())
+    }
+
+    ///
+    /// Attribute the code written from now on to `file:line`
+    /// in `function`, an empty `file` for generated code.
+    ///
+    pub fn mark(&mut self, file: &str, line: usize, function: &str) -> io::Result<()> {
+        if self.annotate && !file.is_empty() {
+            writeln!(self, "// {}.vm:{}", file, line)?;
+        }
+
+        let loc = SourceLoc {
+            address: self.instr_cnt,
+            file: file.to_string(),
+            line,
+            function: function.to_string(),
+        };
+        // A previous command without any code of its own is dropped
+        if self.source_map.last().is_some_and(|last| last.address == loc.address) {
+            self.source_map.pop();
+        }
+        if self.source_map.last().is_none_or(|last| (&last.file, last.line) != (&loc.file, loc.line)) {
+            self.source_map.push(loc);
+        }
+
+        Ok(())
+    }
+
+    /// Where the code at ROM `address` comes from.
+    pub fn locate(&self, address: i32) -> Option<&SourceLoc> {
+        let next = self.source_map.partition_point(|loc| loc.address <= address);
+        next.checked_sub(1).map(|i| &self.source_map[i])
+    }
+
+    /// Write the source map, one `address file line function` entry per line.
+    pub fn write_source_map(&self, out: &mut dyn Write) -> io::Result<()> {
+        for loc in &self.source_map {
+            writeln!(out, "{}", loc)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
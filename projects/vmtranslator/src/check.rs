@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use hack_vm::{Command, Module, Op, Segment};
+
+///
+/// Problem found by `check`, located at the offending command.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.vm:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+// Commands of one `function` up to the next one. Code before
+// the first `function` of a file has no name and no locals.
+struct Function<'a> {
+    name: &'a str,
+    locals: Option<i32>,
+    body: &'a [Command],
+}
+
+fn functions(module: &Module) -> Vec<Function<'_>> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    let mut name = "";
+    let mut locals = None;
+
+    for (i, c) in module.commands.iter().enumerate() {
+        if let Op::Function(f, n) = &c.op {
+            if i > start || locals.is_some() {
+                res.push(Function { name, locals, body: &module.commands[start..i] });
+            }
+            start = i + 1;
+            name = f;
+            locals = Some(*n);
+        }
+    }
+
+    if start < module.commands.len() || locals.is_some() {
+        res.push(Function { name, locals, body: &module.commands[start..] });
+    }
+
+    res
+}
+
+// Values taken from and left on the stack
+pub fn stack_effect(op: &Op) -> (i32, i32) {
+    match op {
+        Op::Add | Op::Sub | Op::Eq | Op::Gt | Op::Lt | Op::And | Op::Or => (2, 1),
+        Op::Mul | Op::Div | Op::Mod | Op::Shl | Op::Shr => (2, 1),
+        Op::Neg | Op::Not => (1, 1),
+        Op::Push(_, _) => (0, 1),
+        Op::Pop(_, _) | Op::IfGoto(_) => (1, 0),
+        Op::Call(_, nargs) => (*nargs, 1),
+        Op::Return => (1, 0),
+        Op::Label(_) | Op::Goto(_) | Op::Function(_, _) => (0, 0),
+    }
+}
+
+struct Checker<'a> {
+    file: &'a str,
+    res: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, c: &Command, message: String) {
+        self.res.push(Diagnostic {
+            file: self.file.to_string(),
+            line: c.line,
+            message,
+        });
+    }
+
+    fn segments(&mut self, f: &Function, nargs: Option<i32>) {
+        for c in f.body {
+            let (seg, idx) = match &c.op {
+                Op::Push(seg, idx) | Op::Pop(seg, idx) => (*seg, *idx),
+                _ => continue,
+            };
+
+            match (seg, f.locals, nargs) {
+                (Segment::Local, Some(n), _) if idx >= n => {
+                    self.error(c, format!("local {} out of range, {} declares {} locals", idx, f.name, n));
+                }
+                (Segment::Argument, _, Some(n)) if idx >= n => {
+                    self.error(c, format!("argument {} out of range, {} is called with {} arguments", idx, f.name, n));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Walk every path through the body tracking the stack depth
+    fn stack(&mut self, f: &Function) {
+        let labels: HashMap<&str, usize> = f
+            .body
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| match &c.op {
+                Op::Label(l) => Some((l.as_str(), i)),
+                _ => None,
+            })
+            .collect();
+
+        let mut depth_at: Vec<Option<i32>> = vec![None; f.body.len()];
+        let mut pending = vec![(0, 0)];
+        while let Some((mut pc, mut depth)) = pending.pop() {
+            while let Some(c) = f.body.get(pc) {
+                if let Some(known) = depth_at[pc] {
+                    if known != depth {
+                        let label = if let Op::Label(l) = &c.op { l.as_str() } else { "" };
+                        self.error(c, format!("stack depth at label {} is both {} and {}", label, known, depth));
+                    }
+                    break;
+                }
+                depth_at[pc] = Some(depth);
+
+                let (taken, left) = stack_effect(&c.op);
+                if depth < taken {
+                    match &c.op {
+                        Op::Return => self.error(c, "return with an empty stack".to_string()),
+                        op => self.error(c, format!("stack underflow in {}", op)),
+                    }
+                }
+                depth = (depth - taken).max(0) + left;
+
+                match &c.op {
+                    Op::Goto(l) | Op::IfGoto(l) => match labels.get(l.as_str()) {
+                        Some(&target) => pending.push((target, depth)),
+                        None => self.error(c, format!("undefined label {} in {}", l, f.name)),
+                    },
+                    _ => {}
+                }
+
+                if matches!(c.op, Op::Goto(_) | Op::Return) {
+                    break;
+                }
+                pc += 1;
+            }
+        }
+    }
+}
+
+///
+/// Verify stack balance, jump targets and segment indices of
+/// every function, and that all calls of a function pass the
+/// same number of arguments. `roots` are called with none by
+/// the bootstrap code. Calls to undefined functions are left
+/// to the linker. The result is sorted by file and line.
+///
+pub fn check(modules: &[Module], roots: &[&str]) -> Vec<Diagnostic> {
+    let mut res = Vec::new();
+
+    let mut nargs: HashMap<&str, (i32, &str, usize)> =
+        roots.iter().map(|name| (*name, (0, "", 0))).collect();
+    for module in modules {
+        for c in &module.commands {
+            let Op::Call(callee, n) = &c.op else { continue };
+            match nargs.get(callee.as_str()) {
+                Some(&(m, file, line)) if m != *n => {
+                    let first = if file.is_empty() { "the bootstrap code".to_string() } else { format!("{}.vm:{}", file, line) };
+                    res.push(Diagnostic {
+                        file: module.name.clone(),
+                        line: c.line,
+                        message: format!("{} called with {} arguments, but with {} at {}", callee, n, m, first),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    nargs.insert(callee, (*n, &module.name, c.line));
+                }
+            }
+        }
+    }
+
+    for module in modules {
+        let mut checker = Checker { file: &module.name, res: Vec::new() };
+        for f in functions(module) {
+            checker.stack(&f);
+            let n = nargs.get(f.name).map(|(n, _, _)| *n);
+            checker.segments(&f, n);
+        }
+        res.extend(checker.res);
+    }
+    res.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+
+    res
+}
@@ -0,0 +1,203 @@
+//!
+//! Runtime checks and counters added by `Options::instrument`.
+//! They use a region of RAM starting at `Instrumentation::base`:
+//!
+//!   RAM[base]          error code once a check failed, see below
+//!   RAM[base + 1]      id of the last function entered
+//!   RAM[base + 2 + i]  number of calls of the function with id i
+//!
+//! By default the region ends right below the screen, at the top
+//! of the heap. The OS allocates from the bottom of the heap, so
+//! only a program that fills the heap reaches it; one that does
+//! can move it elsewhere with `--instrument-base`.
+//!
+//! Function ids number the functions in the order they are
+//! translated, the asm file starts with a table of them. A
+//! failed check stores its error code and halts in `$$HALT`.
+//!
+
+use crate::asm::AsmWriter;
+use crate::ir::{Instr, Unit};
+use hack_vm::Op;
+
+/// End of the RAM the CPU emulator allows, the screen included.
+pub const RAM_END: i32 = 24576;
+/// End of the default region, the first cell of the screen.
+const HEAP_END: i32 = 16384;
+
+/// SP went past the end of the stack into the heap.
+pub const STACK_OVERFLOW: i32 = 1;
+/// A function popped values below its local segment.
+pub const STACK_UNDERFLOW: i32 = 2;
+
+/// Start of the heap, the stack ends right below.
+pub const STACK_END: i32 = 2048;
+
+const OVERFLOW_ROUTINE: &str = "$$STACK_OVERFLOW";
+const UNDERFLOW_ROUTINE: &str = "$$STACK_UNDERFLOW";
+const ERROR_ROUTINE: &str = "$$ERROR";
+const HALT_LOOP: &str = "$$HALT";
+
+///
+/// Which instrumentation to emit.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Instrumentation {
+    /// Check for SP > 2048 on function entry, once the
+    /// locals are allocated.
+    pub overflow: bool,
+    /// Check on return that the working stack still holds
+    /// the return value above the locals.
+    pub underflow: bool,
+    /// Count the calls of every function.
+    pub call_counts: bool,
+    /// Store the id of every function entered.
+    pub last_function: bool,
+    /// First RAM cell of the region, see `default_base`.
+    pub base: i32,
+}
+
+impl Instrumentation {
+    pub fn all() -> Self {
+        Instrumentation {
+            overflow: true,
+            underflow: true,
+            call_counts: true,
+            last_function: true,
+            base: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !(self.has_checks() || self.call_counts || self.last_function)
+    }
+
+    pub fn has_checks(&self) -> bool {
+        self.overflow || self.underflow
+    }
+
+    pub fn error_code(&self) -> i32 {
+        self.base
+    }
+
+    pub fn last_function(&self) -> i32 {
+        self.base + 1
+    }
+
+    pub fn call_count(&self, id: i32) -> i32 {
+        self.base + 2 + id
+    }
+
+    /// Cells of the region for a program of `nfunctions` functions.
+    pub fn size(nfunctions: usize) -> i32 {
+        2 + nfunctions as i32
+    }
+
+    /// The base putting the region at the top of the heap.
+    pub fn default_base(nfunctions: usize) -> i32 {
+        HEAP_END - Self::size(nfunctions)
+    }
+}
+
+/// Number of functions, which get the ids 0..n.
+pub fn function_count(units: &[Unit]) -> usize {
+    functions(units).count()
+}
+
+fn functions(units: &[Unit]) -> impl Iterator<Item = &String> {
+    units.iter().flat_map(|u| &u.code).filter_map(|item| match &item.instr {
+        Instr::Op(Op::Function(name, _)) => Some(name),
+        _ => None,
+    })
+}
+
+// Table of the RAM cells and function ids used
+pub fn header(buf: &mut AsmWriter, units: &[Unit], inst: &Instrumentation) -> std::io::Result<()> {
+    if inst.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(buf, "// instrumentation")?;
+    if inst.has_checks() {
+        writeln!(buf, "// RAM[{}] = error code: {} stack overflow, {} stack underflow", inst.error_code(), STACK_OVERFLOW, STACK_UNDERFLOW)?;
+    }
+    if inst.last_function {
+        writeln!(buf, "// RAM[{}] = id of the last function entered", inst.last_function())?;
+    }
+    if inst.call_counts {
+        writeln!(buf, "// RAM[{} + id] = calls of the function", inst.call_count(0))?;
+    }
+    if inst.last_function || inst.call_counts {
+        for (id, name) in functions(units).enumerate() {
+            writeln!(buf, "// function id {} = {}", id, name)?;
+        }
+    }
+
+    Ok(())
+}
+
+// After the locals of function `id` have been allocated
+pub fn function_entry(buf: &mut AsmWriter, id: i32, inst: &Instrumentation) -> std::io::Result<()> {
+    if inst.call_counts {
+        writeln!(buf, "@{}", inst.call_count(id))?;
+        writeln!(buf, "M=M+1")?;
+    }
+    if inst.last_function {
+        writeln!(buf, "@{}", id)?;
+        writeln!(buf, "D=A")?;
+        writeln!(buf, "@{}", inst.last_function())?;
+        writeln!(buf, "M=D")?;
+    }
+    if inst.overflow {
+        writeln!(buf, "@SP")?;
+        writeln!(buf, "D=M")?;
+        writeln!(buf, "@{}", STACK_END)?;
+        writeln!(buf, "D=D-A")?;
+        writeln!(buf, "@{}", OVERFLOW_ROUTINE)?;
+        writeln!(buf, "D;JGT")?;
+    }
+
+    Ok(())
+}
+
+// Before returning from a function with `nlocals` locals,
+// SP - LCL must be at least nlocals + 1
+pub fn before_return(buf: &mut AsmWriter, nlocals: i32, inst: &Instrumentation) -> std::io::Result<()> {
+    if inst.underflow {
+        writeln!(buf, "@LCL")?;
+        writeln!(buf, "D=M")?;
+        writeln!(buf, "@SP")?;
+        writeln!(buf, "D=M-D")?;
+        writeln!(buf, "@{}", nlocals + 1)?;
+        writeln!(buf, "D=D-A")?;
+        writeln!(buf, "@{}", UNDERFLOW_ROUTINE)?;
+        writeln!(buf, "D;JLT")?;
+    }
+
+    Ok(())
+}
+
+// Error code into its RAM cell, then halt. Placed after
+// the end loop, nothing falls through into it.
+pub fn routines(buf: &mut AsmWriter, inst: &Instrumentation) -> std::io::Result<()> {
+    if !inst.has_checks() {
+        return Ok(());
+    }
+
+    writeln!(buf, "// instrumentation errors")?;
+    for (routine, code) in [(OVERFLOW_ROUTINE, STACK_OVERFLOW), (UNDERFLOW_ROUTINE, STACK_UNDERFLOW)] {
+        writeln!(buf, "({})", routine)?;
+        writeln!(buf, "@{}", code)?;
+        writeln!(buf, "D=A")?;
+        writeln!(buf, "@{}", ERROR_ROUTINE)?;
+        writeln!(buf, "0;JMP")?;
+    }
+    writeln!(buf, "({})", ERROR_ROUTINE)?;
+    writeln!(buf, "@{}", inst.error_code())?;
+    writeln!(buf, "M=D")?;
+    writeln!(buf, "({})", HALT_LOOP)?;
+    writeln!(buf, "@{}", HALT_LOOP)?;
+    writeln!(buf, "0;JMP")?;
+
+    Ok(())
+}
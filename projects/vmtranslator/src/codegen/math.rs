@@ -0,0 +1,392 @@
+//!
+//! Shared routines for the extension commands `mul`, `div`,
+//! `mod`, `shl` and `shr`. They compute the same results as
+//! the OS `Math` class, division by zero included.
+//!
+//! Like the shared comparisons they expect the return address
+//! in D and replace x and y on the stack with the result. Besides
+//! R13-R15 they use the words at SP and SP+1, free once y has
+//! been popped, for the return address and a sign flag.
+//!
+
+use hack_vm::Op;
+
+use super::{call, END_LOOP};
+use crate::asm::AsmWriter;
+
+const MUL_ROUTINE: &str = "$$MUL";
+const DIV_ROUTINE: &str = "$$DIV";
+const MOD_ROUTINE: &str = "$$MOD";
+const SHL_ROUTINE: &str = "$$SHL";
+const SHR_ROUTINE: &str = "$$SHR";
+
+/// Code passed to `Sys.error` on division by zero, as `Math.divide` does.
+const DIVIDE_BY_ZERO: i32 = 3;
+
+///
+/// The routines referenced by the emitted code.
+///
+#[derive(Debug, Default)]
+pub struct Routines {
+    mul: bool,
+    div: bool,
+    modulo: bool,
+    shl: bool,
+    shr: bool,
+    /// Whether the program defines `Sys.error`. Without it
+    /// division by zero just halts.
+    pub sys_error: bool,
+}
+
+impl Routines {
+    ///
+    /// Mark the routine of `op` as used and return its name,
+    /// None if `op` is not an extension command.
+    ///
+    pub fn use_op(&mut self, op: &Op) -> Option<&'static str> {
+        let (used, routine) = match op {
+            Op::Mul => (&mut self.mul, MUL_ROUTINE),
+            Op::Div => (&mut self.div, DIV_ROUTINE),
+            Op::Mod => (&mut self.modulo, MOD_ROUTINE),
+            Op::Shl => (&mut self.shl, SHL_ROUTINE),
+            Op::Shr => (&mut self.shr, SHR_ROUTINE),
+            _ => return None,
+        };
+        *used = true;
+
+        Some(routine)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !(self.mul || self.div || self.modulo || self.shl || self.shr)
+    }
+
+    pub fn write(&self, buf: &mut AsmWriter) -> std::io::Result<()> {
+        if self.mul {
+            multiply(buf)?;
+        }
+        if self.div {
+            divide(buf, DIV_ROUTINE, false, self.sys_error)?;
+        }
+        if self.modulo {
+            divide(buf, MOD_ROUTINE, true, self.sys_error)?;
+        }
+        if self.shl {
+            shift_left(buf)?;
+        }
+        if self.shr {
+            shift_right(buf)?;
+        }
+
+        Ok(())
+    }
+}
+
+// R13 = x, R14 = y, *sp = return address with sp
+// pointing at y and x still below
+fn prologue(buf: &mut AsmWriter, routine: &str) -> std::io::Result<()> {
+    writeln!(buf, "({})", routine)?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "M=D")?;
+
+    // R14 = *(--sp) = y
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "AM=M-1")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "M=D")?;
+
+    // *sp = return address
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "M=D")?;
+
+    // R13 = *(sp - 1) = x
+    writeln!(buf, "A=A-1")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "M=D")?;
+
+    Ok(())
+}
+
+// goto *sp
+fn epilogue(buf: &mut AsmWriter) -> std::io::Result<()> {
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "0;JMP")?;
+
+    Ok(())
+}
+
+// Shift and add: for every bit R15 set in y, clear it from y
+// and add x shifted by the same amount. Stops once y is 0.
+fn multiply(buf: &mut AsmWriter) -> std::io::Result<()> {
+    let r = MUL_ROUTINE;
+    prologue(buf, r)?;
+
+    // *(sp - 1) = 0, R15 = 1
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=0")?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "M=1")?;
+
+    writeln!(buf, "({}.loop)", r)?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.end", r)?;
+    writeln!(buf, "D;JEQ")?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=D&M")?;
+    writeln!(buf, "@{}.next", r)?;
+    writeln!(buf, "D;JEQ")?;
+
+    // y -= bit; *(sp - 1) += x
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "M=M-D")?;
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=D+M")?;
+
+    // x += x; bit += bit
+    writeln!(buf, "({}.next)", r)?;
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "M=D+M")?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "M=D+M")?;
+    writeln!(buf, "@{}.loop", r)?;
+    writeln!(buf, "0;JMP")?;
+
+    writeln!(buf, "({}.end)", r)?;
+    epilogue(buf)
+}
+
+// Long division of |x| by |y| as unsigned numbers. The bits of
+// |x| are shifted out of R13 into the remainder R15 while the
+// quotient is shifted into R13, *(sp - 1) counts the 16 steps.
+// The quotient, or the remainder for `mod`, is negated when
+// *(sp + 1) is set.
+fn divide(buf: &mut AsmWriter, r: &str, remainder: bool, sys_error: bool) -> std::io::Result<()> {
+    prologue(buf, r)?;
+
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.by_zero", r)?;
+    writeln!(buf, "D;JEQ")?;
+
+    // *(sp + 1) = sign; R13 = |x|; R14 = |y|
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M+1")?;
+    writeln!(buf, "M=0")?;
+    for (reg, flips) in [("R13", true), ("R14", !remainder)] {
+        writeln!(buf, "@{}", reg)?;
+        writeln!(buf, "D=M")?;
+        writeln!(buf, "@{}.{}_abs", r, reg)?;
+        writeln!(buf, "D;JGE")?;
+        writeln!(buf, "@{}", reg)?;
+        writeln!(buf, "M=-D")?;
+        if flips {
+            writeln!(buf, "@SP")?;
+            writeln!(buf, "A=M+1")?;
+            writeln!(buf, "M=!M")?;
+        }
+        writeln!(buf, "({}.{}_abs)", r, reg)?;
+    }
+
+    // R15 = 0; *(sp - 1) = 16
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "M=0")?;
+    writeln!(buf, "@16")?;
+    writeln!(buf, "D=A")?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=D")?;
+
+    // R15 = 2 * R15 + top bit of R13; R13 += R13
+    writeln!(buf, "({}.loop)", r)?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "M=D+M")?;
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "M=D+M")?;
+    writeln!(buf, "@{}.shifted", r)?;
+    writeln!(buf, "D;JGE")?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "M=M+1")?;
+    writeln!(buf, "({}.shifted)", r)?;
+
+    // Subtract if R15 >= R14 as unsigned numbers. The remainder is
+    // below |y| <= 32768 before the shift, so it is either at least
+    // 32768 or the signed difference can't overflow.
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.subtract", r)?;
+    writeln!(buf, "D;JLT")?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=D-M")?;
+    writeln!(buf, "@{}.next", r)?;
+    writeln!(buf, "D;JLT")?;
+    writeln!(buf, "({}.subtract)", r)?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "M=M-D")?;
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "M=M+1")?;
+
+    writeln!(buf, "({}.next)", r)?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "MD=M-1")?;
+    writeln!(buf, "@{}.loop", r)?;
+    writeln!(buf, "D;JGT")?;
+
+    // *(sp - 1) = result, negated if *(sp + 1) is set
+    writeln!(buf, "@{}", if remainder { "R15" } else { "R13" })?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=D")?;
+    writeln!(buf, "A=A+1")?;
+    writeln!(buf, "A=A+1")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.end", r)?;
+    writeln!(buf, "D;JEQ")?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=-M")?;
+    writeln!(buf, "({}.end)", r)?;
+    epilogue(buf)?;
+
+    // Sys.error(3) from the frame of the caller, never returns
+    writeln!(buf, "({}.by_zero)", r)?;
+    if sys_error {
+        writeln!(buf, "@{}", DIVIDE_BY_ZERO)?;
+        writeln!(buf, "D=A")?;
+        writeln!(buf, "@SP")?;
+        writeln!(buf, "A=M")?;
+        writeln!(buf, "M=D")?;
+        writeln!(buf, "@SP")?;
+        writeln!(buf, "M=M+1")?;
+        call(buf, "Sys.error", 1, &format!("{}.error", r))?;
+    }
+    writeln!(buf, "@{}", END_LOOP)?;
+    writeln!(buf, "0;JMP")?;
+
+    Ok(())
+}
+
+// *(sp - 1) = 0 and skip to `r`.end unless 0 <= y <= 15
+fn shift_range(buf: &mut AsmWriter, r: &str) -> std::io::Result<()> {
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=0")?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.end", r)?;
+    writeln!(buf, "D;JLT")?;
+    writeln!(buf, "@15")?;
+    writeln!(buf, "D=D-A")?;
+    writeln!(buf, "@{}.end", r)?;
+    writeln!(buf, "D;JGT")?;
+
+    Ok(())
+}
+
+// Double x y times
+fn shift_left(buf: &mut AsmWriter) -> std::io::Result<()> {
+    let r = SHL_ROUTINE;
+    prologue(buf, r)?;
+    shift_range(buf, r)?;
+
+    writeln!(buf, "({}.loop)", r)?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.store", r)?;
+    writeln!(buf, "D;JEQ")?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "M=D-1")?;
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "M=D+M")?;
+    writeln!(buf, "@{}.loop", r)?;
+    writeln!(buf, "0;JMP")?;
+
+    writeln!(buf, "({}.store)", r)?;
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=D")?;
+
+    writeln!(buf, "({}.end)", r)?;
+    epilogue(buf)
+}
+
+// Copy the bits of x from bit y upwards, R15 walks the bits of x
+// and R14 the matching bits of the result
+fn shift_right(buf: &mut AsmWriter) -> std::io::Result<()> {
+    let r = SHR_ROUTINE;
+    prologue(buf, r)?;
+    shift_range(buf, r)?;
+
+    // R15 = 1 << y
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "M=1")?;
+    writeln!(buf, "({}.mask)", r)?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.bits", r)?;
+    writeln!(buf, "D;JEQ")?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "M=D-1")?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "M=D+M")?;
+    writeln!(buf, "@{}.mask", r)?;
+    writeln!(buf, "0;JMP")?;
+
+    writeln!(buf, "({}.bits)", r)?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "M=1")?;
+    writeln!(buf, "({}.loop)", r)?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.end", r)?;
+    writeln!(buf, "D;JEQ")?;
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "D=D&M")?;
+    writeln!(buf, "@{}.next", r)?;
+    writeln!(buf, "D;JEQ")?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=D+M")?;
+
+    writeln!(buf, "({}.next)", r)?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "M=D+M")?;
+    writeln!(buf, "@R14")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "M=D+M")?;
+    writeln!(buf, "@{}.loop", r)?;
+    writeln!(buf, "0;JMP")?;
+
+    writeln!(buf, "({}.end)", r)?;
+    epilogue(buf)
+}
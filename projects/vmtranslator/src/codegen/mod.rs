@@ -0,0 +1,1165 @@
+use hack_vm::{Op, Segment};
+
+use crate::asm::AsmWriter;
+use crate::ir::{Instr, Unit};
+use crate::Options;
+use instrument::Instrumentation;
+
+pub mod instrument;
+mod math;
+mod tos;
+
+const CALL_ROUTINE: &str = "$$CALL";
+const RETURN_ROUTINE: &str = "$$RETURN";
+const EQ_ROUTINE: &str = "$$EQ";
+const GT_ROUTINE: &str = "$$GT";
+const LT_ROUTINE: &str = "$$LT";
+const TAIL_CALL_ROUTINE: &str = "$$TAILCALL";
+const END_LOOP: &str = "$$END";
+
+fn pop(buf: &mut AsmWriter) -> std::io::Result<()> {
+    // --sp
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "M=M-1")?;
+
+    // A = sp
+    writeln!(buf, "A=M")?;
+
+    // D = *sp
+    writeln!(buf, "D=M")?;
+
+    Ok(())
+}
+
+fn pop_static(idx: i32, buf: &mut AsmWriter, filename: &str) -> std::io::Result<()> {
+    pop(buf)?;
+
+    // Ram[static.idx] = D = *sp 
+    writeln!(buf, "@{}.{}", filename, idx)?;
+    writeln!(buf, "M=D")?;
+
+    Ok(())
+}
+
+fn pop_pointer(idx: i32, buf: &mut AsmWriter) -> std::io::Result<()> {
+    pop(buf)?;
+
+    if idx == 0 {
+        // THIS = *sp
+        writeln!(buf, "@THIS")?;
+        writeln!(buf, "M=D")?;
+    } else {
+        assert!(idx == 1);
+        // THAT = *sp
+        writeln!(buf, "@THAT")?;
+        writeln!(buf, "M=D")?;
+    }
+
+    Ok(())
+}
+
+fn pop_temp(idx: i32, buf: &mut AsmWriter) -> std::io::Result<()> {
+    assert!(idx < 8);
+
+    pop(buf)?;
+
+    // Temp.idx = D = *sp
+    writeln!(buf, "@R{}", 5 + idx)?;
+    writeln!(buf, "M=D")?;
+
+    Ok(())
+}
+
+fn pop_segment(idx: i32, buf: &mut AsmWriter, base_var: &str) -> std::io::Result<()> {
+    // --sp
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "M=M-1")?;
+
+    // Ram[13] = base_var + idx
+    writeln!(buf, "@{}", idx)?;
+    writeln!(buf, "D=A")?;
+    writeln!(buf, "@{}", base_var)?;
+    writeln!(buf, "A=D+M")?;
+    writeln!(buf, "D=A")?;
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "M=D")?;
+
+    // d = *sp
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "D=M")?;
+
+    // A = ram[13] = base_var+idx
+    writeln!(buf, "@R13")?;
+    writeln!(buf, "A=M")?;
+
+    // Ram[base_var + idx] = d = *sp
+    writeln!(buf, "M=D")?;
+
+    Ok(())
+}
+
+fn push_write_and_inc(buf: &mut AsmWriter) -> std::io::Result<()> {
+    // d = *sp
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "M=D")?;
+
+    // ++sp
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "M=M+1")?;
+
+    
+    Ok(())
+}
+
+fn push_segment(idx: i32, buf: &mut AsmWriter, base_var: &str) -> std::io::Result<()> {
+    // D = Ram[base_var + idx]
+    writeln!(buf, "@{}", idx)?;
+    writeln!(buf, "D=A")?;
+    writeln!(buf, "@{}", base_var)?;
+    writeln!(buf, "A=D+M")?;
+    writeln!(buf, "D=M")?;
+
+    push_write_and_inc(buf)
+}
+
+fn push_static(idx: i32, buf: &mut AsmWriter, filename: &str) -> std::io::Result<()> {
+    // D = Ram[static.idx]
+    writeln!(buf, "@{}.{}", filename, idx)?;
+    writeln!(buf, "D=M")?;
+
+    push_write_and_inc(buf)
+}
+
+fn push_pointer(idx: i32, buf: &mut AsmWriter) -> std::io::Result<()> {
+    assert!(idx == 0 || idx == 1);
+
+    // D = THIS/THAT
+    if idx == 0 {
+        writeln!(buf, "@THIS")?;
+    } else {
+        writeln!(buf, "@THAT")?;
+    }
+    writeln!(buf, "D=M")?;
+
+    push_write_and_inc(buf)
+}
+
+fn push_temp(idx: i32, buf: &mut AsmWriter) -> std::io::Result<()> {
+    assert!(idx < 8);
+                        
+    // D = Ram[idx + 5]
+    writeln!(buf, "@R{}", idx + 5)?;
+    writeln!(buf, "D=M")?;
+
+    push_write_and_inc(buf)
+}
+
+fn push_const(idx: i32, buf: &mut AsmWriter) -> std::io::Result<()> {
+    // D = idx
+    writeln!(buf, "@{}", idx)?;
+    writeln!(buf, "D=A")?;
+
+    push_write_and_inc(buf)
+}
+
+// Given D = y = *sp and x = *(sp - 1), set D to a value with
+// the sign of x - y. Plain subtraction overflows when x and y
+// have different signs, then the sign of x decides alone.
+fn signed_diff(buf: &mut AsmWriter, prefix: &str) -> std::io::Result<()> {
+    writeln!(buf, "@{}.neg", prefix)?;
+    writeln!(buf, "D;JLT")?;
+
+    // y >= 0, D = x
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.end", prefix)?;
+    writeln!(buf, "D;JLT")?; // x < 0 <= y
+
+    // same signs, x - y cannot overflow
+    writeln!(buf, "({}.sub)", prefix)?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "D=D-M")?;
+    writeln!(buf, "@{}.end", prefix)?;
+    writeln!(buf, "0;JMP")?;
+
+    // y < 0, D = x
+    writeln!(buf, "({}.neg)", prefix)?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@{}.sub", prefix)?;
+    writeln!(buf, "D;JLT")?;
+    writeln!(buf, "D=1")?; // x >= 0 > y
+    writeln!(buf, "({}.end)", prefix)?;
+
+    Ok(())
+}
+
+fn comp(buf: &mut AsmWriter, condition: &str, cont_idx: &mut i32) -> std::io::Result<()> {
+    //--sp
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "M=M-1")?;
+
+    // d = *sp = y
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "D=M")?;
+
+    // *(sp - 1) = x `condition` y
+    if condition == "JEQ" {
+        writeln!(buf, "A=A-1")?; // A = sp-1
+        writeln!(buf, "MD=M-D")?;
+    } else {
+        signed_diff(buf, &format!("__cmp{}", cont_idx))?;
+    }
+
+    writeln!(buf, "@__eq.true{}", cont_idx)?;
+    writeln!(buf, "D; {}", condition)?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "A=A-1")?;
+    writeln!(buf, "M=0")?;
+    writeln!(buf, "@__cont{}", cont_idx)?;
+    writeln!(buf, "0; JMP")?;
+    writeln!(buf, "(__eq.true{})", cont_idx)?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "A=A-1")?;
+    writeln!(buf, "M=-1")?;
+    writeln!(buf, "(__cont{})", cont_idx)?;
+
+    *cont_idx += 1;
+
+    Ok(())
+}
+
+fn shared_routine(buf: &mut AsmWriter, routine: &str, cont_idx: &mut i32) -> std::io::Result<()> {
+    // D = return address
+    writeln!(buf, "@__cont{}", cont_idx)?;
+    writeln!(buf, "D=A")?;
+    writeln!(buf, "@{}", routine)?;
+    writeln!(buf, "0;JMP")?;
+    writeln!(buf, "(__cont{})", cont_idx)?;
+
+    *cont_idx += 1;
+
+    Ok(())
+}
+
+fn comp_routine(buf: &mut AsmWriter, routine: &str, condition: &str) -> std::io::Result<()> {
+    // Expects the return address in D
+    writeln!(buf, "({})", routine)?;
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "M=D")?;
+
+    // d = *(--sp) = y
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "AM=M-1")?;
+    writeln!(buf, "D=M")?;
+
+    // *(sp - 1) = x `condition` y
+    if condition == "JEQ" {
+        writeln!(buf, "A=A-1")?;
+        writeln!(buf, "D=M-D")?;
+    } else {
+        signed_diff(buf, routine)?;
+        writeln!(buf, "@SP")?;
+        writeln!(buf, "A=M-1")?;
+    }
+    writeln!(buf, "M=-1")?;
+    writeln!(buf, "@{}.true", routine)?;
+    writeln!(buf, "D;{}", condition)?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=0")?;
+    writeln!(buf, "({}.true)", routine)?;
+
+    writeln!(buf, "@R15")?;
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "0;JMP")?;
+
+    Ok(())
+}
+
+fn arith(buf: &mut AsmWriter, op: &str) -> std::io::Result<()> {
+    //--sp
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "M=M-1")?;
+
+    // d = *sp
+    writeln!(buf, "A=M")?;
+    writeln!(buf, "D=M")?;
+
+    // *(sp-1) = *(sp-1) + *sp
+    writeln!(buf, "A=A-1")?;
+    writeln!(buf, "M=M{}D", op)?;
+
+    Ok(())
+}
+
+fn neg(buf: &mut AsmWriter) -> std::io::Result<()> {
+    // *(sp-1) = -*(sp-1)
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=-M")?;
+
+    Ok(())
+}
+
+fn not(buf: &mut AsmWriter) -> std::io::Result<()> {
+    // *sp = !*sp
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=!M")?;
+
+    Ok(())
+}
+
+fn base_var(seg: Segment) -> Option<&'static str> {
+    match seg {
+        Segment::Local => Some("LCL"),
+        Segment::Argument => Some("ARG"),
+        Segment::This => Some("THIS"),
+        Segment::That => Some("THAT"),
+        _ => None,
+    }
+}
+
+// A = address of seg[idx]. D is clobbered only for base
+// segments with idx > 2, unless `keep_d` is set, in which
+// case D is parked in R13 while the address is computed.
+fn select(buf: &mut AsmWriter, seg: Segment, idx: i32, filename: &str, keep_d: bool) -> std::io::Result<()> {
+    match seg {
+        Segment::Static => writeln!(buf, "@{}.{}", filename, idx)?,
+        Segment::Temp => writeln!(buf, "@R{}", 5 + idx)?,
+        Segment::Pointer => writeln!(buf, "@{}", if idx == 0 { "THIS" } else { "THAT" })?,
+        Segment::Constant => unreachable!("constant segment has no address"),
+        _ => {
+            let base_var = base_var(seg).unwrap();
+            if idx <= 2 {
+                writeln!(buf, "@{}", base_var)?;
+                writeln!(buf, "A=M")?;
+                for _ in 0..idx {
+                    writeln!(buf, "A=A+1")?;
+                }
+            } else if !keep_d {
+                writeln!(buf, "@{}", idx)?;
+                writeln!(buf, "D=A")?;
+                writeln!(buf, "@{}", base_var)?;
+                writeln!(buf, "A=D+M")?;
+            } else {
+                writeln!(buf, "@R13")?;
+                writeln!(buf, "M=D")?;
+                writeln!(buf, "@{}", idx)?;
+                writeln!(buf, "D=A")?;
+                writeln!(buf, "@{}", base_var)?;
+                writeln!(buf, "D=D+M")?;
+                writeln!(buf, "@R14")?;
+                writeln!(buf, "M=D")?;
+                writeln!(buf, "@R13")?;
+                writeln!(buf, "D=M")?;
+                writeln!(buf, "@R14")?;
+                writeln!(buf, "A=M")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// D = value
+fn load_value(buf: &mut AsmWriter, value: i16) -> std::io::Result<()> {
+    match value {
+        -1..=1 => writeln!(buf, "D={}", value)?,
+        i16::MIN => {
+            writeln!(buf, "@{}", i16::MAX)?;
+            writeln!(buf, "D=!A")?;
+        }
+        v if v < 0 => {
+            writeln!(buf, "@{}", -v)?;
+            writeln!(buf, "D=-A")?;
+        }
+        v => {
+            writeln!(buf, "@{}", v)?;
+            writeln!(buf, "D=A")?;
+        }
+    }
+
+    Ok(())
+}
+
+// D = seg[idx]
+fn load(buf: &mut AsmWriter, seg: Segment, idx: i32, filename: &str) -> std::io::Result<()> {
+    if seg == Segment::Constant {
+        return load_value(buf, idx as i16);
+    }
+
+    select(buf, seg, idx, filename, false)?;
+    writeln!(buf, "D=M")?;
+
+    Ok(())
+}
+
+// seg[idx] = D
+fn store(buf: &mut AsmWriter, seg: Segment, idx: i32, filename: &str) -> std::io::Result<()> {
+    select(buf, seg, idx, filename, true)?;
+    writeln!(buf, "M=D")?;
+
+    Ok(())
+}
+
+fn push_value(buf: &mut AsmWriter, value: i16) -> std::io::Result<()> {
+    load_value(buf, value)?;
+    push_write_and_inc(buf)
+}
+
+fn move_segment(buf: &mut AsmWriter, from: (Segment, i32), to: (Segment, i32), filename: &str) -> std::io::Result<()> {
+    load(buf, from.0, from.1, filename)?;
+    store(buf, to.0, to.1, filename)
+}
+
+fn set_segment(buf: &mut AsmWriter, seg: Segment, idx: i32, value: i16, filename: &str) -> std::io::Result<()> {
+    load_value(buf, value)?;
+    store(buf, seg, idx, filename)
+}
+
+fn add_top(buf: &mut AsmWriter, value: i16) -> std::io::Result<()> {
+    // *(sp-1) += value
+    match value {
+        1 | -1 => {
+            writeln!(buf, "@SP")?;
+            writeln!(buf, "A=M-1")?;
+            writeln!(buf, "M=M{}1", if value > 0 { "+" } else { "-" })?;
+        }
+        _ => {
+            load_value(buf, value)?;
+            writeln!(buf, "@SP")?;
+            writeln!(buf, "A=M-1")?;
+            writeln!(buf, "M=D+M")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn add_in_place(buf: &mut AsmWriter, seg: Segment, idx: i32, value: i16, filename: &str) -> std::io::Result<()> {
+    // seg[idx] += value
+    match value {
+        1 | -1 => {
+            select(buf, seg, idx, filename, false)?;
+            writeln!(buf, "M=M{}1", if value > 0 { "+" } else { "-" })?;
+        }
+        _ => {
+            load_value(buf, value)?;
+            select(buf, seg, idx, filename, true)?;
+            writeln!(buf, "M=D+M")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn if_not_goto(buf: &mut AsmWriter, label: &str) -> std::io::Result<()> {
+    // d = *(--sp) + 1, zero only for `true`
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "AM=M-1")?;
+    writeln!(buf, "D=M+1")?;
+
+    // jump when `not d` is nonzero
+    writeln!(buf, "@{}", label)?;
+    writeln!(buf, "D;JNE")?;
+
+    Ok(())
+}
+
+fn load_that(buf: &mut AsmWriter) -> std::io::Result<()> {
+    // THAT = *(sp-1); *(sp-1) = *THAT
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@THAT")?;
+    writeln!(buf, "M=D")?;
+    writeln!(buf, "A=D")?;
+    writeln!(buf, "D=M")?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "A=M-1")?;
+    writeln!(buf, "M=D")?;
+
+    Ok(())
+}
+
+fn push_indirect(buf: &mut AsmWriter, seg: Segment, idx: i32, filename: &str) -> std::io::Result<()> {
+    // THAT = seg[idx]; push *THAT
+    load(buf, seg, idx, filename)?;
+    writeln!(buf, "@THAT")?;
+    writeln!(buf, "M=D")?;
+    writeln!(buf, "A=D")?;
+    writeln!(buf, "D=M")?;
+    push_write_and_inc(buf)
+}
+
+fn call(out_file: &mut AsmWriter, name: &str, nargs: i32, ret_addr: &str) -> std::io::Result<()> {
+    writeln!(out_file, "@{}", ret_addr)?;
+    writeln!(out_file, "D=A")?;
+    push_write_and_inc(out_file)?; // 7
+
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "D=M")?;
+    push_write_and_inc(out_file)?; // 14
+
+    writeln!(out_file, "@ARG")?;
+    writeln!(out_file, "D=M")?;
+    push_write_and_inc(out_file)?; // 21
+
+    writeln!(out_file, "@THIS")?;
+    writeln!(out_file, "D=M")?;
+    push_write_and_inc(out_file)?; // 28
+
+    writeln!(out_file, "@THAT")?;
+    writeln!(out_file, "D=M")?;
+    push_write_and_inc(out_file)?; // 35
+
+    writeln!(out_file, "@SP")?;
+    writeln!(out_file, "D=M")?;
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "M=D")?;
+    writeln!(out_file, "@{}", 5 + nargs)?;
+    writeln!(out_file, "D=D-A")?;
+    writeln!(out_file, "@ARG")?;
+    writeln!(out_file, "M=D")?;
+
+    writeln!(out_file, "@{}", generate_entry_point(name))?;
+    writeln!(out_file, "0;JMP")?; // 45
+
+    writeln!(out_file, "({})", ret_addr)?;
+
+    Ok(())
+}
+
+fn call_shared(out_file: &mut AsmWriter, name: &str, nargs: i32, ret_addr: &str) -> std::io::Result<()> {
+    // R13 = nargs
+    writeln!(out_file, "@{}", nargs)?;
+    writeln!(out_file, "D=A")?;
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "M=D")?;
+
+    // R14 = callee
+    writeln!(out_file, "@{}", generate_entry_point(name))?;
+    writeln!(out_file, "D=A")?;
+    writeln!(out_file, "@R14")?;
+    writeln!(out_file, "M=D")?;
+
+    // D = return address
+    writeln!(out_file, "@{}", ret_addr)?;
+    writeln!(out_file, "D=A")?;
+    writeln!(out_file, "@{}", CALL_ROUTINE)?;
+    writeln!(out_file, "0;JMP")?; // 12
+
+    writeln!(out_file, "({})", ret_addr)?;
+
+    Ok(())
+}
+
+fn call_routine(out_file: &mut AsmWriter) -> std::io::Result<()> {
+    // Expects the return address in D, nargs in R13 and
+    // the callee's entry point in R14.
+    writeln!(out_file, "({})", CALL_ROUTINE)?;
+
+    // push return address
+    writeln!(out_file, "@SP")?;
+    writeln!(out_file, "A=M")?;
+    writeln!(out_file, "M=D")?;
+
+    // push LCL, ARG, THIS, THAT
+    for reg in ["LCL", "ARG", "THIS", "THAT"] {
+        writeln!(out_file, "@{}", reg)?;
+        writeln!(out_file, "D=M")?;
+        writeln!(out_file, "@SP")?;
+        writeln!(out_file, "AM=M+1")?;
+        writeln!(out_file, "M=D")?;
+    }
+
+    // LCL = ++sp
+    writeln!(out_file, "@SP")?;
+    writeln!(out_file, "MD=M+1")?;
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "M=D")?;
+
+    // ARG = sp - 5 - nargs
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "D=D-M")?;
+    writeln!(out_file, "@5")?;
+    writeln!(out_file, "D=D-A")?;
+    writeln!(out_file, "@ARG")?;
+    writeln!(out_file, "M=D")?;
+
+    // goto callee
+    writeln!(out_file, "@R14")?;
+    writeln!(out_file, "A=M")?;
+    writeln!(out_file, "0;JMP")?;
+
+    Ok(())
+}
+
+fn tail_call(out_file: &mut AsmWriter, name: &str, nargs: i32) -> std::io::Result<()> {
+    // R13 = nargs
+    writeln!(out_file, "@{}", nargs)?;
+    writeln!(out_file, "D=A")?;
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "M=D")?;
+
+    // R14 = callee
+    writeln!(out_file, "@{}", generate_entry_point(name))?;
+    writeln!(out_file, "D=A")?;
+    writeln!(out_file, "@R14")?;
+    writeln!(out_file, "M=D")?;
+
+    writeln!(out_file, "@{}", TAIL_CALL_ROUTINE)?;
+    writeln!(out_file, "0;JMP")?;
+
+    Ok(())
+}
+
+fn tail_call_routine(out_file: &mut AsmWriter) -> std::io::Result<()> {
+    // Expects nargs in R13 and the callee's entry point in R14.
+    // The callee gets the frame of the current function: its
+    // arguments and the saved frame of the caller are moved down
+    // to ARG, then it is entered like from a regular call.
+    writeln!(out_file, "({})", TAIL_CALL_ROUTINE)?;
+
+    // RAM[SP..SP+5] = saved frame at RAM[LCL-5..LCL], so the
+    // new arguments and the frame form one block
+    for i in 0..5 {
+        writeln!(out_file, "@LCL")?;
+        writeln!(out_file, "D=M")?;
+        writeln!(out_file, "@{}", 5 - i)?;
+        writeln!(out_file, "A=D-A")?;
+        writeln!(out_file, "D=M")?;
+        writeln!(out_file, "@SP")?;
+        writeln!(out_file, "A=M")?;
+        for _ in 0..i {
+            writeln!(out_file, "A=A+1")?;
+        }
+        writeln!(out_file, "M=D")?;
+    }
+
+    // R15 = distance from the block at sp - nargs down to ARG
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "D=M")?;
+    writeln!(out_file, "@SP")?;
+    writeln!(out_file, "D=M-D")?;
+    writeln!(out_file, "@ARG")?;
+    writeln!(out_file, "D=D-M")?;
+    writeln!(out_file, "@R15")?;
+    writeln!(out_file, "M=D")?;
+
+    // R13 = words to move = nargs + 5
+    writeln!(out_file, "@5")?;
+    writeln!(out_file, "D=A")?;
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "M=D+M")?;
+
+    // Move the block with LCL as the destination pointer,
+    // which leaves LCL = ARG + nargs + 5 as a call would
+    writeln!(out_file, "@ARG")?;
+    writeln!(out_file, "D=M")?;
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "M=D")?;
+    writeln!(out_file, "({}.copy)", TAIL_CALL_ROUTINE)?;
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "D=M")?;
+    writeln!(out_file, "@{}.done", TAIL_CALL_ROUTINE)?;
+    writeln!(out_file, "D;JEQ")?;
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "D=M")?;
+    writeln!(out_file, "@R15")?;
+    writeln!(out_file, "A=D+M")?;
+    writeln!(out_file, "D=M")?;
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "A=M")?;
+    writeln!(out_file, "M=D")?;
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "M=M+1")?;
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "M=M-1")?;
+    writeln!(out_file, "@{}.copy", TAIL_CALL_ROUTINE)?;
+    writeln!(out_file, "0;JMP")?;
+    writeln!(out_file, "({}.done)", TAIL_CALL_ROUTINE)?;
+
+    // SP = LCL, goto callee
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "D=M")?;
+    writeln!(out_file, "@SP")?;
+    writeln!(out_file, "M=D")?;
+    writeln!(out_file, "@R14")?;
+    writeln!(out_file, "A=M")?;
+    writeln!(out_file, "0;JMP")?;
+
+    Ok(())
+}
+
+fn ret(out_file: &mut AsmWriter) -> std::io::Result<()> {
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "D=M-1")?; // D = address of old frame last value
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "M=D")?; // RAM[13] = old frame end
+
+    // save return address in case it's
+    // overwritten by return value. This will
+    // happen if function is called with 0 args.
+    writeln!(out_file, "@4")?;
+    writeln!(out_file, "D=D-A")?; // D = address of old frame first value = return address
+    writeln!(out_file, "A=D")?;
+    writeln!(out_file, "D=M")?;
+    writeln!(out_file, "@R14")?;
+    writeln!(out_file, "M=D")?;
+
+    writeln!(out_file, "@SP")?;
+    writeln!(out_file, "A=M-1")?;
+    writeln!(out_file, "D=M")?; // d holds return value now
+
+    writeln!(out_file, "@ARG")?;
+    writeln!(out_file, "A=M")?;
+    writeln!(out_file, "M=D")?; // RAM[ARG] holds return value now
+
+    writeln!(out_file, "D=A")?; // D=ARG
+    writeln!(out_file, "@SP")?;
+    writeln!(out_file, "M=D+1")?; // SP = ARG + 1
+
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "A=M")?;
+    writeln!(out_file, "D=M")?; // d = that
+    writeln!(out_file, "@THAT")?;
+    writeln!(out_file, "M=D")?; // that restored
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "M=M-1")?;
+    writeln!(out_file, "A=M")?;
+    writeln!(out_file, "D=M")?; // d = this
+    writeln!(out_file, "@THIS")?;
+    writeln!(out_file, "M=D")?; // this restored
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "M=M-1")?;
+    writeln!(out_file, "A=M")?;
+    writeln!(out_file, "D=M")?; // d = ARG
+    writeln!(out_file, "@ARG")?;
+    writeln!(out_file, "M=D")?; // arg restored
+    writeln!(out_file, "@R13")?;
+    writeln!(out_file, "M=M-1")?;
+    writeln!(out_file, "A=M")?;
+    writeln!(out_file, "D=M")?; // d = LCL
+    writeln!(out_file, "@LCL")?;
+    writeln!(out_file, "M=D")?; // lcl restored
+
+    writeln!(out_file, "@R14")?;
+    writeln!(out_file, "A=M")?; // A = return address
+    writeln!(out_file, "0;JMP")?;
+
+    Ok(())
+}
+
+// Push `nlocals` zeros for the locals of a function
+fn init_locals(out_file: &mut AsmWriter, nlocals: i32) -> std::io::Result<()> {
+    if nlocals > 0 {
+        writeln!(out_file, "@SP")?;
+        writeln!(out_file, "A=M")?;
+        for _ in 0..nlocals {
+            writeln!(out_file, "M=0")?;
+            writeln!(out_file, "A=A+1")?;
+        }
+        writeln!(out_file, "D=A")?;
+        writeln!(out_file, "@SP")?;
+        writeln!(out_file, "M=D")?;
+    }
+
+    Ok(())
+}
+
+///
+/// Instructions executed to call a function with `nlocals`
+/// locals and return from it, leaving out its body and the
+/// pushing of the arguments. Counted on the templates `opts`
+/// selects, all of them straight-line code, with the runtime
+/// checks passing.
+///
+pub fn call_cost(nlocals: i32, opts: &Options) -> std::io::Result<i32> {
+    let mut sink = AsmWriter::new(Box::new(std::io::sink()));
+    if opts.trampolines {
+        call_shared(&mut sink, "f", 0, "ret")?;
+        call_routine(&mut sink)?;
+    } else {
+        call(&mut sink, "f", 0, "ret")?;
+    }
+    init_locals(&mut sink, nlocals)?;
+    instrument::function_entry(&mut sink, 0, &opts.instrument)?;
+
+    instrument::before_return(&mut sink, nlocals, &opts.instrument)?;
+    if opts.trampolines {
+        writeln!(sink, "@{}", RETURN_ROUTINE)?;
+        writeln!(sink, "0;JMP")?;
+    }
+    ret(&mut sink)?;
+
+    Ok(sink.instr_cnt)
+}
+
+fn generate_label(curr_fun: &str, label: &str) -> String {
+    let mut res = String::new();
+    if !curr_fun.is_empty() {
+        res += curr_fun;
+        res.push('$');
+    }
+    res += label;
+
+    res
+}
+
+fn generate_entry_point(fun_name: &str) -> String {
+    let mut res = String::new();
+    res += fun_name;
+
+    res
+}
+
+fn generate_return_addr(curr_fun: &str, call_idx: &mut i32) -> String {
+    let mut res = String::new();
+    assert!(!curr_fun.is_empty(), "Calling from outside a function: {}", call_idx);
+    res += curr_fun;
+    res += "$ret.";
+    res += &call_idx.to_string();
+
+    *call_idx += 1;
+    
+    res
+}
+
+/// Shared routines referenced by the emitted code when
+/// translating with `Options::trampolines`, the tail call
+/// routine, the routines of the extension commands and the
+/// error routines of the instrumentation.
+#[derive(Default)]
+struct SharedRoutines {
+    call: bool,
+    tail_call: bool,
+    ret: bool,
+    eq: bool,
+    gt: bool,
+    lt: bool,
+    math: math::Routines,
+    instrument: Instrumentation,
+}
+
+impl SharedRoutines {
+    fn is_empty(&self) -> bool {
+        !(self.call || self.tail_call || self.ret || self.eq || self.gt || self.lt || !self.math.is_empty() || self.instrument.has_checks())
+    }
+
+    fn write(&self, out_file: &mut AsmWriter) -> std::io::Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        // Never fall through into the routines
+        out_file.mark("", 0, "")?;
+        writeln!(out_file, "// shared routines")?;
+        writeln!(out_file, "({})", END_LOOP)?;
+        writeln!(out_file, "@{}", END_LOOP)?;
+        writeln!(out_file, "0;JMP")?;
+
+        if self.call {
+            call_routine(out_file)?;
+        }
+        if self.tail_call {
+            tail_call_routine(out_file)?;
+        }
+        if self.ret {
+            writeln!(out_file, "({})", RETURN_ROUTINE)?;
+            ret(out_file)?;
+        }
+        if self.eq {
+            comp_routine(out_file, EQ_ROUTINE, "JEQ")?;
+        }
+        if self.gt {
+            comp_routine(out_file, GT_ROUTINE, "JGT")?;
+        }
+        if self.lt {
+            comp_routine(out_file, LT_ROUTINE, "JLT")?;
+        }
+        self.math.write(out_file)?;
+
+        instrument::routines(out_file, &self.instrument)
+    }
+}
+
+// reg = value for each of `registers`
+pub fn init(out_file: &mut AsmWriter, registers: &[(String, i16)]) -> std::io::Result<()> {
+    out_file.mark("", 0, "")?;
+    for (reg, value) in registers {
+        load_value(out_file, *value)?;
+        writeln!(out_file, "@{}", reg)?;
+        writeln!(out_file, "M=D")?;
+    }
+
+    Ok(())
+}
+
+pub fn bootstrap(out_file: &mut AsmWriter, entry: &str) -> std::io::Result<()> {
+    let mut call_idx = 0;
+    call(out_file, entry, 0, &generate_return_addr("_", &mut call_idx))
+}
+
+pub fn output(units: &[Unit], out_file: &mut AsmWriter, opts: &Options) -> std::io::Result<()> {
+    let mut cont_idx = 0;
+    let mut curr_fun = String::new();
+    let mut call_idx = 0;
+    let mut shared = SharedRoutines {
+        instrument: opts.instrument,
+        ..Default::default()
+    };
+    shared.math.sys_error = units.iter().flat_map(|u| &u.code).any(|item| matches!(&item.instr, Instr::Op(Op::Function(name, _)) if name == "Sys.error"));
+    let mut cached = false;
+    let mut fun_id = 0;
+    let mut curr_nlocals = 0;
+
+    let bytecode = units.iter().flat_map(|u| u.code.iter().map(move |item| (u.name.as_str(), item)));
+    for (filename, item) in bytecode {
+        let instr = &item.instr;
+        let function = match instr {
+            Instr::Op(Op::Function(name, _)) => name,
+            _ => &curr_fun,
+        };
+        out_file.mark(filename, item.line, function)?;
+
+        if opts.cache_tos {
+            if tos::emit(out_file, instr, filename, &curr_fun, &mut cont_idx, &mut cached, opts.trampolines)? {
+                continue;
+            }
+            tos::flush(out_file, &mut cached)?;
+        }
+
+        match instr {
+            Instr::PushValue(value) => {
+                writeln!(out_file, "// push {}", value)?;
+                push_value(out_file, *value)?;
+            },
+            Instr::Move(s, i, t, j) => {
+                writeln!(out_file, "// push {} {}; pop {} {}", s, i, t, j)?;
+                move_segment(out_file, (*s, *i), (*t, *j), filename)?;
+            },
+            Instr::Set(seg, idx, value) => {
+                writeln!(out_file, "// push {}; pop {} {}", value, seg, idx)?;
+                set_segment(out_file, *seg, *idx, *value, filename)?;
+            },
+            Instr::AddTop(value) => {
+                writeln!(out_file, "// push {}; add", value)?;
+                add_top(out_file, *value)?;
+            },
+            Instr::AddInPlace(seg, idx, value) => {
+                writeln!(out_file, "// {} {} += {}", seg, idx, value)?;
+                add_in_place(out_file, *seg, *idx, *value, filename)?;
+            },
+            Instr::IfNotGoto(label) => {
+                let label = generate_label(&curr_fun, label);
+                writeln!(out_file, "// not; if-goto {}", label)?;
+                if_not_goto(out_file, &label)?;
+            },
+            Instr::LoadThat => {
+                writeln!(out_file, "// pop pointer 1; push that 0")?;
+                load_that(out_file)?;
+            },
+            Instr::PushIndirect(seg, idx) => {
+                writeln!(out_file, "// push {} {}; pop pointer 1; push that 0", seg, idx)?;
+                push_indirect(out_file, *seg, *idx, filename)?;
+            },
+            Instr::Op(op @ Op::Pop(seg, idx)) => {
+                writeln!(out_file, "// {}", op)?;
+                match seg {
+                    Segment::Local => {
+                        pop_segment(*idx, out_file, "LCL")?;
+                    },
+                    Segment::Argument => {
+                        pop_segment(*idx, out_file, "ARG")?;
+                    },
+                    Segment::This => {
+                        pop_segment(*idx, out_file, "THIS")?;
+                    },
+                    Segment::That => {
+                        pop_segment(*idx, out_file, "THAT")?;
+                    },
+                    Segment::Static => {
+                        pop_static(*idx, out_file, filename)?;
+                    },
+                    Segment::Pointer => {
+                        pop_pointer(*idx, out_file)?;
+                    },
+                    Segment::Temp => {
+                        pop_temp(*idx, out_file)?;
+                    },
+                    Segment::Constant => {
+                        unreachable!("pop constant is rejected by the parser");
+                    },
+                }
+            },
+            Instr::Op(op @ Op::Push(seg, idx)) => {
+                writeln!(out_file, "// {}", op)?;
+                match seg {
+                    Segment::Local => {
+                        push_segment(*idx, out_file, "LCL")?;
+                    },
+                    Segment::Argument => {
+                        push_segment(*idx, out_file, "ARG")?;
+                    },
+                    Segment::This => {
+                        push_segment(*idx, out_file, "THIS")?;
+                    },
+                    Segment::That => {
+                        push_segment(*idx, out_file, "THAT")?;
+                    },
+                    Segment::Static => {
+                        push_static(*idx, out_file, filename)?;
+                    },
+                    Segment::Pointer => {
+                        push_pointer(*idx, out_file)?;
+                    },
+                    Segment::Temp => {
+                        push_temp(*idx, out_file)?;
+                    },
+                    Segment::Constant => {
+                        push_const(*idx, out_file)?;
+                    },
+                }
+            },
+            Instr::Op(Op::Add) => {
+                writeln!(out_file, "// add")?;
+                arith(out_file, "+")?
+            },
+            Instr::Op(Op::Sub) => {
+                writeln!(out_file, "// sub")?;
+                arith(out_file, "-")?
+            },
+            Instr::Op(Op::Neg) => {
+                writeln!(out_file, "// neg")?;
+                neg(out_file)?;
+            },
+            Instr::Op(Op::Eq) => {
+                writeln!(out_file, "// eq")?;
+                if opts.trampolines {
+                    shared.eq = true;
+                    shared_routine(out_file, EQ_ROUTINE, &mut cont_idx)?;
+                } else {
+                    comp(out_file, "JEQ", &mut cont_idx)?;
+                }
+            },
+            Instr::Op(Op::Gt) => {
+                writeln!(out_file, "// gt")?;
+                if opts.trampolines {
+                    shared.gt = true;
+                    shared_routine(out_file, GT_ROUTINE, &mut cont_idx)?;
+                } else {
+                    comp(out_file, "JGT", &mut cont_idx)?;
+                }
+            },
+            Instr::Op(Op::Lt) => {
+                writeln!(out_file, "// lt")?;
+                if opts.trampolines {
+                    shared.lt = true;
+                    shared_routine(out_file, LT_ROUTINE, &mut cont_idx)?;
+                } else {
+                    comp(out_file, "JLT", &mut cont_idx)?;
+                }
+            },
+            Instr::Op(Op::And) => {
+                writeln!(out_file, "// and")?;
+                arith(out_file, "&")?
+            },
+            Instr::Op(Op::Or) => {
+                writeln!(out_file, "// or")?;
+                arith(out_file, "|")?
+            },
+            Instr::Op(Op::Not) => {
+                writeln!(out_file, "// not")?;
+                not(out_file)?;
+            },
+            Instr::Op(op @ (Op::Mul | Op::Div | Op::Mod | Op::Shl | Op::Shr)) => {
+                writeln!(out_file, "// {}", op)?;
+                let routine = shared.math.use_op(op).unwrap();
+                shared_routine(out_file, routine, &mut cont_idx)?;
+            },
+            Instr::Op(Op::Label(label)) => {
+                let label = generate_label(&curr_fun, label);
+                writeln!(out_file, "// label {}", label)?;
+                writeln!(out_file, "({})", label)?;
+            },
+            Instr::Op(Op::Goto(label)) => {
+                let label = generate_label( &curr_fun, label);
+                writeln!(out_file, "// goto {}", label)?;
+                writeln!(out_file, "@{}", label)?;
+                writeln!(out_file, "0;JMP")?;
+            },
+            Instr::Op(Op::IfGoto(label)) => {
+                let label = generate_label( &curr_fun, label);
+
+                writeln!(out_file, "// if-goto {}", label)?;
+                // --sp
+                writeln!(out_file, "@SP")?;
+                writeln!(out_file, "M=M-1")?;
+
+                // d = Ram[SP]
+                writeln!(out_file, "A=M")?;
+                writeln!(out_file, "D=M")?;
+                
+                // any nonzero value jumps
+                writeln!(out_file, "@{}", label)?;
+                writeln!(out_file, "D;JNE")?;
+
+            },
+            Instr::Op(Op::Call(name, nargs)) => {                
+                writeln!(out_file, "// call {} {}", name, nargs)?;
+
+                let ret_addr = generate_return_addr(&curr_fun, &mut call_idx);
+                if opts.trampolines {
+                    shared.call = true;
+                    call_shared(out_file, name, *nargs, &ret_addr)?;
+                } else {
+                    call(out_file, name, *nargs, &ret_addr)?;
+                }
+            },
+            Instr::TailCall(name, nargs) => {
+                writeln!(out_file, "// call {} {}; return", name, nargs)?;
+                shared.tail_call = true;
+                tail_call(out_file, name, *nargs)?;
+            },
+            Instr::Op(Op::Function(name, nlocals)) => {
+                curr_fun = name.clone();
+                curr_nlocals = *nlocals;
+
+                writeln!(out_file, "// function {} {}", name, nlocals)?;
+
+                writeln!(out_file, "({})", generate_entry_point(name))?;
+                init_locals(out_file, *nlocals)?;
+
+                instrument::function_entry(out_file, fun_id, &opts.instrument)?;
+                fun_id += 1;
+            },
+            Instr::Op(Op::Return) => {
+                writeln!(out_file, "// return")?;
+                if !curr_fun.is_empty() {
+                    instrument::before_return(out_file, curr_nlocals, &opts.instrument)?;
+                }
+                if opts.trampolines {
+                    shared.ret = true;
+                    writeln!(out_file, "@{}", RETURN_ROUTINE)?;
+                    writeln!(out_file, "0;JMP")?;
+                } else {
+                    ret(out_file)?;
+                }
+            },
+        }
+    }
+
+    tos::flush(out_file, &mut cached)?;
+
+    shared.write(out_file)
+}
@@ -0,0 +1,208 @@
+//!
+//! Top-of-stack caching. While `cached` is set the topmost
+//! VM stack value lives in D instead of RAM[SP-1], i.e. the
+//! logical stack is RAM[256..SP] followed by D.
+//!
+//! Only straight-line commands are handled here. Anything
+//! else (labels, jumps, calls, returns and the fused
+//! memory-to-memory moves, which clobber D) gets the value
+//! flushed to memory first and uses the regular templates.
+//!
+
+use hack_vm::Op;
+
+use super::{generate_label, load, load_value, signed_diff, store};
+use crate::asm::AsmWriter;
+use crate::ir::Instr;
+
+// *sp++ = D
+pub fn flush(buf: &mut AsmWriter, cached: &mut bool) -> std::io::Result<()> {
+    if *cached {
+        writeln!(buf, "@SP")?;
+        writeln!(buf, "AM=M+1")?;
+        writeln!(buf, "A=A-1")?;
+        writeln!(buf, "M=D")?;
+        *cached = false;
+    }
+
+    Ok(())
+}
+
+// D = *--sp, the top of stack is now cached
+fn fill(buf: &mut AsmWriter, cached: &mut bool) -> std::io::Result<()> {
+    if !*cached {
+        writeln!(buf, "@SP")?;
+        writeln!(buf, "AM=M-1")?;
+        writeln!(buf, "D=M")?;
+        *cached = true;
+    }
+
+    Ok(())
+}
+
+// D = *--sp `op` D
+fn binary(buf: &mut AsmWriter, comp: &str, cached: &mut bool) -> std::io::Result<()> {
+    fill(buf, cached)?;
+    writeln!(buf, "@SP")?;
+    writeln!(buf, "AM=M-1")?;
+    writeln!(buf, "D={}", comp)?;
+
+    Ok(())
+}
+
+fn comp(buf: &mut AsmWriter, condition: &str, cont_idx: &mut i32, cached: &mut bool) -> std::io::Result<()> {
+    if condition == "JEQ" {
+        // D = x - y
+        binary(buf, "M-D", cached)?;
+    } else {
+        // *sp = y, x stays below
+        fill(buf, cached)?;
+        writeln!(buf, "@SP")?;
+        writeln!(buf, "A=M")?;
+        writeln!(buf, "M=D")?;
+        signed_diff(buf, &format!("__cmp{}", cont_idx))?;
+
+        // --sp
+        writeln!(buf, "@SP")?;
+        writeln!(buf, "M=M-1")?;
+    }
+
+    writeln!(buf, "@__eq.true{}", cont_idx)?;
+    writeln!(buf, "D;{}", condition)?;
+    writeln!(buf, "D=0")?;
+    writeln!(buf, "@__cont{}", cont_idx)?;
+    writeln!(buf, "0;JMP")?;
+    writeln!(buf, "(__eq.true{})", cont_idx)?;
+    writeln!(buf, "D=-1")?;
+    writeln!(buf, "(__cont{})", cont_idx)?;
+
+    *cont_idx += 1;
+
+    Ok(())
+}
+
+// THAT = D; D = *THAT
+fn load_that(buf: &mut AsmWriter) -> std::io::Result<()> {
+    writeln!(buf, "@THAT")?;
+    writeln!(buf, "M=D")?;
+    writeln!(buf, "A=D")?;
+    writeln!(buf, "D=M")?;
+
+    Ok(())
+}
+
+///
+/// Emit `instr` keeping the top of stack in D. Returns false,
+/// without writing anything, if `instr` has no cached form.
+/// Comparisons are left to the shared routines when
+/// `shared_comps` is set.
+///
+pub fn emit(
+    buf: &mut AsmWriter,
+    instr: &Instr,
+    filename: &str,
+    curr_fun: &str,
+    cont_idx: &mut i32,
+    cached: &mut bool,
+    shared_comps: bool,
+) -> std::io::Result<bool> {
+    match instr {
+        Instr::Op(op @ Op::Push(seg, idx)) => {
+            writeln!(buf, "// {}", op)?;
+            flush(buf, cached)?;
+            load(buf, *seg, *idx, filename)?;
+            *cached = true;
+        }
+        Instr::PushValue(value) => {
+            writeln!(buf, "// push {}", value)?;
+            flush(buf, cached)?;
+            load_value(buf, *value)?;
+            *cached = true;
+        }
+        Instr::Op(op @ Op::Pop(seg, idx)) => {
+            writeln!(buf, "// {}", op)?;
+            fill(buf, cached)?;
+            store(buf, *seg, *idx, filename)?;
+            *cached = false;
+        }
+        Instr::Op(op @ (Op::Add | Op::Sub | Op::And | Op::Or)) => {
+            writeln!(buf, "// {}", op)?;
+            let comp = match op {
+                Op::Add => "D+M",
+                Op::Sub => "M-D",
+                Op::And => "D&M",
+                _ => "D|M",
+            };
+            binary(buf, comp, cached)?;
+        }
+        Instr::Op(op @ (Op::Neg | Op::Not)) => {
+            writeln!(buf, "// {}", op)?;
+            fill(buf, cached)?;
+            writeln!(buf, "D={}D", if *op == Op::Neg { "-" } else { "!" })?;
+        }
+        Instr::Op(op @ (Op::Eq | Op::Gt | Op::Lt)) if !shared_comps => {
+            writeln!(buf, "// {}", op)?;
+            let condition = match op {
+                Op::Eq => "JEQ",
+                Op::Gt => "JGT",
+                _ => "JLT",
+            };
+            comp(buf, condition, cont_idx, cached)?;
+        }
+        Instr::AddTop(value) => {
+            writeln!(buf, "// push {}; add", value)?;
+            fill(buf, cached)?;
+            match value {
+                1 => writeln!(buf, "D=D+1")?,
+                -1 => writeln!(buf, "D=D-1")?,
+                v if *v >= 0 => {
+                    writeln!(buf, "@{}", v)?;
+                    writeln!(buf, "D=D+A")?;
+                }
+                // Fused additions wrap around to it, it has no
+                // positive counterpart
+                &i16::MIN => {
+                    writeln!(buf, "@{}", i16::MAX)?;
+                    writeln!(buf, "A=!A")?;
+                    writeln!(buf, "D=D+A")?;
+                }
+                v => {
+                    writeln!(buf, "@{}", -v)?;
+                    writeln!(buf, "D=D-A")?;
+                }
+            }
+        }
+        Instr::Op(Op::IfGoto(label)) | Instr::IfNotGoto(label) => {
+            let label = generate_label(curr_fun, label);
+            let negate = matches!(instr, Instr::IfNotGoto(_));
+            if negate {
+                writeln!(buf, "// not; if-goto {}", label)?;
+            } else {
+                writeln!(buf, "// if-goto {}", label)?;
+            }
+            fill(buf, cached)?;
+            // `not d` is zero only for `true`, -1
+            if negate {
+                writeln!(buf, "D=D+1")?;
+            }
+            writeln!(buf, "@{}", label)?;
+            writeln!(buf, "D;JNE")?;
+            *cached = false;
+        }
+        Instr::LoadThat => {
+            writeln!(buf, "// pop pointer 1; push that 0")?;
+            fill(buf, cached)?;
+            load_that(buf)?;
+        }
+        Instr::PushIndirect(seg, idx) => {
+            writeln!(buf, "// push {} {}; pop pointer 1; push that 0", seg, idx)?;
+            flush(buf, cached)?;
+            load(buf, *seg, *idx, filename)?;
+            load_that(buf)?;
+            *cached = true;
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
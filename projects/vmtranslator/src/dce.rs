@@ -0,0 +1,78 @@
+use std::collections::{HashMap, HashSet};
+
+use hack_vm::{Module, Op};
+
+// Name of the function each command belongs to,
+// None for commands before the first `function`.
+fn owners(module: &Module) -> Vec<Option<&str>> {
+    let mut curr = None;
+    module
+        .commands
+        .iter()
+        .map(|c| {
+            if let Op::Function(name, _) = &c.op {
+                curr = Some(name.as_str());
+            }
+            curr
+        })
+        .collect()
+}
+
+///
+/// Remove every function not reachable through `call`s
+/// from `entry`. Nothing is removed if `entry` is not
+/// defined. Returns the names of the removed functions
+/// in program order.
+///
+pub fn eliminate_dead_functions(modules: &mut [Module], entry: &str) -> Vec<String> {
+    let mut calls: HashMap<&str, Vec<&str>> = HashMap::new();
+    for module in modules.iter() {
+        for (c, owner) in module.commands.iter().zip(owners(module)) {
+            match (&c.op, owner) {
+                (Op::Function(name, _), _) => {
+                    calls.entry(name).or_default();
+                }
+                (Op::Call(callee, _), Some(owner)) => {
+                    calls.entry(owner).or_default().push(callee);
+                }
+                // Division by zero reports the error through Sys.error
+                (Op::Div | Op::Mod, Some(owner)) => {
+                    calls.entry(owner).or_default().push("Sys.error");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if !calls.contains_key(entry) {
+        return Vec::new();
+    }
+
+    let mut reachable = HashSet::new();
+    let mut stack = vec![entry];
+    while let Some(name) = stack.pop() {
+        if reachable.insert(name) {
+            stack.extend(calls.get(name).into_iter().flatten());
+        }
+    }
+
+    let reachable: HashSet<String> = reachable.into_iter().map(String::from).collect();
+    let mut removed = Vec::new();
+    for module in modules.iter_mut() {
+        let keep: Vec<bool> = owners(module)
+            .into_iter()
+            .map(|owner| owner.is_none_or(|f| reachable.contains(f)))
+            .collect();
+
+        for (c, keep) in module.commands.iter().zip(&keep) {
+            if let (Op::Function(name, _), false) = (&c.op, keep) {
+                removed.push(name.clone());
+            }
+        }
+
+        let mut keep = keep.into_iter();
+        module.commands.retain(|_| keep.next().unwrap());
+    }
+
+    removed
+}
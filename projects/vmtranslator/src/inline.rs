@@ -0,0 +1,287 @@
+use std::collections::{HashMap, HashSet};
+
+use hack_vm::{Command, Index, Module, Op, Segment};
+
+use crate::check::stack_effect;
+
+// A function whose calls get replaced by its body
+#[derive(Debug, Clone)]
+struct Callee {
+    module: String,
+    nlocals: i32,
+    /// Commands after `function` up to the final `return`
+    body: Vec<Op>,
+    /// `static` refers to the callee's file, so the body can
+    /// only be inlined into the same file
+    uses_static: bool,
+    /// Pointers set by the body, restored after it like
+    /// `return` would
+    pointers: Vec<Index>,
+}
+
+// Name, locals and commands after `function` of every function
+fn functions(module: &Module) -> Vec<(&str, i32, &[Command])> {
+    let starts: Vec<usize> = module
+        .commands
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| matches!(c.op, Op::Function(_, _)))
+        .map(|(i, _)| i)
+        .collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| {
+            let end = starts.get(n + 1).copied().unwrap_or(module.commands.len());
+            let Op::Function(name, nlocals) = &module.commands[start].op else { unreachable!() };
+            (name.as_str(), *nlocals, &module.commands[start + 1..end])
+        })
+        .collect()
+}
+
+// Whether every path through `body` ends in a `return` with
+// exactly the returned value on the stack, so that the body
+// leaves just that value behind once inlined
+fn returns_one_value(body: &[Command]) -> bool {
+    let labels: HashMap<&str, usize> = body
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| match &c.op {
+            Op::Label(l) => Some((l.as_str(), i)),
+            _ => None,
+        })
+        .collect();
+
+    let mut depth_at: Vec<Option<i32>> = vec![None; body.len()];
+    let mut pending = vec![(0, 0)];
+    while let Some((mut pc, mut depth)) = pending.pop() {
+        loop {
+            let Some(c) = body.get(pc) else { return false };
+            match depth_at[pc] {
+                Some(known) if known == depth => break,
+                Some(_) => return false,
+                None => depth_at[pc] = Some(depth),
+            }
+
+            let (taken, left) = stack_effect(&c.op);
+            if depth < taken || (c.op == Op::Return && depth != 1) {
+                return false;
+            }
+            depth = depth - taken + left;
+
+            match &c.op {
+                Op::Goto(l) | Op::IfGoto(l) => match labels.get(l.as_str()) {
+                    Some(&target) => pending.push((target, depth)),
+                    None => return false,
+                },
+                _ => {}
+            }
+
+            if matches!(c.op, Op::Goto(_) | Op::Return) {
+                break;
+            }
+            pc += 1;
+        }
+    }
+
+    true
+}
+
+fn reaches(calls: &HashMap<&str, Vec<&str>>, from: &str, to: &str) -> bool {
+    let mut seen = HashSet::new();
+    let mut stack = calls.get(from).cloned().unwrap_or_default();
+    while let Some(name) = stack.pop() {
+        if name == to {
+            return true;
+        }
+        if seen.insert(name) {
+            stack.extend(calls.get(name).into_iter().flatten());
+        }
+    }
+
+    false
+}
+
+// Functions of at most `limit` commands that can be inlined
+fn callees(modules: &[Module], limit: usize) -> HashMap<String, Callee> {
+    let mut calls: HashMap<&str, Vec<&str>> = HashMap::new();
+    for module in modules {
+        for (name, _, body) in functions(module) {
+            let callees = body.iter().filter_map(|c| match &c.op {
+                Op::Call(callee, _) => Some(callee.as_str()),
+                _ => None,
+            });
+            calls.entry(name).or_default().extend(callees);
+        }
+    }
+
+    let mut res = HashMap::new();
+    for module in modules {
+        for (name, nlocals, body) in functions(module) {
+            let inlinable = body.len() <= limit
+                && body.last().is_some_and(|c| c.op == Op::Return)
+                && returns_one_value(body)
+                && !reaches(&calls, name, name);
+            if !inlinable {
+                continue;
+            }
+
+            let mut pointers: Vec<Index> = body
+                .iter()
+                .filter_map(|c| match c.op {
+                    Op::Pop(Segment::Pointer, p) => Some(p),
+                    _ => None,
+                })
+                .collect();
+            pointers.sort();
+            pointers.dedup();
+
+            res.insert(
+                name.to_string(),
+                Callee {
+                    module: module.name.clone(),
+                    nlocals,
+                    body: body.iter().map(|c| c.op.clone()).collect(),
+                    uses_static: body.iter().any(|c| matches!(c.op, Op::Push(Segment::Static, _) | Op::Pop(Segment::Static, _))),
+                    pointers,
+                },
+            );
+        }
+    }
+
+    res
+}
+
+// The body of `callee` in place of `call callee nargs`. Its arguments,
+// locals and the saved pointers live in the caller's locals from `base`,
+// its labels are made unique with `id`. Returns the commands and the
+// number of locals used.
+fn expand(callee: &Callee, nargs: i32, base: i32, id: usize, line: usize) -> (Vec<Command>, i32) {
+    let local = base + nargs;
+    let saved = local + callee.nlocals;
+    let end = format!("inline{}.return", id);
+    let rename = |l: &str| format!("inline{}${}", id, l);
+
+    let mut ops = Vec::new();
+    for (k, p) in (0..).zip(&callee.pointers) {
+        ops.push(Op::Push(Segment::Pointer, *p));
+        ops.push(Op::Pop(Segment::Local, saved + k));
+    }
+    for i in (0..nargs).rev() {
+        ops.push(Op::Pop(Segment::Local, base + i));
+    }
+    for j in 0..callee.nlocals {
+        ops.push(Op::Push(Segment::Constant, 0));
+        ops.push(Op::Pop(Segment::Local, local + j));
+    }
+
+    let last = callee.body.len() - 1;
+    let mut jumps_to_end = false;
+    for (i, op) in callee.body.iter().enumerate() {
+        let op = match op {
+            Op::Push(Segment::Argument, idx) => Op::Push(Segment::Local, base + idx),
+            Op::Pop(Segment::Argument, idx) => Op::Pop(Segment::Local, base + idx),
+            Op::Push(Segment::Local, idx) => Op::Push(Segment::Local, local + idx),
+            Op::Pop(Segment::Local, idx) => Op::Pop(Segment::Local, local + idx),
+            Op::Label(l) => Op::Label(rename(l)),
+            Op::Goto(l) => Op::Goto(rename(l)),
+            Op::IfGoto(l) => Op::IfGoto(rename(l)),
+            Op::Return if i == last => continue,
+            Op::Return => {
+                jumps_to_end = true;
+                Op::Goto(end.clone())
+            }
+            op => op.clone(),
+        };
+        ops.push(op);
+    }
+    if jumps_to_end {
+        ops.push(Op::Label(end));
+    }
+
+    for (k, p) in (0..).zip(&callee.pointers) {
+        ops.push(Op::Push(Segment::Local, saved + k));
+        ops.push(Op::Pop(Segment::Pointer, *p));
+    }
+
+    let commands = ops.into_iter().map(|op| Command { op, line }).collect();
+    (commands, nargs + callee.nlocals + callee.pointers.len() as i32)
+}
+
+// Inline the calls of `callees` once. The callers get the locals for
+// the inlined bodies added after their own.
+fn inline_pass(modules: &mut [Module], callees: &HashMap<String, Callee>, next_id: &mut usize) -> usize {
+    let mut count = 0;
+    for module in modules.iter_mut() {
+        let mut res: Vec<Command> = Vec::with_capacity(module.commands.len());
+        // Position of the caller's `function` in `res`, its locals
+        // and the locals needed by the inlined bodies
+        let mut caller: Option<(usize, i32, i32)> = None;
+
+        for c in module.commands.drain(..) {
+            match (&c.op, caller) {
+                (Op::Function(_, nlocals), _) => {
+                    grow_locals(&mut res, caller);
+                    caller = Some((res.len(), *nlocals, 0));
+                }
+                (Op::Call(name, nargs), Some((pos, base, extra))) => {
+                    if let Some(callee) = callees.get(name).filter(|f| !f.uses_static || f.module == module.name) {
+                        let (body, used) = expand(callee, *nargs, base, *next_id, c.line);
+                        res.extend(body);
+                        caller = Some((pos, base, extra.max(used)));
+                        *next_id += 1;
+                        count += 1;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            res.push(c);
+        }
+        grow_locals(&mut res, caller);
+
+        module.commands = res;
+    }
+
+    count
+}
+
+fn grow_locals(code: &mut [Command], caller: Option<(usize, i32, i32)>) {
+    if let Some((pos, base, extra)) = caller {
+        if let Op::Function(_, nlocals) = &mut code[pos].op {
+            *nlocals = base + extra;
+        }
+    }
+}
+
+///
+/// Replace the calls of functions with at most `limit` commands
+/// by their bodies. Recursive functions are never inlined, nor are
+/// functions using `static` into other files, and the value returned
+/// must be the only one left on the stack.
+///
+/// Arguments and locals of an inlined body become locals of the
+/// caller, and pointers it sets are restored afterwards. Bodies are
+/// inlined into each other until no such call is left. Returns the
+/// number of calls replaced, the functions themselves are kept for
+/// dead function elimination to remove.
+///
+pub fn inline_functions(modules: &mut [Module], limit: usize) -> usize {
+    let callees = callees(modules, limit);
+    if callees.is_empty() {
+        return 0;
+    }
+
+    let mut next_id = 0;
+    let mut total = 0;
+    loop {
+        let count = inline_pass(modules, &callees, &mut next_id);
+        if count == 0 {
+            break;
+        }
+        total += count;
+    }
+
+    total
+}
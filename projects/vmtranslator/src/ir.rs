@@ -0,0 +1,63 @@
+use hack_vm::{Index, Module, Op, Segment};
+
+///
+/// Instructions handed to the code generator: plain VM ops
+/// and the fused forms produced by the optimizer.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+    Op(Op),
+
+    /// push of a folded constant, may be outside 0..32767
+    PushValue(i16),
+    /// push s i; pop t j
+    Move(Segment, Index, Segment, Index),
+    /// push constant v; pop t j
+    Set(Segment, Index, i16),
+    /// push constant c; add
+    AddTop(i16),
+    /// push s i; push constant c; add; pop s i
+    AddInPlace(Segment, Index, i16),
+    /// not; if-goto label
+    IfNotGoto(String),
+    /// pop pointer 1; push that 0
+    LoadThat,
+    /// push s i; pop pointer 1; push that 0
+    PushIndirect(Segment, Index),
+    /// call f n; return, reusing the current frame
+    TailCall(String, Index),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub instr: Instr,
+    pub line: usize,
+}
+
+///
+/// Code of a single `.vm` file. `name` scopes
+/// the static segment like `Module::name`.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Unit {
+    pub name: String,
+    pub code: Vec<Item>,
+}
+
+impl From<&Module> for Unit {
+    fn from(module: &Module) -> Self {
+        let code = module
+            .commands
+            .iter()
+            .map(|c| Item {
+                instr: Instr::Op(c.op.clone()),
+                line: c.line,
+            })
+            .collect();
+
+        Unit {
+            name: module.name.clone(),
+            code,
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use hack_vm::{Module, Op};
+
+///
+/// A call left without a matching `function` after linking.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unresolved {
+    pub file: String,
+    pub line: usize,
+    pub callee: String,
+}
+
+impl fmt::Display for Unresolved {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "undefined entry function {}", self.callee)
+        } else {
+            write!(f, "{}.vm:{}: call to undefined function {}", self.file, self.line, self.callee)
+        }
+    }
+}
+
+fn defined(modules: &[Module]) -> HashSet<String> {
+    modules
+        .iter()
+        .flat_map(Module::ops)
+        .filter_map(|op| match op {
+            Op::Function(name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Calls without a definition, `roots` are treated as
+// called from outside the program.
+fn unresolved(modules: &[Module], roots: &[&str]) -> Vec<Unresolved> {
+    let defined = defined(modules);
+    let roots = roots.iter().map(|name| Unresolved {
+        file: String::new(),
+        line: 0,
+        callee: name.to_string(),
+    });
+
+    modules
+        .iter()
+        .flat_map(|m| {
+            m.commands.iter().filter_map(move |c| match &c.op {
+                Op::Call(callee, _) => Some(Unresolved {
+                    file: m.name.clone(),
+                    line: c.line,
+                    callee: callee.clone(),
+                }),
+                _ => None,
+            })
+        })
+        .chain(roots)
+        .filter(|u| !defined.contains(&u.callee))
+        .collect()
+}
+
+fn find_class(class: &str, search_path: &[PathBuf]) -> Option<PathBuf> {
+    search_path
+        .iter()
+        .map(|dir| dir.join(format!("{}.vm", class)))
+        .find(|path| path.is_file())
+}
+
+///
+/// Add the classes providing unresolved calls from the
+/// directories in `search_path`, the first directory
+/// containing `Class.vm` wins. Classes already in `modules`
+/// are never replaced, so user code overrides the library.
+///
+/// Returns the calls that are still unresolved.
+///
+pub fn link(
+    modules: &mut Vec<Module>,
+    search_path: &[PathBuf],
+    roots: &[&str],
+) -> Result<Vec<Unresolved>, Box<dyn Error>> {
+    loop {
+        let classes: HashSet<String> = modules.iter().map(|m| m.name.clone()).collect();
+        let mut missing: Vec<String> = unresolved(modules, roots)
+            .into_iter()
+            .filter_map(|u| u.callee.split_once('.').map(|(class, _)| class.to_string()))
+            .filter(|class| !classes.contains(class))
+            .collect();
+        missing.sort();
+        missing.dedup();
+
+        let mut linked = false;
+        for class in missing {
+            if let Some(path) = find_class(&class, search_path) {
+                println!("Linking: {}...", path.display());
+                let source = fs::read_to_string(&path)?;
+                modules.push(hack_vm::parse(&class, &source)?);
+                linked = true;
+            }
+        }
+
+        if !linked {
+            break;
+        }
+    }
+
+    Ok(unresolved(modules, roots))
+}
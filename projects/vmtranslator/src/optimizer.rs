@@ -0,0 +1,149 @@
+use hack_vm::{Op, Segment};
+
+use crate::ir::{Instr, Item, Unit};
+
+///
+/// Peephole pass fusing common VM sequences into the
+/// specialized `Instr` forms. Level 0 leaves the code as is.
+///
+/// Fusing only looks at the tail of the already processed
+/// code, so sequences never span a label, call or function.
+///
+pub fn optimize(unit: &mut Unit, level: u8) {
+    if level == 0 {
+        return;
+    }
+
+    let mut res: Vec<Item> = Vec::with_capacity(unit.code.len());
+    for item in unit.code.drain(..) {
+        res.push(item);
+        while reduce(&mut res) {}
+    }
+
+    unit.code = res;
+}
+
+fn constant(instr: &Instr) -> Option<i16> {
+    match instr {
+        Instr::Op(Op::Push(Segment::Constant, c)) => Some(*c as i16),
+        Instr::PushValue(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn fold(op: &Op, x: i16, y: i16) -> Option<i16> {
+    let res = match op {
+        Op::Add => x.wrapping_add(y),
+        Op::Sub => x.wrapping_sub(y),
+        Op::And => x & y,
+        Op::Or => x | y,
+        Op::Eq => -((x == y) as i16),
+        Op::Gt => -((x > y) as i16),
+        Op::Lt => -((x < y) as i16),
+        Op::Mul => x.wrapping_mul(y),
+        // Division by zero is left to the runtime error
+        Op::Div if y != 0 => x.wrapping_div(y),
+        Op::Mod if y != 0 => x.wrapping_rem(y),
+        Op::Shl if (0..16).contains(&y) => ((x as u16) << y) as i16,
+        Op::Shr if (0..16).contains(&y) => ((x as u16) >> y) as i16,
+        Op::Shl | Op::Shr => 0,
+        _ => return None,
+    };
+
+    Some(res)
+}
+
+fn reduce3(a: &Instr, b: &Instr, c: &Instr) -> Option<Instr> {
+    if let (Some(x), Some(y), Instr::Op(op)) = (constant(a), constant(b), c) {
+        return fold(op, x, y).map(Instr::PushValue);
+    }
+
+    match (a, b, c) {
+        (Instr::Op(Op::Push(s, i)), Instr::AddTop(v), Instr::Op(Op::Pop(t, j)))
+            if s == t && i == j =>
+        {
+            Some(Instr::AddInPlace(*s, *i, *v))
+        }
+        _ => None,
+    }
+}
+
+fn reduce2(a: &Instr, b: &Instr) -> Option<Instr> {
+    if let Some(x) = constant(a) {
+        return match b {
+            Instr::Op(Op::Neg) => Some(Instr::PushValue(x.wrapping_neg())),
+            Instr::Op(Op::Not) => Some(Instr::PushValue(!x)),
+            Instr::Op(Op::Add) => Some(Instr::AddTop(x)),
+            Instr::Op(Op::Sub) => Some(Instr::AddTop(x.wrapping_neg())),
+            Instr::Op(Op::Pop(t, j)) => Some(Instr::Set(*t, *j, x)),
+            _ => None,
+        };
+    }
+
+    match (a, b) {
+        (Instr::Op(Op::Push(s, i)), Instr::Op(Op::Pop(t, j))) => {
+            Some(Instr::Move(*s, *i, *t, *j))
+        }
+        (Instr::Op(Op::Not), Instr::Op(Op::IfGoto(l))) => Some(Instr::IfNotGoto(l.clone())),
+        (Instr::Op(Op::Pop(Segment::Pointer, 1)), Instr::Op(Op::Push(Segment::That, 0))) => {
+            Some(Instr::LoadThat)
+        }
+        (Instr::Move(s, i, Segment::Pointer, 1), Instr::Op(Op::Push(Segment::That, 0))) => {
+            Some(Instr::PushIndirect(*s, *i))
+        }
+        (Instr::AddTop(x), Instr::AddTop(y)) => Some(Instr::AddTop(x.wrapping_add(*y))),
+        _ => None,
+    }
+}
+
+///
+/// Fuse the instructions at the end of `code`.
+/// Returns whether anything changed.
+///
+fn reduce(code: &mut Vec<Item>) -> bool {
+    let n = code.len();
+
+    if n >= 3 {
+        if let Some(instr) = reduce3(&code[n - 3].instr, &code[n - 2].instr, &code[n - 1].instr) {
+            replace_tail(code, 3, instr);
+            return true;
+        }
+    }
+
+    if n >= 2 {
+        if let Some(instr) = reduce2(&code[n - 2].instr, &code[n - 1].instr) {
+            replace_tail(code, 2, instr);
+            return true;
+        }
+    }
+
+    false
+}
+
+fn replace_tail(code: &mut Vec<Item>, len: usize, instr: Instr) {
+    let line = code[code.len() - len].line;
+    code.truncate(code.len() - len);
+    code.push(Item { instr, line });
+}
+
+///
+/// Replace every `call f n` directly followed by `return` with a
+/// tail call, which reuses the frame of the calling function.
+/// Returns the number of calls replaced.
+///
+pub fn eliminate_tail_calls(unit: &mut Unit) -> usize {
+    let mut res: Vec<Item> = Vec::with_capacity(unit.code.len());
+    let mut count = 0;
+    for item in unit.code.drain(..) {
+        if let (Instr::Op(Op::Return), Some(Instr::Op(Op::Call(name, nargs)))) = (&item.instr, res.last().map(|i| &i.instr)) {
+            let instr = Instr::TailCall(name.clone(), *nargs);
+            replace_tail(&mut res, 1, instr);
+            count += 1;
+        } else {
+            res.push(item);
+        }
+    }
+
+    unit.code = res;
+    count
+}
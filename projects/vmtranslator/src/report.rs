@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use hack_vm::{Module, Op};
+
+use crate::asm::SourceLoc;
+use crate::codegen;
+use crate::Options;
+
+/// Output format of `Report::write`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+impl Format {
+    /// The format for a report file, by its extension.
+    pub fn from_path(path: &std::path::Path) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Format::Csv,
+            Some("json") => Format::Json,
+            _ => Format::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionReport {
+    pub name: String,
+    /// VM commands from `function` up to the next function.
+    pub commands: usize,
+    /// Hack instructions emitted for them.
+    pub instructions: i32,
+    /// `call` commands of the function in the whole program.
+    pub call_sites: usize,
+    /// Instructions executed by a call and its return, see
+    /// `codegen::call_cost`.
+    pub call_cost: i32,
+}
+
+///
+/// Code size of the functions of a translated program, largest
+/// first. Code no VM command was translated to, like the bootstrap
+/// code and the shared routines, only counts towards `total`.
+///
+#[derive(Debug, Default)]
+pub struct Report {
+    pub functions: Vec<FunctionReport>,
+    pub total: i32,
+}
+
+///
+/// Report on the functions of `modules`, translated with `opts`
+/// to `instr_cnt` instructions with `source_map`.
+///
+pub fn build(modules: &[Module], source_map: &[SourceLoc], instr_cnt: i32, opts: &Options) -> io::Result<Report> {
+    let mut instructions: HashMap<&str, i32> = HashMap::new();
+    for (i, loc) in source_map.iter().enumerate() {
+        let end = source_map.get(i + 1).map_or(instr_cnt, |next| next.address);
+        *instructions.entry(loc.function.as_str()).or_default() += end - loc.address;
+    }
+
+    let mut call_sites: HashMap<&str, usize> = HashMap::new();
+    for op in modules.iter().flat_map(|m| m.ops()) {
+        if let Op::Call(name, _) = op {
+            *call_sites.entry(name.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut functions: Vec<FunctionReport> = Vec::new();
+    for op in modules.iter().flat_map(|m| m.ops()) {
+        match op {
+            Op::Function(name, nlocals) => functions.push(FunctionReport {
+                name: name.clone(),
+                commands: 1,
+                instructions: instructions.get(name.as_str()).copied().unwrap_or(0),
+                call_sites: call_sites.get(name.as_str()).copied().unwrap_or(0),
+                call_cost: codegen::call_cost(*nlocals, opts)?,
+            }),
+            _ => {
+                if let Some(f) = functions.last_mut() {
+                    f.commands += 1;
+                }
+            }
+        }
+    }
+    functions.sort_by(|a, b| b.instructions.cmp(&a.instructions).then_with(|| a.name.cmp(&b.name)));
+
+    Ok(Report { functions, total: instr_cnt })
+}
+
+fn json_string(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+
+    res
+}
+
+impl Report {
+    pub fn write(&self, out: &mut impl Write, format: Format) -> io::Result<()> {
+        match format {
+            Format::Text => self.write_text(out),
+            Format::Csv => self.write_csv(out),
+            Format::Json => self.write_json(out),
+        }
+    }
+
+    fn write_text(&self, out: &mut impl Write) -> io::Result<()> {
+        let width = self.functions.iter().map(|f| f.name.len()).max().unwrap_or(0).max("function".len());
+        writeln!(out, "{:<width$} {:>8} {:>12} {:>6} {:>8}", "function", "commands", "instructions", "calls", "per call")?;
+        for f in &self.functions {
+            writeln!(out, "{:<width$} {:>8} {:>12} {:>6} {:>8}", f.name, f.commands, f.instructions, f.call_sites, f.call_cost)?;
+        }
+
+        let in_functions: i32 = self.functions.iter().map(|f| f.instructions).sum();
+        writeln!(out, "{} functions, {} instructions, {} in generated code", self.functions.len(), self.total, self.total - in_functions)
+    }
+
+    fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "function,commands,instructions,call_sites,call_cost")?;
+        for f in &self.functions {
+            writeln!(out, "{},{},{},{},{}", f.name, f.commands, f.instructions, f.call_sites, f.call_cost)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_json(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"total_instructions\": {},", self.total)?;
+        writeln!(out, "  \"functions\": [")?;
+        for (i, f) in self.functions.iter().enumerate() {
+            let sep = if i + 1 < self.functions.len() { "," } else { "" };
+            writeln!(
+                out,
+                "    {{\"function\": {}, \"commands\": {}, \"instructions\": {}, \"call_sites\": {}, \"call_cost\": {}}}{}",
+                json_string(&f.name),
+                f.commands,
+                f.instructions,
+                f.call_sites,
+                f.call_cost,
+                sep
+            )?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{self, Write};
+
+use hack_vm::{Index, Op, Segment};
+
+use crate::ir::{Instr, Unit};
+
+/// First address the assembler gives to variables.
+pub const STATIC_BASE: i32 = 16;
+/// Words between `STATIC_BASE` and the stack at 256.
+pub const STATIC_LIMIT: usize = 240;
+
+///
+/// The static variables of a program in the order the assembler
+/// allocates them, by first use, so `vars[i]` ends up at
+/// `STATIC_BASE + i`.
+///
+#[derive(Debug, Default)]
+pub struct StaticMap {
+    pub vars: Vec<(String, Index)>,
+}
+
+// Static variables accessed by `instr`, in the order of the emitted code
+fn statics(instr: &Instr) -> Vec<Index> {
+    let segments: Vec<(Segment, Index)> = match instr {
+        Instr::Op(Op::Push(s, i) | Op::Pop(s, i)) => vec![(*s, *i)],
+        Instr::Move(s, i, t, j) => vec![(*s, *i), (*t, *j)],
+        Instr::Set(s, i, _) | Instr::AddInPlace(s, i, _) | Instr::PushIndirect(s, i) => vec![(*s, *i)],
+        _ => Vec::new(),
+    };
+
+    segments.into_iter().filter(|(s, _)| *s == Segment::Static).map(|(_, i)| i).collect()
+}
+
+///
+/// Collect the static variables of `units`, which must be in the
+/// order they are translated.
+///
+pub fn allocate(units: &[Unit]) -> StaticMap {
+    let mut map = StaticMap::default();
+    for unit in units {
+        let mut seen = Vec::new();
+        for idx in unit.code.iter().flat_map(|item| statics(&item.instr)) {
+            if !seen.contains(&idx) {
+                seen.push(idx);
+                map.vars.push((unit.name.clone(), idx));
+            }
+        }
+    }
+
+    map
+}
+
+impl StaticMap {
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    pub fn fits(&self) -> bool {
+        self.len() <= STATIC_LIMIT
+    }
+
+    ///
+    /// Write one line per file with the addresses of its
+    /// variables and their number, e.g. `Main 16-18 (3)`. A file's
+    /// variables are contiguous unless they are first used in
+    /// between those of another file.
+    ///
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut files: Vec<(&str, Vec<i32>)> = Vec::new();
+        for (addr, (file, _)) in (STATIC_BASE..).zip(&self.vars) {
+            match files.iter_mut().find(|(f, _)| f == file) {
+                Some((_, addrs)) => addrs.push(addr),
+                None => files.push((file, vec![addr])),
+            }
+        }
+
+        for (file, addrs) in files {
+            let mut ranges: Vec<(i32, i32)> = Vec::new();
+            for addr in &addrs {
+                match ranges.last_mut() {
+                    Some((_, last)) if *last + 1 == *addr => *last = *addr,
+                    _ => ranges.push((*addr, *addr)),
+                }
+            }
+
+            let ranges: Vec<String> = ranges
+                .into_iter()
+                .map(|(first, last)| if first == last { first.to_string() } else { format!("{}-{}", first, last) })
+                .collect();
+            writeln!(out, "{} {} ({})", file, ranges.join(","), addrs.len())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+mod common;
+
+// Translate the files, which should fail, and return the errors
+fn errors(name: &str, files: &[(&str, &str)]) -> Vec<String> {
+    let common::Output { success, stdout, .. } = common::translate(&format!("check_{}", name), files, &[]);
+    assert!(!success, "{}", stdout);
+
+    stdout.lines().filter_map(|l| l.strip_prefix("Error: ")).map(String::from).collect()
+}
+
+const SYS: &str = "\
+function Sys.init 0
+push constant 1
+call Foo.k 1
+pop temp 0
+push constant 1
+if-goto L
+push constant 5
+label L
+pop temp 0
+label END
+goto END
+";
+
+const FOO: &str = "\
+function Foo.f 0
+return
+function Foo.g 0
+pop temp 0
+push constant 0
+return
+function Foo.h 0
+goto NOWHERE
+function Foo.k 1
+push local 1
+push argument 2
+return
+function Foo.m 0
+push constant 1
+push constant 2
+call Foo.k 2
+return
+";
+
+#[test]
+fn reports_every_check_in_order() {
+    let errors = errors("all", &[("Sys.vm", SYS), ("Foo.vm", FOO)]);
+
+    assert_eq!(
+        errors,
+        [
+            "Foo.vm:2: return with an empty stack",
+            "Foo.vm:4: stack underflow in pop temp 0",
+            "Foo.vm:8: undefined label NOWHERE in Foo.h",
+            "Foo.vm:10: local 1 out of range, Foo.k declares 1 locals",
+            "Foo.vm:11: argument 2 out of range, Foo.k is called with 2 arguments",
+            "Sys.vm:3: Foo.k called with 1 arguments, but with 2 at Foo.vm:16",
+            "Sys.vm:8: stack depth at label L is both 1 and 0",
+        ]
+    );
+}
+
+#[test]
+fn reports_undefined_calls() {
+    let foo = "function Foo.f 0\npush constant 0\nreturn\n";
+    let errors = errors("undefined", &[("Sys.vm", "function Sys.init 0\ncall Foo.f 0\ncall Foo.g 1\nlabel END\ngoto END\n"), ("Foo.vm", foo)]);
+
+    assert_eq!(errors, ["Sys.vm:3: call to undefined function Foo.g"]);
+}
+
+#[test]
+fn bootstrap_calls_the_entry_without_arguments() {
+    let sys = "function Sys.init 0\nlabel END\ngoto END\nfunction Sys.f 0\npush constant 0\ncall Sys.init 1\nreturn\n";
+    let errors = errors("entry", &[("Sys.vm", sys)]);
+
+    assert_eq!(errors, ["Sys.vm:6: Sys.init called with 1 arguments, but with 0 at the bootstrap code"]);
+}
@@ -0,0 +1,146 @@
+// Shared by the test binaries, each of which uses only a part
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Run until the program halts in an `(L) @L 0;JMP` loop or falls off the
+// end, return the number of instructions executed before that
+pub fn execute(rom: &[u16], ram: &mut [i16]) -> usize {
+    let (mut a, mut d, mut pc) = (0i16, 0i16, 0usize);
+    for cycles in 0..1_000_000 {
+        let Some(&instr) = rom.get(pc) else { return cycles };
+        if instr & 0x8000 == 0 {
+            a = instr as i16;
+            pc += 1;
+            continue;
+        }
+
+        let y = if instr & 0x1000 != 0 { ram[a as u16 as usize] } else { a };
+        let c = (instr >> 6) & 0x3f;
+        let mut x = d;
+        let mut y = y;
+        if c & 0x20 != 0 {
+            x = 0;
+        }
+        if c & 0x10 != 0 {
+            x = !x;
+        }
+        if c & 0x08 != 0 {
+            y = 0;
+        }
+        if c & 0x04 != 0 {
+            y = !y;
+        }
+        let mut out = if c & 0x02 != 0 { x.wrapping_add(y) } else { x & y };
+        if c & 0x01 != 0 {
+            out = !out;
+        }
+
+        let addr = a;
+        if instr & 0x08 != 0 {
+            ram[addr as u16 as usize] = out;
+        }
+        if instr & 0x10 != 0 {
+            d = out;
+        }
+        if instr & 0x20 != 0 {
+            a = out;
+        }
+
+        let j = instr & 0x07;
+        let jump = (j & 0x04 != 0 && out < 0) || (j & 0x02 != 0 && out == 0) || (j & 0x01 != 0 && out > 0);
+        if jump {
+            if addr as usize + 1 == pc && rom[addr as usize] == addr as u16 {
+                return cycles;
+            }
+            pc = addr as usize;
+        } else {
+            pc += 1;
+        }
+    }
+
+    panic!("program did not halt");
+}
+
+/// A translator run in a fresh directory.
+pub struct Output {
+    pub success: bool,
+    pub stdout: String,
+    /// The files it wrote, by name.
+    pub files: BTreeMap<String, String>,
+}
+
+// Write the `.vm` `files` to a fresh directory and translate it into
+// `out` with `flags`, relative paths in which are in the directory
+pub fn translate_to(name: &str, files: &[(&str, &str)], out: &str, flags: &[&str]) -> Output {
+    // Tests running in parallel may translate the same program
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let run = RUNS.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir().join(format!("vmtranslator_{}_{}_{}", name, std::process::id(), run));
+    fs::create_dir_all(&dir).unwrap();
+    for (file, src) in files {
+        fs::write(dir.join(file), src).unwrap();
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vmtranslator"))
+        .current_dir(&dir)
+        .arg(".")
+        .arg(out)
+        .args(flags)
+        .output()
+        .unwrap();
+
+    let mut written = BTreeMap::new();
+    for entry in fs::read_dir(&dir).unwrap() {
+        let file = entry.unwrap().file_name().into_string().unwrap();
+        if !files.iter().any(|(f, _)| *f == file) {
+            written.insert(file.clone(), fs::read_to_string(dir.join(&file)).unwrap());
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+
+    Output {
+        success: output.status.success(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        files: written,
+    }
+}
+
+// Translate the `.vm` `files` into `Out.asm`
+pub fn translate(name: &str, files: &[(&str, &str)], flags: &[&str]) -> Output {
+    translate_to(name, files, "Out.asm", flags)
+}
+
+/// A translated program after it ran.
+pub struct Run {
+    pub ram: Vec<i16>,
+    pub asm: String,
+    /// What the translator printed.
+    pub stdout: String,
+}
+
+// The default instrumentation region ends below the screen
+pub fn error_code(nfunctions: usize) -> usize {
+    16384 - 2 - nfunctions
+}
+
+// Translate the `.vm` `files` as one program with `flags`, run it
+// from zeroed RAM and return the result
+pub fn run_program(name: &str, files: &[(&str, &str)], flags: &[&str]) -> Run {
+    let Output { success, stdout, mut files } = translate(name, files, flags);
+    assert!(success, "{}", stdout);
+
+    let asm = files.remove("Out.asm").unwrap();
+    let mut ram = vec![0i16; 32768];
+    execute(&hack_assembler::assemble(&asm).unwrap().code, &mut ram);
+
+    Run { ram, asm, stdout }
+}
+
+// Translate `Sys.vm` with the bootstrap code, run it and return the RAM
+pub fn run(name: &str, sys: &str, flags: &[&str]) -> Vec<i16> {
+    run_program(name, &[("Sys.vm", sys)], flags).ram
+}
@@ -0,0 +1,64 @@
+mod common;
+
+const RESULTS: i16 = 4000;
+const BOUNDARY: [i16; 9] = [i16::MIN, -32767, -16384, -1, 0, 1, 16384, 32766, i16::MAX];
+
+fn push(value: i16) -> String {
+    match value {
+        i16::MIN => "push constant 32767\nneg\npush constant 1\nsub\n".to_string(),
+        v if v < 0 => format!("push constant {}\nneg\n", -v),
+        v => format!("push constant {}\n", v),
+    }
+}
+
+fn check(name: &str, flags: &[&str]) {
+    let mut vm = format!("push constant {}\npop pointer 1\n", RESULTS);
+    let mut expected = Vec::new();
+    for &x in &BOUNDARY {
+        for &y in &BOUNDARY {
+            for (op, f) in COMPARISONS {
+                vm += &push(x);
+                vm += &push(y);
+                vm += &format!("{}\npop that {}\n", op, expected.len());
+                expected.push((x, y, op, -(f(x, y) as i16)));
+            }
+        }
+    }
+
+    let flags = [&["--sp=256"][..], flags].concat();
+    let ram = common::run_program(name, &[("Comp.vm", &vm)], &flags).ram;
+
+    for (i, (x, y, op, res)) in expected.into_iter().enumerate() {
+        let got = ram[RESULTS as usize + i];
+        assert_eq!(got, res, "{} {} {} with {:?}", x, op, y, flags);
+    }
+}
+
+type Comparison = (&'static str, fn(i16, i16) -> bool);
+
+const COMPARISONS: [Comparison; 3] = [
+    ("gt", |x, y| x > y),
+    ("lt", |x, y| x < y),
+    ("eq", |x, y| x == y),
+];
+
+#[test]
+fn inline_comparisons() {
+    check("inline", &[]);
+}
+
+#[test]
+fn shared_comparisons() {
+    check("shared", &["--trampolines"]);
+}
+
+#[test]
+fn cached_comparisons() {
+    check("cached", &["--cache-tos"]);
+    check("cached_shared", &["--cache-tos", "--trampolines"]);
+}
+
+#[test]
+fn folded_comparisons() {
+    check("folded", &["-O1"]);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+const SYS: &str = "\
+function Sys.init 0
+call Main.main 0
+pop static 0
+label END
+goto END
+function Sys.error 0
+push argument 0
+pop static 1
+label HALT
+goto HALT
+function Sys.unused 0
+push constant 0
+return
+";
+
+// `Main.divide` reaches `Sys.error` only through the shared
+// division routine
+const MAIN: &str = "\
+function Main.main 0
+push constant 42
+push constant 6
+call Main.divide 2
+return
+function Main.divide 0
+push argument 0
+push argument 1
+div
+return
+function Main.dead 0
+call Main.deadToo 0
+return
+function Main.deadToo 0
+push constant 0
+return
+";
+
+#[test]
+fn removes_unreachable_functions() {
+    let common::Run { ram, asm, stdout } = common::run_program("dce", &[("Sys.vm", SYS), ("Main.vm", MAIN)], &[]);
+
+    assert!(stdout.contains("Removed 3 unreachable functions:"), "{}", stdout);
+    let mut removed: Vec<&str> = stdout.lines().filter_map(|l| l.strip_prefix("    ")).collect();
+    removed.sort();
+    assert_eq!(removed, ["Main.dead", "Main.deadToo", "Sys.unused"]);
+
+    for kept in ["Sys.init", "Sys.error", "Main.main", "Main.divide"] {
+        assert!(asm.contains(&format!("({})", kept)), "{} was removed", kept);
+    }
+    for dead in ["Main.dead", "Main.deadToo", "Sys.unused"] {
+        assert!(!asm.contains(&format!("({})", dead)), "{} was kept", dead);
+    }
+
+    assert_eq!(ram[16], 7);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+const SYS: &str = "\
+function Sys.init 0
+push constant 6
+push constant 7
+call Sys.mul 2
+pop static 0
+label END
+goto END
+function Sys.mul 1
+label LOOP
+push argument 1
+push constant 0
+eq
+if-goto DONE
+push local 0
+push argument 0
+add
+pop local 0
+push argument 1
+push constant 1
+sub
+pop argument 1
+goto LOOP
+label DONE
+push local 0
+return
+";
+
+#[test]
+fn writes_hack_directly() {
+    let common::Output { success, stdout, files } = common::translate_to("hack_direct", &[("Sys.vm", SYS)], "Sys.hack", &["--asm=Side.asm", "--trampolines"]);
+    assert!(success, "{}", stdout);
+
+    let rom: Vec<u16> = files["Sys.hack"].lines().map(|l| u16::from_str_radix(l, 2).unwrap()).collect();
+    assert_eq!(rom, hack_assembler::assemble(&files["Side.asm"]).unwrap().code);
+
+    let mut ram = vec![0i16; 32768];
+    common::execute(&rom, &mut ram);
+    assert_eq!(ram[16], 42);
+}
+
+#[test]
+fn reports_assembly_errors_against_vm_commands() {
+    let sys = SYS.replace("Sys.mul", "2mul");
+    let common::Output { success, stdout, files } = common::translate_to("hack_errors", &[("Sys.vm", &sys)], "Sys.hack", &[]);
+
+    assert!(!success);
+    assert!(stdout.contains("Error: Sys.vm:4 in Sys.init: invalid symbol: @2mul"), "{}", stdout);
+    assert!(stdout.contains("in 2mul: invalid label: (2mul)"), "{}", stdout);
+    assert!(!files.contains_key("Sys.hack"));
+}
+
+#[test]
+fn side_output_needs_hack_output() {
+    let common::Output { success, stdout, .. } = common::translate_to("hack_side", &[("Sys.vm", SYS)], "Sys.asm", &["--asm=Other.asm"]);
+
+    assert!(!success);
+    assert!(stdout.contains("--asm needs a .hack output file"));
+}
@@ -0,0 +1,110 @@
+mod common;
+
+const SYS: &str = "\
+function Sys.init 0
+push constant 5000
+pop pointer 0
+push constant 42
+pop this 0
+push constant 3000
+push constant 7
+call Obj.setX 2
+pop temp 0
+push constant 3000
+call Obj.getX 1
+pop temp 1
+push this 0
+pop temp 2
+push constant 5
+neg
+call Obj.abs 1
+pop temp 3
+push constant 10
+call Obj.sum 1
+pop temp 4
+call Obj.next 0
+pop temp 5
+label END
+goto END
+";
+
+const OBJ: &str = "\
+function Obj.getX 0
+push argument 0
+pop pointer 0
+push this 0
+return
+function Obj.setX 0
+push argument 0
+pop pointer 0
+push argument 1
+pop this 0
+push constant 0
+return
+function Obj.abs 1
+push argument 0
+pop local 0
+push local 0
+push constant 0
+lt
+if-goto NEG
+push local 0
+return
+label NEG
+push local 0
+neg
+return
+function Obj.sum 0
+push argument 0
+push constant 0
+eq
+if-goto BASE
+push argument 0
+push argument 0
+push constant 1
+sub
+call Obj.sum 1
+add
+return
+label BASE
+push constant 0
+return
+function Obj.next 0
+push static 0
+push constant 1
+add
+pop static 0
+push static 0
+return
+";
+
+// Translate Sys.vm and Obj.vm, run them and return the RAM and the output
+fn run(name: &str, flags: &[&str]) -> (Vec<i16>, String) {
+    let run = common::run_program(name, &[("Sys.vm", SYS), ("Obj.vm", OBJ)], flags);
+
+    (run.ram, run.stdout)
+}
+
+#[test]
+fn inlines_small_functions() {
+    for flags in [&["--inline=16"][..], &["--inline=16", "-O1", "--cache-tos"], &["--inline=16", "--trampolines", "--tco"]] {
+        let (ram, stdout) = run("small", flags);
+        assert_eq!(ram[3000], 7, "{:?}", flags);
+        assert_eq!(&ram[6..11], &[7, 42, 5, 55, 1], "{:?}", flags);
+
+        // Obj.sum is recursive and Obj.next uses its file's statics
+        assert!(stdout.contains("Inlined 3 calls"), "{}", stdout);
+        let removed = stdout.split_once("unreachable functions:").unwrap().1;
+        for name in ["Obj.getX", "Obj.setX", "Obj.abs"] {
+            assert!(removed.contains(name), "{}", stdout);
+        }
+        assert!(!removed.contains("Obj.sum") && !removed.contains("Obj.next"), "{}", stdout);
+    }
+}
+
+#[test]
+fn respects_the_size_limit() {
+    let (ram, stdout) = run("limit", &["--inline=4"]);
+    assert_eq!(&ram[6..11], &[7, 42, 5, 55, 1]);
+    assert!(stdout.contains("Inlined 1 calls"), "{}", stdout);
+}
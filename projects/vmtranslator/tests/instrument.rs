@@ -0,0 +1,88 @@
+mod common;
+
+
+const RECURSION: &str = "\
+function Sys.init 0
+call Sys.forever 0
+return
+function Sys.forever 1
+call Sys.forever 0
+return
+";
+
+const CORRUPT_LCL: &str = "\
+function Sys.init 0
+call Sys.corrupt 0
+return
+function Sys.corrupt 0
+push constant 0
+pop pointer 1
+push constant 2000
+pop that 1
+push constant 0
+return
+";
+
+const CALLS: &str = "\
+function Sys.init 0
+call Sys.f 0
+pop temp 0
+call Sys.f 0
+pop temp 0
+call Sys.g 0
+label END
+goto END
+function Sys.f 0
+call Sys.g 0
+return
+function Sys.g 0
+push constant 1
+return
+";
+
+#[test]
+fn detects_stack_overflow() {
+    for flags in [&["--instrument=overflow"][..], &["--instrument=all", "--trampolines", "--cache-tos"]] {
+        let ram = common::run("overflow", RECURSION, flags);
+        assert_eq!(ram[common::error_code(2)], 1, "{:?}", flags);
+        assert!(ram[0] > 2048);
+    }
+}
+
+#[test]
+fn detects_corrupted_frame() {
+    for flags in [&["--instrument=underflow"][..], &["--instrument=all", "-O1", "--trampolines"]] {
+        let ram = common::run("underflow", CORRUPT_LCL, flags);
+        assert_eq!(ram[common::error_code(2)], 2, "{:?}", flags);
+    }
+}
+
+#[test]
+fn counts_calls() {
+    for flags in [&["--instrument=calls,last-function"][..], &["--instrument=all", "--trampolines"]] {
+        let ram = common::run("calls", CALLS, flags);
+        let base = common::error_code(3);
+        assert_eq!(&ram[base + 2..base + 5], &[1, 2, 3], "{:?}", flags);
+        assert_eq!(ram[base + 1], 2, "{:?}", flags);
+        assert_eq!(ram[base], 0);
+        assert_eq!(ram[16384..], vec![0; 32768 - 16384]);
+    }
+}
+
+#[test]
+fn moves_the_region() {
+    let ram = common::run("base", CALLS, &["--instrument=all", "--instrument-base=3000"]);
+
+    assert_eq!(&ram[3000..3005], &[0, 2, 1, 2, 3]);
+    assert_eq!(ram[common::error_code(3)..], vec![0; 32768 - common::error_code(3)]);
+}
+
+#[test]
+fn rejects_a_region_outside_ram() {
+    for base in ["24572", "1000"] {
+        let flag = format!("--instrument-base={}", base);
+        let common::Output { success, stdout, .. } = common::translate("inst_outside", &[("Sys.vm", CALLS)], &["--instrument=calls", &flag]);
+        assert!(!success, "{}", stdout);
+        assert!(stdout.contains("outside RAM[2048..24576]"), "{}", stdout);
+    }
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use std::path::Path;
+
+// Uses the OS for memory access only, but has its own `Sys`
+const SYS: &str = "\
+function Sys.init 0
+call Memory.init 0
+pop temp 0
+call Main.main 0
+pop temp 0
+label END
+goto END
+function Sys.error 0
+push argument 0
+pop temp 1
+label HALT
+goto HALT
+";
+
+const MAIN: &str = "\
+function Main.main 0
+push constant 3000
+push constant 77
+call Memory.poke 2
+pop temp 0
+push constant 3000
+call Memory.peek 1
+pop temp 2
+push constant 0
+return
+";
+
+#[test]
+fn links_only_the_os_classes_needed() {
+    let os_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tools/OS");
+    let library = format!("-L{}", os_dir.display());
+    let common::Run { ram, asm, stdout } = common::run_program("linker", &[("Sys.vm", SYS), ("Main.vm", MAIN)], &[&library]);
+
+    // `Sys` of the program wins over the OS one, which would
+    // pull in every other class
+    let linked: Vec<&str> = stdout.lines().filter(|l| l.starts_with("Linking: ")).collect();
+    assert_eq!(linked.len(), 1, "{}", stdout);
+    assert!(linked[0].ends_with("Memory.vm..."), "{}", stdout);
+    assert!(asm.contains("(Memory.peek)"));
+    assert!(!asm.contains("(Sys.halt)"));
+    assert!(!asm.contains("(Math.multiply)"));
+
+    assert_eq!(ram[3000], 77);
+    assert_eq!(ram[7], 77);
+}
+
+#[test]
+fn reports_calls_the_library_cannot_resolve() {
+    let os_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tools/OS");
+    let library = format!("-L{}", os_dir.display());
+    let main = "function Main.main 0\ncall Game.run 0\nreturn\n";
+    let common::Output { success, stdout, .. } = common::translate("linker_missing", &[("Sys.vm", SYS), ("Main.vm", main)], &[&library]);
+
+    assert!(!success);
+    assert!(stdout.contains("Error: Main.vm:2: call to undefined function Game.run"), "{}", stdout);
+}
+
+#[test]
+fn bootstraps_only_an_entry_function_found() {
+    let main = "function Main.main 0\npush constant 0\nreturn\n";
+
+    // The OS defines `Sys.init`
+    let os_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tools/OS");
+    let library = format!("-L{}", os_dir.display());
+    let common::Output { success, stdout, files } = common::translate("linker_os_entry", &[("Main.vm", main)], &[&library]);
+    assert!(success, "{}", stdout);
+    assert!(files["Out.asm"].contains("(Sys.init)"));
+
+    // A library without it leaves the program as it is
+    let common::Output { success, stdout, files } = common::translate("linker_no_entry", &[("Main.vm", main)], &["-L."]);
+    assert!(success, "{}", stdout);
+    assert!(!files["Out.asm"].contains("Sys.init"));
+}
@@ -0,0 +1,84 @@
+mod common;
+
+const RESULTS: usize = 3000;
+
+const VALUES: [i16; 18] = [0, 1, -1, 2, -2, 3, 7, -7, 100, -100, 255, 1000, -1000, 12345, -12345, 32767, -32767, -32768];
+
+fn push(src: &mut String, value: i16) {
+    match value {
+        -32768 => src.push_str("push constant 32767\nneg\npush constant 1\nsub\n"),
+        v if v < 0 => src.push_str(&format!("push constant {}\nneg\n", -v)),
+        v => src.push_str(&format!("push constant {}\n", v)),
+    }
+}
+
+// Sys.init storing `x op y` for every pair at RAM[RESULTS..]
+fn program(op: &str, pairs: &[(i16, i16)]) -> String {
+    let mut src = String::from("function Sys.init 0\n");
+    for (i, (x, y)) in pairs.iter().enumerate() {
+        push(&mut src, *x);
+        push(&mut src, *y);
+        src.push_str(&format!("{}\npush constant {}\npop pointer 1\npop that 0\n", op, RESULTS + i));
+    }
+    src.push_str("label END\ngoto END\n");
+
+    src
+}
+
+fn check(op: &str, ys: &[i16], expected: fn(i16, i16) -> i16) {
+    let pairs: Vec<(i16, i16)> = VALUES.iter().flat_map(|x| ys.iter().map(move |y| (*x, *y))).collect();
+    let sys = program(op, &pairs);
+
+    for flags in [&[][..], &["--cache-tos"], &["--trampolines", "--cache-tos"], &["-O1"]] {
+        let ram = common::run(op, &sys, flags);
+        for (i, (x, y)) in pairs.iter().enumerate() {
+            assert_eq!(ram[RESULTS + i], expected(*x, *y), "{} {} {} {:?}", x, op, y, flags);
+        }
+    }
+}
+
+#[test]
+fn multiplies_like_math_multiply() {
+    check("mul", &VALUES, |x, y| x.wrapping_mul(y));
+}
+
+#[test]
+fn divides_like_math_divide() {
+    check("div", &VALUES[1..], |x, y| x.wrapping_div(y));
+    check("mod", &VALUES[1..], |x, y| x.wrapping_rem(y));
+}
+
+#[test]
+fn shifts_logically() {
+    let ys = [0, 1, 2, 7, 8, 15, 16, 17, 100, -1, -16];
+    check("shl", &ys, |x, y| if (0..16).contains(&y) { ((x as u16) << y) as i16 } else { 0 });
+    check("shr", &ys, |x, y| if (0..16).contains(&y) { ((x as u16) >> y) as i16 } else { 0 });
+}
+
+#[test]
+fn reports_division_by_zero() {
+    let sys = "\
+function Sys.init 0
+push constant 7
+push constant 0
+div
+pop temp 0
+label END
+goto END
+function Sys.error 0
+push argument 0
+pop static 0
+label HALT
+goto HALT
+";
+    for flags in [&[][..], &["--cache-tos"], &["-O1"]] {
+        let ram = common::run("by_zero", sys, flags);
+        assert_eq!(ram[16], 3, "{:?}", flags);
+        assert_eq!(ram[5], 0, "{:?}", flags);
+    }
+
+    // Without Sys.error the program just halts
+    let ram = common::run("by_zero_halt", &sys.replace("Sys.error", "Sys.other"), &[]);
+    assert_eq!(ram[16], 0);
+    assert_eq!(ram[5], 0);
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// What a test script of the book sets before running its program,
+// and the RAM cells it compares afterwards with their values
+struct Script {
+    init: Vec<(usize, i16)>,
+    expected: Vec<(usize, i16)>,
+}
+
+fn name(dir: &Path) -> &str {
+    dir.file_name().unwrap().to_str().unwrap()
+}
+
+// The test programs of projects 07 and 08
+fn programs() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let mut dirs = Vec::new();
+    for project in ["07", "08"] {
+        for group in fs::read_dir(root.join(project)).unwrap() {
+            let group = group.unwrap().path();
+            if !group.is_dir() {
+                continue;
+            }
+            for dir in fs::read_dir(&group).unwrap() {
+                let dir = dir.unwrap().path();
+                if dir.join(format!("{}.tst", name(&dir))).exists() {
+                    dirs.push(dir);
+                }
+            }
+        }
+    }
+    dirs.sort();
+
+    dirs
+}
+
+fn read_script(dir: &Path) -> Script {
+    let tst = fs::read_to_string(dir.join(format!("{}.tst", name(dir)))).unwrap();
+    let cmp = fs::read_to_string(dir.join(format!("{}.cmp", name(dir)))).unwrap();
+
+    let init = tst
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("set RAM["))
+        .map(|l| {
+            let (addr, value) = l.split_once(']').unwrap();
+            (addr.parse().unwrap(), value.split(',').next().unwrap().trim().parse().unwrap())
+        })
+        .collect();
+
+    // The .cmp headers are cut to the column width, the
+    // addresses come from the output list instead
+    let list = tst.split("output-list").nth(1).unwrap().split(';').next().unwrap();
+    let addrs = list.split("RAM[").skip(1).map(|s| s.split(']').next().unwrap().parse().unwrap());
+    let values = cmp
+        .lines()
+        .filter(|l| !l.contains("RAM"))
+        .flat_map(|l| l.split('|'))
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| v.trim().parse().unwrap());
+
+    Script {
+        init,
+        expected: addrs.zip(values).collect(),
+    }
+}
+
+// The `.vm` files in `dir` with their sources
+fn read_program(dir: &Path) -> Vec<(String, String)> {
+    let mut files = Vec::new();
+    for file in fs::read_dir(dir).unwrap() {
+        let file = file.unwrap().path();
+        if file.extension().is_some_and(|ext| ext == "vm") {
+            files.push((name(&file).to_string(), fs::read_to_string(&file).unwrap()));
+        }
+    }
+
+    files
+}
+
+// Translate the `files` with `flags` and run them from the RAM their
+// script sets, return the RAM and the number of cycles it ran
+fn run(name: &str, files: &[(String, String)], script: &Script, flags: &[&str]) -> (Vec<i16>, usize) {
+    let files: Vec<(&str, &str)> = files.iter().map(|(f, src)| (f.as_str(), src.as_str())).collect();
+    let common::Output { success, stdout, files } = common::translate(&format!("programs_{}", name), &files, flags);
+    assert!(success, "{}", stdout);
+
+    let rom = hack_assembler::assemble(&files["Out.asm"]).unwrap().code;
+    let mut ram = vec![0i16; 32768];
+    for &(addr, value) in &script.init {
+        ram[addr] = value;
+    }
+    let cycles = common::execute(&rom, &mut ram);
+
+    (ram, cycles)
+}
+
+fn check(flags: &[&str]) {
+    for dir in programs() {
+        let script = read_script(&dir);
+        let (ram, _) = run(name(&dir), &read_program(&dir), &script, flags);
+        for &(addr, value) in &script.expected {
+            assert_eq!(ram[addr], value, "RAM[{}] of {} with {:?}", addr, name(&dir), flags);
+        }
+    }
+}
+
+#[test]
+fn inline_routines() {
+    check(&[]);
+}
+
+#[test]
+fn shared_routines() {
+    check(&["--trampolines"]);
+}
+
+#[test]
+fn cached_top_of_stack() {
+    check(&["--cache-tos"]);
+    check(&["--cache-tos", "--trampolines"]);
+
+    // Same results as the plain translation, in fewer cycles
+    for dir in programs() {
+        let (script, files) = (read_script(&dir), read_program(&dir));
+        let (plain, plain_cycles) = run(name(&dir), &files, &script, &[]);
+        let (cached, cached_cycles) = run(name(&dir), &files, &script, &["--cache-tos"]);
+        for &(addr, _) in &script.expected {
+            assert_eq!(cached[addr], plain[addr], "RAM[{}] of {}", addr, name(&dir));
+        }
+        assert!(cached_cycles < plain_cycles, "{}: {} >= {}", name(&dir), cached_cycles, plain_cycles);
+    }
+}
+
+#[test]
+fn if_goto_jumps_on_any_nonzero() {
+    // RAM[16] counts the jumps taken, `not 2` is -3
+    let vm = "push constant 0\npop pointer 0\n\
+push constant 2\nif-goto A\npush constant 100\npop this 16\nlabel A\n\
+push constant 2\nnot\nif-goto B\npush constant 100\npop this 16\nlabel B\n\
+push constant 0\nnot\nnot\nif-goto C\npush this 16\npush constant 1\nadd\npop this 16\nlabel C\n";
+    let files = [("If.vm".to_string(), vm.to_string())];
+
+    for flags in [&["--sp=256"][..], &["--sp=256", "-O1"], &["--sp=256", "--cache-tos"], &["--sp=256", "-O1", "--cache-tos"]] {
+        let script = Script { init: Vec::new(), expected: Vec::new() };
+        let (ram, _) = run("if", &files, &script, flags);
+        assert_eq!(ram[16], 1, "{:?}", flags);
+    }
+}
+
+#[test]
+fn adds_constants_fused_to_the_smallest_value() {
+    // -O1 fuses the additions into one of -32768
+    let vm = "push local 0\npush constant 32767\nadd\npush constant 1\nadd\npop temp 0\n";
+    let files = [("Min.vm".to_string(), vm.to_string())];
+
+    for flags in [&["--sp=256", "--lcl=300", "-O1"][..], &["--sp=256", "--lcl=300", "-O1", "--cache-tos"]] {
+        let script = Script { init: vec![(300, 5)], expected: Vec::new() };
+        let (ram, _) = run("min", &files, &script, flags);
+        assert_eq!(ram[5], 5i16.wrapping_add(i16::MIN), "{:?}", flags);
+    }
+}
+
+#[test]
+fn reports_when_shared_routines_are_larger() {
+    let eq = "push constant 1\npush constant 2\neq\n";
+    let common::Output { stdout, .. } = common::translate("programs_size", &[("Eq.vm", eq)], &["--trampolines"]);
+
+    assert!(stdout.contains("Code size: 36 instructions with shared routines, 32 inline (12.5% larger)"), "{}", stdout);
+}
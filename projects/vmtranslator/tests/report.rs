@@ -0,0 +1,67 @@
+mod common;
+
+const SYS: &str = "\
+function Sys.init 0
+push constant 1
+call Sys.twice 1
+push constant 2
+call Sys.twice 1
+call Sys.pair 2
+pop temp 0
+label END
+goto END
+function Sys.twice 0
+push argument 0
+push argument 0
+add
+return
+function Sys.pair 2
+push argument 0
+push argument 1
+call Sys.twice 1
+add
+return
+";
+
+// Translate Sys.vm with `--report=<report_file>`, return the report
+fn report(name: &str, flags: &[&str]) -> String {
+    let report = format!("--report={}", name);
+    let flags = [&[report.as_str()][..], flags].concat();
+    let common::Output { success, stdout, mut files } = common::translate(&format!("report_{}", name), &[("Sys.vm", SYS)], &flags);
+    assert!(success, "{}", stdout);
+
+    files.remove(name).unwrap()
+}
+
+#[test]
+fn reports_functions_by_size() {
+    let csv = report("report.csv", &[]);
+    let rows: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+    assert_eq!(rows[0], ["function", "commands", "instructions", "call_sites", "call_cost"]);
+
+    let names: Vec<&str> = rows[1..].iter().map(|r| r[0]).collect();
+    assert_eq!(names, ["Sys.init", "Sys.pair", "Sys.twice"], "{}", csv);
+    let sizes: Vec<i32> = rows[1..].iter().map(|r| r[2].parse().unwrap()).collect();
+    assert!(sizes.windows(2).all(|w| w[0] >= w[1]), "{}", csv);
+
+    let twice = &rows[3];
+    assert_eq!((twice[1], twice[3]), ("5", "3"), "{}", csv);
+    let pair = &rows[2];
+    assert_eq!((pair[1], pair[3]), ("6", "1"), "{}", csv);
+
+    // Zeroing the two locals costs more per call
+    let cost = |row: &Vec<&str>| row[4].parse::<i32>().unwrap();
+    assert!(cost(pair) > cost(twice), "{}", csv);
+}
+
+#[test]
+fn formats_by_extension() {
+    let json = report("report.json", &["--trampolines"]);
+    assert!(json.trim_start().starts_with('{') && json.trim_end().ends_with('}'), "{}", json);
+    assert!(json.contains("\"total_instructions\": "), "{}", json);
+    assert!(json.contains("{\"function\": \"Sys.twice\", \"commands\": 5, "), "{}", json);
+
+    let text = report("report.txt", &[]);
+    assert!(text.starts_with("function "), "{}", text);
+    assert!(text.contains("3 functions, "), "{}", text);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::collections::HashMap;
+
+const SYS: &str = "\
+// Entry
+function Sys.init 0
+push constant 4
+call Main.double 1
+pop temp 0
+label END
+goto END
+";
+
+const MAIN: &str = "\
+function Main.double 1
+push argument 0
+pop local 0
+push local 0
+push local 0
+add
+return
+";
+
+// Translate both files, returns the annotated asm and the source map
+fn translate(name: &str, flags: &[&str]) -> (String, String) {
+    let flags = [&["--annotate", "--source-map=Prog.map"][..], flags].concat();
+    let common::Output { success, stdout, mut files } =
+        common::translate_to(&format!("map_{}", name), &[("Sys.vm", SYS), ("Main.vm", MAIN)], "Prog.asm", &flags);
+    assert!(success, "{}", stdout);
+
+    (files.remove("Prog.asm").unwrap(), files.remove("Prog.map").unwrap())
+}
+
+fn check(name: &str, flags: &[&str]) {
+    let (asm, map) = translate(name, flags);
+
+    let entries: Vec<(i32, &str, usize, &str)> = map
+        .lines()
+        .map(|l| {
+            let fields: Vec<&str> = l.split(' ').collect();
+            (fields[0].parse().unwrap(), fields[1], fields[2].parse().unwrap(), fields[3])
+        })
+        .collect();
+    assert_eq!(entries[0], (0, "-", 0, "-"), "{:?}", flags);
+    assert!(entries.windows(2).all(|w| w[0].0 < w[1].0), "{:?}", flags);
+
+    // Every annotation in the asm sits at the address the map gives
+    let by_loc: HashMap<(&str, usize), (i32, &str)> =
+        entries.iter().map(|&(addr, file, line, function)| ((file, line), (addr, function))).collect();
+    let mut address = 0;
+    let mut matched = 0;
+    for line in asm.lines().map(str::trim) {
+        if let Some((file, line)) = line.strip_prefix("// ").and_then(|c| c.split_once(".vm:")) {
+            let file = format!("{}.vm", file);
+            if let Some(&(addr, function)) = by_loc.get(&(file.as_str(), line.parse().unwrap())) {
+                assert_eq!(addr, address, "{}:{} with {:?}", file, line, flags);
+                assert_eq!(function, if file == "Sys.vm" { "Sys.init" } else { "Main.double" });
+                matched += 1;
+            }
+        } else if !(line.is_empty() || line.starts_with("//") || line.starts_with('(')) {
+            address += 1;
+        }
+    }
+    assert_eq!(matched, entries.iter().filter(|e| e.1 != "-").count(), "{:?}", flags);
+    assert!(entries.last().unwrap().0 < address);
+}
+
+#[test]
+fn source_map_matches_annotations() {
+    check("default", &[]);
+    check("trampolines", &["--trampolines"]);
+    check("cache_tos", &["--cache-tos"]);
+    check("optimized", &["-O1", "--trampolines"]);
+}
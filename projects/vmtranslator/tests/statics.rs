@@ -0,0 +1,59 @@
+mod common;
+
+// `pop static i` of the values 1..=n in the order of `indices`
+fn set_statics(function: &str, indices: &[i32]) -> String {
+    let mut src = format!("function {} 0\n", function);
+    for (value, idx) in (1..).zip(indices) {
+        src.push_str(&format!("push constant {}\npop static {}\n", value, idx));
+    }
+    src.push_str("push constant 0\nreturn\n");
+
+    src
+}
+
+#[test]
+fn maps_statics_to_addresses() {
+    let sys = "function Sys.init 0\ncall Sys.set 0\npop temp 0\ncall Foo.set 0\npop temp 0\nlabel END\ngoto END\n".to_string() + &set_statics("Sys.set", &[2, 0]);
+    let foo = set_statics("Foo.set", &[0, 1, 2, 3]);
+
+    let common::Output { success, stdout, files } = common::translate("statics_map", &[("Sys.vm", &sys), ("Foo.vm", &foo)], &["--static-map=Out.map"]);
+    assert!(success, "{}", stdout);
+    assert!(stdout.contains("Static variables: 6 of 240"), "{}", stdout);
+
+    let map = &files["Out.map"];
+    let program = hack_assembler::assemble(&files["Out.asm"]).unwrap();
+    let mut ram = vec![0i16; 32768];
+    common::execute(&program.code, &mut ram);
+
+    // Files are translated in name order, Sys.2 comes before Sys.0
+    assert_eq!(program.variables, ["Foo.0", "Foo.1", "Foo.2", "Foo.3", "Sys.2", "Sys.0"]);
+    assert_eq!(map, "Foo 16-19 (4)\nSys 20-21 (2)\n");
+    assert_eq!(&ram[16..20], &[1, 2, 3, 4]);
+    assert_eq!(&ram[20..22], &[1, 2]);
+}
+
+#[test]
+fn limits_the_number_of_statics_not_their_index() {
+    let sys = "function Sys.init 0\npush constant 7\npop static 300\npush static 300\npop static 1000\nlabel END\ngoto END\n";
+
+    let common::Output { success, stdout, files } = common::translate("statics_index", &[("Sys.vm", sys)], &[]);
+    assert!(success, "{}", stdout);
+    assert!(stdout.contains("Static variables: 2 of 240"), "{}", stdout);
+
+    let program = hack_assembler::assemble(&files["Out.asm"]).unwrap();
+    let mut ram = vec![0i16; 32768];
+    common::execute(&program.code, &mut ram);
+
+    assert_eq!(program.variables, ["Sys.300", "Sys.1000"]);
+    assert_eq!(&ram[16..18], &[7, 7]);
+}
+
+#[test]
+fn rejects_too_many_statics() {
+    let indices: Vec<i32> = (0..121).collect();
+    let (foo, bar) = (set_statics("Foo.set", &indices), set_statics("Bar.set", &indices));
+
+    let common::Output { success, stdout, .. } = common::translate("statics_overflow", &[("Foo.vm", &foo), ("Bar.vm", &bar)], &[]);
+    assert!(!success);
+    assert!(stdout.contains("242 static variables do not fit"), "{}", stdout);
+}
@@ -0,0 +1,103 @@
+mod common;
+
+const RESULT: usize = 5;
+
+// count(n, acc) = n == 0 ? acc : count(n - 1, acc + 1), 1000 levels deep
+const COUNT: &str = "\
+function Sys.init 0
+push constant 1000
+push constant 7
+call Sys.count 2
+pop temp 0
+label END
+goto END
+function Sys.count 1
+push argument 0
+push constant 0
+eq
+if-goto DONE
+push argument 1
+push constant 1
+add
+pop local 0
+push argument 0
+push constant 1
+sub
+push local 0
+call Sys.count 2
+return
+label DONE
+push argument 1
+return
+";
+
+// Mutual recursion through functions with different argument
+// and local counts, so the frame moves both up and down
+const EVEN_ODD: &str = "\
+function Sys.init 0
+push constant 1001
+call Sys.even 1
+pop temp 0
+label END
+goto END
+function Sys.even 0
+push argument 0
+push constant 0
+eq
+if-goto DONE
+push argument 0
+push constant 1
+sub
+push constant 5
+push constant 6
+call Sys.odd 3
+return
+label DONE
+push constant 0
+not
+return
+function Sys.odd 3
+push argument 0
+push constant 0
+eq
+if-goto DONE
+push argument 1
+push argument 2
+add
+pop local 2
+push argument 0
+push constant 1
+sub
+call Sys.even 1
+return
+label DONE
+push constant 0
+return
+";
+
+// Run `sys` with the stack overflow check
+fn run(name: &str, sys: &str, flags: &[&str]) -> Vec<i16> {
+    common::run(name, sys, &[&["--instrument=overflow"], flags].concat())
+}
+
+#[test]
+fn deep_recursion_overflows_without_tco() {
+    assert_eq!(run("count_plain", COUNT, &[])[common::error_code(2)], 1);
+    assert_eq!(run("even_odd_plain", EVEN_ODD, &[])[common::error_code(3)], 1);
+}
+
+#[test]
+fn tail_calls_run_in_constant_stack() {
+    let modes: [&[&str]; 4] = [&["--tco"], &["--tco", "--trampolines"], &["--tco", "--cache-tos"], &["--tco", "-O1"]];
+    for flags in modes {
+        let ram = run("count", COUNT, flags);
+        assert_eq!(ram[common::error_code(2)], 0, "{:?}", flags);
+        assert_eq!(ram[RESULT], 1007, "{:?}", flags);
+        assert_eq!(ram[0], 261, "{:?}", flags);
+
+        let ram = run("even_odd", EVEN_ODD, flags);
+        assert_eq!(ram[common::error_code(3)], 0, "{:?}", flags);
+        assert_eq!(ram[RESULT], 0, "{:?}", flags);
+        assert_eq!(ram[0], 261, "{:?}", flags);
+    }
+}